@@ -0,0 +1,47 @@
+// Measures emulation speed in emulated MHz (a DMG runs at 4.194304 MHz)
+// Usage : cargo run --release --example benchmark [cartridge rom]
+// Without a cartridge the boot ROM runs on an empty cartridge slot
+
+use std::env;
+use std::fs;
+use std::process;
+use std::time::Instant;
+
+use gumboi::GumBoi;
+
+const STEPS: usize = 20_000_000;
+const DMG_MHZ: f64 = 4.194304;
+
+fn main() {
+    let mut gumboi = GumBoi::new();
+    if let Some(file_name) = env::args().nth(1) {
+        let cartridge_rom = match fs::read(&file_name) {
+            Ok(cartridge_rom) => cartridge_rom,
+            Err(error) => {
+                eprintln!("Benchmark : {} : {}", file_name, error);
+                process::exit(1);
+            }
+        };
+        if let Err(error) = gumboi.insert_cartridge(cartridge_rom) {
+            eprintln!("Benchmark : {}", error);
+            process::exit(1);
+        }
+    }
+
+    let start = Instant::now();
+    for _ in 0..STEPS {
+        if let Err(error) = gumboi.step() {
+            eprintln!("Benchmark : {}", error);
+            break;
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    let mhz = gumboi.get_cycles() as f64 / elapsed / 1_000_000.0;
+    println!(
+        "{} T-cycles in {:.3}s : {:.2} MHz ({:.1}x DMG speed)",
+        gumboi.get_cycles(),
+        elapsed,
+        mhz,
+        mhz / DMG_MHZ
+    );
+}
@@ -0,0 +1,668 @@
+/* Cartridge Header : 0x100 - 0x14F
+0100-0103 : Entry point
+0104-0133 : Nintendo logo
+0134-0143 : Title (0134-013E on CGB titles, followed by the manufacturer code and CGB flag)
+0144-0145 : New licensee code
+0146      : SGB flag
+0147      : Cartridge type
+0148      : ROM size
+0149      : RAM size
+014A      : Destination code
+014B      : Old licensee code (0x33 : use the new licensee code)
+014C      : Mask ROM version
+014D      : Header checksum, verified by the boot ROM
+014E-014F : Global checksum (big endian), not verified by hardware
+*/
+
+use super::error::GumBoiError;
+use super::mbc::{RomOnly, RumbleEvent, MBC, MBC1, MBC2, MBC2_RAM_SIZE, MBC3, MBC5, ROM_BANK_SIZE};
+use super::rtc::{SystemClock, TimeSource, RTC, RTC_FOOTER_SIZE};
+
+const HEADER_END: usize = 0x150;
+const TITLE: usize = 0x134;
+const MANUFACTURER_CODE: usize = 0x13F;
+const CGB_FLAG: usize = 0x143;
+const NEW_LICENSEE_CODE: usize = 0x144;
+const SGB_FLAG: usize = 0x146;
+const CARTRIDGE_TYPE: usize = 0x147;
+const ROM_SIZE: usize = 0x148;
+const RAM_SIZE: usize = 0x149;
+const OLD_LICENSEE_CODE: usize = 0x14B;
+const VERSION: usize = 0x14C;
+const HEADER_CHECKSUM: usize = 0x14D;
+const GLOBAL_CHECKSUM: usize = 0x14E;
+const LOGO: usize = 0x104;
+
+const UNBANKED_ROM_SIZE: usize = 0x8000;
+const MBC1M_ROM_SIZE: usize = 0x100000;
+const MBC1M_GAME_SIZE: usize = 0x40000;
+
+pub const NINTENDO_LOGO: [u8; 0x30] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum CgbSupport {
+    None,
+    Compatible, // 0x80 : runs on both DMG and CGB
+    Only,       // 0xC0 : CGB exclusive
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Mapper {
+    RomOnly,
+    MBC1,
+    MBC2,
+    MMM01,
+    MBC3,
+    MBC5,
+    MBC6,
+    MBC7,
+    PocketCamera,
+    TAMA5,
+    HuC3,
+    HuC1,
+}
+
+// 0x147 : the memory bank controller and the extra hardware on the cartridge
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct CartridgeType {
+    pub code: u8,
+    pub mapper: Mapper,
+    pub ram: bool,
+    pub battery: bool,
+    pub timer: bool,
+    pub rumble: bool,
+}
+
+impl CartridgeType {
+    fn from_code(code: u8) -> Result<CartridgeType, GumBoiError> {
+        // (mapper, ram, battery, timer, rumble)
+        let (mapper, ram, battery, timer, rumble) = match code {
+            0x00 => (Mapper::RomOnly, false, false, false, false),
+            0x01 => (Mapper::MBC1, false, false, false, false),
+            0x02 => (Mapper::MBC1, true, false, false, false),
+            0x03 => (Mapper::MBC1, true, true, false, false),
+            0x05 => (Mapper::MBC2, false, false, false, false),
+            0x06 => (Mapper::MBC2, false, true, false, false),
+            0x08 => (Mapper::RomOnly, true, false, false, false),
+            0x09 => (Mapper::RomOnly, true, true, false, false),
+            0x0B => (Mapper::MMM01, false, false, false, false),
+            0x0C => (Mapper::MMM01, true, false, false, false),
+            0x0D => (Mapper::MMM01, true, true, false, false),
+            0x0F => (Mapper::MBC3, false, true, true, false),
+            0x10 => (Mapper::MBC3, true, true, true, false),
+            0x11 => (Mapper::MBC3, false, false, false, false),
+            0x12 => (Mapper::MBC3, true, false, false, false),
+            0x13 => (Mapper::MBC3, true, true, false, false),
+            0x19 => (Mapper::MBC5, false, false, false, false),
+            0x1A => (Mapper::MBC5, true, false, false, false),
+            0x1B => (Mapper::MBC5, true, true, false, false),
+            0x1C => (Mapper::MBC5, false, false, false, true),
+            0x1D => (Mapper::MBC5, true, false, false, true),
+            0x1E => (Mapper::MBC5, true, true, false, true),
+            0x20 => (Mapper::MBC6, false, false, false, false),
+            0x22 => (Mapper::MBC7, true, true, false, true),
+            0xFC => (Mapper::PocketCamera, false, false, false, false),
+            0xFD => (Mapper::TAMA5, false, false, false, false),
+            0xFE => (Mapper::HuC3, false, false, false, false),
+            0xFF => (Mapper::HuC1, true, true, false, false),
+            _ => return Err(GumBoiError::InvalidCartridgeType { code }),
+        };
+        Ok(CartridgeType {
+            code,
+            mapper,
+            ram,
+            battery,
+            timer,
+            rumble,
+        })
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum Licensee {
+    Old(u8),
+    New(String), // Two ASCII characters, used when the old code is 0x33
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub manufacturer_code: Option<String>,
+    pub cgb_support: CgbSupport,
+    pub sgb_support: bool,
+    pub cartridge_type: CartridgeType,
+    pub rom_size: usize, // Bytes
+    pub ram_size: usize, // Bytes
+    pub licensee: Licensee,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    // Parses 0x100 - 0x14F, rejecting headers the boot ROM would lock up on
+    pub fn parse(rom: &[u8]) -> Result<CartridgeHeader, GumBoiError> {
+        if rom.len() < HEADER_END {
+            return Err(GumBoiError::CartridgeTooSmall { size: rom.len() });
+        }
+        let computed = CartridgeHeader::compute_header_checksum(rom);
+        if computed != rom[HEADER_CHECKSUM] {
+            return Err(GumBoiError::HeaderChecksumMismatch {
+                expected: rom[HEADER_CHECKSUM],
+                computed,
+            });
+        }
+
+        let cgb_support = match rom[CGB_FLAG] {
+            0xC0 => CgbSupport::Only,
+            0x80 => CgbSupport::Compatible,
+            _ => CgbSupport::None,
+        };
+        // CGB era titles shrink to 11 bytes to make room for the manufacturer code
+        let manufacturer_code = &rom[MANUFACTURER_CODE..CGB_FLAG];
+        let (title, manufacturer_code) = match cgb_support {
+            CgbSupport::None => (&rom[TITLE..CGB_FLAG], None),
+            _ if manufacturer_code.iter().all(u8::is_ascii_uppercase) => (
+                &rom[TITLE..MANUFACTURER_CODE],
+                Some(ascii_string(manufacturer_code)),
+            ),
+            _ => (&rom[TITLE..CGB_FLAG], None),
+        };
+        let licensee = match rom[OLD_LICENSEE_CODE] {
+            0x33 => Licensee::New(ascii_string(&rom[NEW_LICENSEE_CODE..NEW_LICENSEE_CODE + 2])),
+            code => Licensee::Old(code),
+        };
+        let rom_size = match rom[ROM_SIZE] {
+            code @ 0x00..=0x08 => (2 * ROM_BANK_SIZE) << code,
+            code => return Err(GumBoiError::InvalidRomSize { code }),
+        };
+        let ram_size = match rom[RAM_SIZE] {
+            0x00 => 0x0,
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            code => return Err(GumBoiError::InvalidRamSize { code }),
+        };
+
+        Ok(CartridgeHeader {
+            title: ascii_string(title),
+            manufacturer_code,
+            cgb_support,
+            sgb_support: rom[SGB_FLAG] == 0x03,
+            cartridge_type: CartridgeType::from_code(rom[CARTRIDGE_TYPE])?,
+            rom_size,
+            ram_size,
+            licensee,
+            version: rom[VERSION],
+            header_checksum: rom[HEADER_CHECKSUM],
+            global_checksum: (rom[GLOBAL_CHECKSUM] as u16) << 8 | rom[GLOBAL_CHECKSUM + 1] as u16,
+        })
+    }
+    // x = x - byte - 1 over 0x134 - 0x14C
+    fn compute_header_checksum(rom: &[u8]) -> u8 {
+        rom[TITLE..HEADER_CHECKSUM]
+            .iter()
+            .fold(0u8, |checksum, byte| {
+                checksum.wrapping_sub(*byte).wrapping_sub(1)
+            })
+    }
+    // Sum of every byte in the ROM except the global checksum itself
+    fn compute_global_checksum(rom: &[u8]) -> u16 {
+        rom.iter()
+            .enumerate()
+            .filter(|(addr, _)| *addr != GLOBAL_CHECKSUM && *addr != GLOBAL_CHECKSUM + 1)
+            .fold(0u16, |checksum, (_, byte)| {
+                checksum.wrapping_add(*byte as u16)
+            })
+    }
+}
+
+// Title and code fields are NUL padded ASCII
+fn ascii_string(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|byte| **byte != 0x0)
+        .map(|byte| *byte as char)
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+// MBC1M multicarts share the MBC1 header, but every 256 KiB game carries its own boot logo
+fn is_multicart(rom: &[u8]) -> bool {
+    rom.len() == MBC1M_ROM_SIZE
+        && (1..4).any(|game| {
+            let logo = game * MBC1M_GAME_SIZE + LOGO;
+            rom[logo..logo + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+        })
+}
+
+pub struct Cartridge {
+    header: CartridgeHeader,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    mbc: Box<dyn MBC>,
+    save_dirty: bool, // Battery RAM written since the last save
+}
+
+impl Cartridge {
+    // Fails on dumps the hardware would refuse to boot, or that are shorter than the header says.
+    // Overdumps are cut down to the declared size. The global checksum is left to
+    // verify_global_checksum, as real hardware never checks it
+    pub fn new(rom: Vec<u8>) -> Result<Cartridge, GumBoiError> {
+        Cartridge::with_time_source(rom, Box::new(SystemClock))
+    }
+    // The time source drives the MBC3 real time clock, it is unused by every other cartridge
+    pub fn with_time_source(
+        mut rom: Vec<u8>,
+        time_source: Box<dyn TimeSource>,
+    ) -> Result<Cartridge, GumBoiError> {
+        let header = CartridgeHeader::parse(&rom)?;
+        if rom.len() < header.rom_size {
+            return Err(GumBoiError::RomSizeMismatch {
+                expected: header.rom_size,
+                size: rom.len(),
+            });
+        }
+        rom.truncate(header.rom_size);
+        let mbc: Box<dyn MBC> = match header.cartridge_type.mapper {
+            Mapper::RomOnly if rom.len() > UNBANKED_ROM_SIZE => {
+                return Err(GumBoiError::CartridgeTooLarge {
+                    size: rom.len(),
+                    max: UNBANKED_ROM_SIZE,
+                })
+            }
+            Mapper::RomOnly => Box::new(RomOnly),
+            Mapper::MBC1 => Box::new(MBC1::new(is_multicart(&rom))),
+            Mapper::MBC3 => Box::new(MBC3::new(match header.cartridge_type.timer {
+                true => Some(RTC::new(time_source)),
+                false => None,
+            })),
+            Mapper::MBC5 => Box::new(MBC5::new(header.cartridge_type.rumble)),
+            Mapper::MBC2 => Box::new(MBC2::new()),
+            _ => {
+                return Err(GumBoiError::UnsupportedCartridgeType {
+                    code: header.cartridge_type.code,
+                })
+            }
+        };
+        // MBC2 RAM is inside the controller, the header declares none
+        let ram_size = match header.cartridge_type.mapper {
+            Mapper::MBC2 => MBC2_RAM_SIZE,
+            _ => header.ram_size,
+        };
+        Ok(Cartridge {
+            ram: vec![0u8; ram_size],
+            header,
+            rom,
+            mbc,
+            save_dirty: false,
+        })
+    }
+    pub fn verify_global_checksum(&self) -> Result<(), GumBoiError> {
+        let computed = CartridgeHeader::compute_global_checksum(&self.rom);
+        match computed == self.header.global_checksum {
+            true => Ok(()),
+            false => Err(GumBoiError::GlobalChecksumMismatch {
+                expected: self.header.global_checksum,
+                computed,
+            }),
+        }
+    }
+    pub fn get_header(&self) -> &CartridgeHeader {
+        &self.header
+    }
+    pub fn get_rom_size(&self) -> usize {
+        self.rom.len()
+    }
+    // 0x0000 - 0x7FFF and 0xA000 - 0xBFFF, banked by the MBC
+    pub fn read_rom(&self, addr: u16) -> u8 {
+        self.mbc.read_rom(&self.rom, addr)
+    }
+    pub fn write_rom(&mut self, addr: u16, value: u8) {
+        self.mbc.write_rom(addr, value);
+    }
+    pub fn read_ram(&self, offset: u16) -> u8 {
+        self.mbc.read_ram(&self.ram, offset)
+    }
+    pub fn write_ram(&mut self, offset: u16, value: u8) {
+        if self.mbc.write_ram(&mut self.ram, offset, value) {
+            self.save_dirty |= self.header.cartridge_type.battery;
+        }
+    }
+    pub fn has_battery(&self) -> bool {
+        self.header.cartridge_type.battery
+    }
+    pub fn is_save_dirty(&self) -> bool {
+        self.save_dirty
+    }
+    pub fn drain_rumble_events(&mut self) -> Vec<RumbleEvent> {
+        self.mbc.drain_rumble_events()
+    }
+    // External RAM, followed by the 48 byte RTC footer on carts with a clock
+    pub fn get_save_data(&self) -> Vec<u8> {
+        let mut save_data = self.ram.clone();
+        if let Some(rtc) = self.mbc.rtc() {
+            save_data.extend_from_slice(&rtc.to_footer());
+        }
+        save_data
+    }
+    // Called once a snapshot has safely reached the disk
+    pub fn mark_saved(&mut self) {
+        self.save_dirty = false;
+    }
+    // Saves without a footer are accepted on RTC carts, the clock then starts from zero
+    pub fn load_save_data(&mut self, save_data: &[u8]) -> Result<(), GumBoiError> {
+        let ram_size = self.ram.len();
+        let footer_size = save_data.len().checked_sub(ram_size);
+        match (self.mbc.rtc_mut(), footer_size) {
+            (Some(rtc), Some(footer_size)) if RTC::is_valid_footer_size(footer_size) => {
+                rtc.load_footer(&save_data[ram_size..])?
+            }
+            _ if save_data.len() == ram_size => {}
+            (rtc, _) => {
+                return Err(GumBoiError::SaveSizeMismatch {
+                    expected: ram_size
+                        + match rtc {
+                            Some(_) => RTC_FOOTER_SIZE,
+                            None => 0,
+                        },
+                    size: save_data.len(),
+                })
+            }
+        }
+        self.ram.copy_from_slice(&save_data[..ram_size]);
+        Ok(())
+    }
+}
+
+// Recomputes both checksums so hand built test images pass validation
+#[cfg(test)]
+pub fn fix_checksums(rom: &mut [u8]) {
+    rom[HEADER_CHECKSUM] = CartridgeHeader::compute_header_checksum(rom);
+    let global_checksum = CartridgeHeader::compute_global_checksum(rom);
+    rom[GLOBAL_CHECKSUM] = (global_checksum >> 8) as u8;
+    rom[GLOBAL_CHECKSUM + 1] = global_checksum as u8;
+}
+
+#[cfg(test)]
+mod cartridge_tests {
+    use super::{
+        fix_checksums, Cartridge, CartridgeHeader, CgbSupport, Licensee, Mapper, HEADER_CHECKSUM,
+        NINTENDO_LOGO,
+    };
+    use crate::error::GumBoiError;
+
+    // 64 KiB MBC1+RAM+BATTERY CGB title with a new licensee code
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0u8; 0x10000];
+        rom[0x134..0x13F].copy_from_slice(b"POKEMON_SLV");
+        rom[0x13F..0x143].copy_from_slice(b"AAXE");
+        rom[0x143] = 0x80;
+        rom[0x144..0x146].copy_from_slice(b"01");
+        rom[0x146] = 0x03;
+        rom[0x147] = 0x03;
+        rom[0x148] = 0x01;
+        rom[0x149] = 0x03;
+        rom[0x14B] = 0x33;
+        rom[0x14C] = 0x02;
+        rom[0x8000] = 0x42;
+        fix_checksums(&mut rom);
+        rom
+    }
+
+    #[test]
+    fn parses_header_fields() {
+        let header = CartridgeHeader::parse(&rom()).unwrap();
+        assert_eq!(
+            (
+                header.title.as_str(),
+                header.manufacturer_code,
+                header.cgb_support,
+                header.sgb_support,
+                header.licensee,
+                header.version
+            ),
+            (
+                "POKEMON_SLV",
+                Some(String::from("AAXE")),
+                CgbSupport::Compatible,
+                true,
+                Licensee::New(String::from("01")),
+                0x02
+            )
+        );
+    }
+    #[test]
+    fn parses_cartridge_type_and_sizes() {
+        let header = CartridgeHeader::parse(&rom()).unwrap();
+        let cartridge_type = header.cartridge_type;
+        assert_eq!(
+            (
+                cartridge_type.mapper,
+                cartridge_type.ram,
+                cartridge_type.battery,
+                cartridge_type.timer,
+                header.rom_size,
+                header.ram_size
+            ),
+            (Mapper::MBC1, true, true, false, 0x10000, 0x8000)
+        );
+    }
+    #[test]
+    fn dmg_title_uses_all_sixteen_bytes() {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x134..0x144].copy_from_slice(b"SUPER MARIOLAND ");
+        rom[0x14B] = 0x01;
+        fix_checksums(&mut rom);
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(
+            (
+                header.title.as_str(),
+                header.manufacturer_code,
+                header.cgb_support,
+                header.licensee
+            ),
+            (
+                "SUPER MARIOLAND",
+                None,
+                CgbSupport::None,
+                Licensee::Old(0x01)
+            )
+        );
+    }
+    #[test]
+    fn rejects_bad_header_checksum() {
+        let mut rom = rom();
+        rom[HEADER_CHECKSUM] ^= 0xFF;
+        assert!(matches!(
+            CartridgeHeader::parse(&rom),
+            Err(GumBoiError::HeaderChecksumMismatch { .. })
+        ));
+    }
+    #[test]
+    fn verifies_global_checksum() {
+        let cartridge = Cartridge::new(rom()).unwrap();
+        assert_eq!(cartridge.verify_global_checksum(), Ok(()));
+    }
+    #[test]
+    fn flags_bad_global_checksum() {
+        let mut rom = rom();
+        rom[0x8000] = 0x43;
+        let cartridge = Cartridge::new(rom).unwrap();
+        let expected = cartridge.get_header().global_checksum;
+        assert_eq!(
+            cartridge.verify_global_checksum(),
+            Err(GumBoiError::GlobalChecksumMismatch {
+                expected,
+                computed: expected.wrapping_add(1)
+            })
+        );
+    }
+    #[test]
+    fn rejects_invalid_codes() {
+        for (addr, code, error) in [
+            (
+                0x147,
+                0x04,
+                GumBoiError::InvalidCartridgeType { code: 0x04 },
+            ),
+            (0x148, 0x09, GumBoiError::InvalidRomSize { code: 0x09 }),
+            (0x149, 0x06, GumBoiError::InvalidRamSize { code: 0x06 }),
+        ]
+        .iter()
+        {
+            let mut rom = rom();
+            rom[*addr] = *code;
+            fix_checksums(&mut rom);
+            assert_eq!(CartridgeHeader::parse(&rom), Err(error.clone()));
+        }
+    }
+    #[test]
+    fn rejects_truncated_dumps() {
+        let mut rom = rom();
+        rom.truncate(0x8000);
+        assert_eq!(
+            Cartridge::new(rom).err(),
+            Some(GumBoiError::RomSizeMismatch {
+                expected: 0x10000,
+                size: 0x8000
+            })
+        );
+        assert_eq!(
+            Cartridge::new(vec![0u8; 0x100]).err(),
+            Some(GumBoiError::CartridgeTooSmall { size: 0x100 })
+        );
+    }
+    #[test]
+    fn truncates_overdumps() {
+        let mut rom = rom();
+        rom.resize(0x20000, 0xFF);
+        let cartridge = Cartridge::new(rom).unwrap();
+        assert_eq!(cartridge.rom.len(), 0x10000);
+    }
+    #[test]
+    fn detects_mbc1_multicarts() {
+        let mut rom = vec![0u8; 0x100000];
+        rom[0x147] = 0x01;
+        rom[0x148] = 0x05;
+        rom[0x40104..0x40134].copy_from_slice(&NINTENDO_LOGO);
+        rom[0x44000] = 0x42;
+        fix_checksums(&mut rom);
+        let mut cartridge = Cartridge::new(rom.clone()).unwrap();
+        cartridge.write_rom(0x4000, 0x01);
+        cartridge.write_rom(0x2000, 0x01);
+        assert_eq!(cartridge.read_rom(0x4000), 0x42);
+        // Without the second logo BANK2 = 1 points at bank 0x21
+        rom[0x40104] = 0x0;
+        fix_checksums(&mut rom);
+        let mut cartridge = Cartridge::new(rom).unwrap();
+        cartridge.write_rom(0x4000, 0x01);
+        cartridge.write_rom(0x2000, 0x01);
+        assert_eq!(cartridge.read_rom(0x4000), 0x0);
+    }
+    #[test]
+    fn mbc2_carts_get_their_built_in_ram() {
+        let mut rom = rom();
+        rom[0x147] = 0x06;
+        rom[0x149] = 0x00;
+        fix_checksums(&mut rom);
+        assert_eq!(Cartridge::new(rom).unwrap().get_save_data().len(), 0x200);
+    }
+    #[test]
+    fn rejects_unsupported_mappers() {
+        let mut rom = rom();
+        rom[0x147] = 0xFC;
+        fix_checksums(&mut rom);
+        assert_eq!(
+            Cartridge::new(rom).err(),
+            Some(GumBoiError::UnsupportedCartridgeType { code: 0xFC })
+        );
+    }
+}
+
+#[cfg(test)]
+mod save_data_tests {
+    use super::{fix_checksums, Cartridge};
+    use crate::error::GumBoiError;
+    use crate::rtc::rtc_tests::FakeClock;
+
+    // 64 KiB, 8 KiB RAM
+    fn mbc3(cartridge_type: u8, clock: &FakeClock) -> Cartridge {
+        let mut rom = vec![0u8; 0x10000];
+        rom[0x147] = cartridge_type;
+        rom[0x148] = 0x01;
+        rom[0x149] = 0x02;
+        fix_checksums(&mut rom);
+        Cartridge::with_time_source(rom, Box::new(clock.clone())).unwrap()
+    }
+
+    #[test]
+    fn only_stored_writes_dirty_the_save() {
+        let mut cartridge = mbc3(0x10, &FakeClock::default());
+        cartridge.write_ram(0x0, 0x42);
+        assert!(!cartridge.is_save_dirty());
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0x0, 0x42);
+        assert!(cartridge.is_save_dirty());
+        cartridge.mark_saved();
+        assert!(!cartridge.is_save_dirty());
+    }
+    #[test]
+    fn rtc_state_persists_in_the_footer() {
+        let clock = FakeClock::default();
+        let mut cartridge = mbc3(0x10, &clock);
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0x0, 0x42);
+        cartridge.write_rom(0x4000, 0x0A);
+        cartridge.write_ram(0x0, 0x05);
+        let save_data = cartridge.get_save_data();
+        assert_eq!(save_data.len(), 0x2000 + 48);
+
+        let mut restored = mbc3(0x10, &clock);
+        clock.advance(3600);
+        restored.load_save_data(&save_data).unwrap();
+        restored.write_rom(0x0000, 0x0A);
+        restored.write_rom(0x6000, 0x00);
+        restored.write_rom(0x6000, 0x01);
+        restored.write_rom(0x4000, 0x0A);
+        assert_eq!(restored.read_ram(0x0), 0x06);
+        restored.write_rom(0x4000, 0x00);
+        assert_eq!(restored.read_ram(0x0), 0x42);
+    }
+    #[test]
+    fn rejects_mismatched_save_sizes() {
+        let clock = FakeClock::default();
+        let mut cartridge = mbc3(0x10, &clock);
+        assert_eq!(cartridge.load_save_data(&[0u8; 0x2000]), Ok(()));
+        assert_eq!(
+            cartridge.load_save_data(&[0u8; 0x2001]),
+            Err(GumBoiError::SaveSizeMismatch {
+                expected: 0x2000 + 48,
+                size: 0x2001
+            })
+        );
+        assert_eq!(
+            cartridge.load_save_data(&[0u8; 0x1000]),
+            Err(GumBoiError::SaveSizeMismatch {
+                expected: 0x2000 + 48,
+                size: 0x1000
+            })
+        );
+        let mut cartridge = mbc3(0x13, &clock);
+        assert_eq!(cartridge.get_save_data().len(), 0x2000);
+        assert_eq!(
+            cartridge.load_save_data(&[0u8; 0x2000 + 48]),
+            Err(GumBoiError::SaveSizeMismatch {
+                expected: 0x2000,
+                size: 0x2000 + 48
+            })
+        );
+    }
+}
@@ -2,14 +2,13 @@
 add8 : 26 Intructions
 add16 : 12 instructions
 sub8 : 37 instructions
-sub16 : 7 instructions
 */
 
 // ANCHOR <Opcode> | <Instruction> | <[Z N H C]> | <Bytes> | <Cycles>
+// Mnemonics, lengths, cycles and flag effects live in the opcode tables (src/opcodes.rs)
 
-use std::sync::{Arc, Mutex};
-
-use super::memory::Memory;
+use super::bus::{Bus, M_CYCLE};
+use super::error::GumBoiError;
 use super::registers::Flag;
 use super::registers::Registers;
 
@@ -18,1745 +17,477 @@ pub enum CPUState {
     Halt,
     Stop,
     Active,
-    Exit,
+    Lock, // Illegal opcode executed, CPU hangs until reset
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
     registers: Registers,
-    memory: Arc<Mutex<Memory>>,
-    cycle: usize,
+    cycle: usize, // T-cycles taken by the last step
     state: CPUState,
-    ime: Arc<Mutex<bool>>, // Interrupt Master Enable
+    ime: bool,           // Interrupt Master Enable
+    ime_scheduled: bool, // EI enables IME only after the following instruction
+    halt_bug: bool,      // HALT with IME clear and an interrupt pending skips the PC increment
 }
 
 impl CPU {
-    pub fn new(memory: Arc<Mutex<Memory>>, ime: Arc<Mutex<bool>>) -> CPU {
+    pub fn new() -> CPU {
         CPU {
             registers: Registers::new(),
             cycle: 0,
             state: CPUState::Active,
-            memory: memory,
-            ime: ime,
+            ime: false,
+            ime_scheduled: false,
+            halt_bug: false,
         }
     }
-    fn get_next_byte8(&mut self) -> u8 {
-        let mut byte: u8 = self.memory.lock().unwrap().get_addr(self.registers.pc);
-        self.registers.pc += 1;
-        byte
+    // One M-cycle passes for the whole system
+    fn tick<B: Bus>(&mut self, bus: &mut B) {
+        self.cycle += M_CYCLE;
+        bus.tick();
     }
-    fn get_next_byte16(&mut self) -> u16 {
-        let mut byte: u16;
-        byte = self.memory.lock().unwrap().get_addr(self.registers.pc) as u16;
-        self.registers.pc += 1;
-        byte |= (self.memory.lock().unwrap().get_addr(self.registers.pc) as u16) << 8;
-        self.registers.pc += 1;
+    // Memory accesses take one M-cycle each, the rest of the system is ticked before the access
+    fn read8<B: Bus>(&mut self, bus: &mut B, addr: u16) -> u8 {
+        self.tick(bus);
+        bus.read(addr)
+    }
+    fn write8<B: Bus>(&mut self, bus: &mut B, addr: u16, value: u8) {
+        self.tick(bus);
+        bus.write(addr, value);
+    }
+    fn get_next_byte8<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        let byte: u8 = self.read8(bus, self.registers.pc);
+        self.registers.pc = self.registers.pc.wrapping_add(1);
         byte
     }
-    pub fn execute(&mut self) {
-        let opcode: u8 = self.memory.lock().unwrap().get_addr(self.registers.pc);
-        //println!("{:#x?}", opcode);
-        let mut opcode_cb: u8 = 0x0;
-        let mut byte: u16 = 0x0;
-        let mut byte8: u8 = 0x0;
-        let mut flag: bool = false;
-
-        self.registers.pc += 1;
-        // SECTION CPU Instructions
-        match opcode {
-            //8 bit LD
-            // LD r d8 [- - - -]
-            0x3E => {
-                self.registers.a = self.get_next_byte8();
-                self.cycle = 8;
-            }
-            0x06 => {
-                self.registers.b = self.get_next_byte8();
-                self.cycle = 8;
-            }
-            0x0E => {
-                self.registers.c = self.get_next_byte8();
-                self.cycle = 8;
-            }
-            0x16 => {
-                self.registers.d = self.get_next_byte8();
-                self.cycle = 8;
-            }
-            0x1E => {
-                self.registers.e = self.get_next_byte8();
-                self.cycle = 8;
-            }
-            0x26 => {
-                self.registers.h = self.get_next_byte8();
-                self.cycle = 8;
-            }
-            0x2E => {
-                self.registers.l = self.get_next_byte8();
-                self.cycle = 8;
-            }
-            0x36 => {
-                byte8 = self.get_next_byte8();
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(self.registers.get_hl(), byte8);
-                self.cycle = 12;
-            }
-
-            0x7F => {
-                self.registers.a = self.registers.a;
-                self.cycle = 4;
-            }
-            0x78 => {
-                self.registers.a = self.registers.b;
-                self.cycle = 4;
-            }
-            0x79 => {
-                self.registers.a = self.registers.c;
-                self.cycle = 4;
-            }
-            0x7A => {
-                self.registers.a = self.registers.d;
-                self.cycle = 4;
-            }
-            0x7B => {
-                self.registers.a = self.registers.e;
-                self.cycle = 4;
-            }
-            0x7C => {
-                self.registers.a = self.registers.h;
-                self.cycle = 4;
-            }
-            0x7D => {
-                self.registers.a = self.registers.l;
-                self.cycle = 4;
-            }
-            0x7E => {
-                self.registers.a = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.cycle = 8;
-            }
-            0x40 => {
-                self.registers.b = self.registers.b;
-                self.cycle = 4;
-            }
-            0x41 => {
-                self.registers.b = self.registers.c;
-                self.cycle = 4;
-            }
-            0x42 => {
-                self.registers.b = self.registers.d;
-                self.cycle = 4;
-            }
-            0x43 => {
-                self.registers.b = self.registers.e;
-                self.cycle = 4;
-            }
-            0x44 => {
-                self.registers.b = self.registers.h;
-                self.cycle = 4;
-            }
-            0x45 => {
-                self.registers.b = self.registers.l;
-                self.cycle = 4;
-            }
-            0x46 => {
-                self.registers.b = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.cycle = 8;
-            }
-            0x48 => {
-                self.registers.c = self.registers.b;
-                self.cycle = 4;
-            }
-            0x49 => {
-                self.registers.c = self.registers.c;
-                self.cycle = 4;
-            }
-            0x4A => {
-                self.registers.c = self.registers.d;
-                self.cycle = 4;
-            }
-            0x4B => {
-                self.registers.c = self.registers.e;
-                self.cycle = 4;
-            }
-            0x4C => {
-                self.registers.c = self.registers.h;
-                self.cycle = 4;
-            }
-            0x4D => {
-                self.registers.c = self.registers.l;
-                self.cycle = 4;
-            }
-            0x4E => {
-                self.registers.c = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.cycle = 8;
-            }
-            0x50 => {
-                self.registers.d = self.registers.b;
-                self.cycle = 4;
-            }
-            0x51 => {
-                self.registers.d = self.registers.c;
-                self.cycle = 4;
-            }
-            0x52 => {
-                self.registers.d = self.registers.d;
-                self.cycle = 4;
-            }
-            0x53 => {
-                self.registers.d = self.registers.e;
-                self.cycle = 4;
-            }
-            0x54 => {
-                self.registers.d = self.registers.h;
-                self.cycle = 4;
-            }
-            0x55 => {
-                self.registers.d = self.registers.l;
-                self.cycle = 4;
-            }
-            0x56 => {
-                self.registers.d = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.cycle = 8;
-            }
-            0x58 => {
-                self.registers.e = self.registers.b;
-                self.cycle = 4;
-            }
-            0x59 => {
-                self.registers.e = self.registers.c;
-                self.cycle = 4;
-            }
-            0x5A => {
-                self.registers.e = self.registers.d;
-                self.cycle = 4;
-            }
-            0x5B => {
-                self.registers.e = self.registers.e;
-                self.cycle = 4;
-            }
-            0x5C => {
-                self.registers.e = self.registers.h;
-                self.cycle = 4;
-            }
-            0x5D => {
-                self.registers.e = self.registers.l;
-                self.cycle = 4;
-            }
-            0x5E => {
-                self.registers.e = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.cycle = 8;
-            }
-            0x60 => {
-                self.registers.h = self.registers.b;
-                self.cycle = 4;
-            }
-            0x61 => {
-                self.registers.h = self.registers.c;
-                self.cycle = 4;
-            }
-            0x62 => {
-                self.registers.h = self.registers.d;
-                self.cycle = 4;
-            }
-            0x63 => {
-                self.registers.h = self.registers.e;
-                self.cycle = 4;
-            }
-            0x64 => {
-                self.registers.h = self.registers.h;
-                self.cycle = 4;
-            }
-            0x65 => {
-                self.registers.h = self.registers.l;
-                self.cycle = 4;
-            }
-            0x66 => {
-                self.registers.h = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.cycle = 8;
-            }
-            0x68 => {
-                self.registers.l = self.registers.b;
-                self.cycle = 4;
-            }
-            0x69 => {
-                self.registers.l = self.registers.c;
-                self.cycle = 4;
-            }
-            0x6A => {
-                self.registers.l = self.registers.d;
-                self.cycle = 4;
-            }
-            0x6B => {
-                self.registers.l = self.registers.e;
-                self.cycle = 4;
-            }
-            0x6C => {
-                self.registers.l = self.registers.h;
-                self.cycle = 4;
-            }
-            0x6D => {
-                self.registers.l = self.registers.l;
-                self.cycle = 4;
-            }
-            0x6E => {
-                self.registers.l = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.cycle = 8;
-            }
-            0x70 => {
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(self.registers.get_hl(), self.registers.b);
-                self.cycle = 8;
-            }
-            0x71 => {
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(self.registers.get_hl(), self.registers.c);
-                self.cycle = 8;
-            }
-            0x72 => {
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(self.registers.get_hl(), self.registers.d);
-                self.cycle = 8;
-            }
-            0x73 => {
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(self.registers.get_hl(), self.registers.e);
-                self.cycle = 8;
-            }
-            0x74 => {
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(self.registers.get_hl(), self.registers.h);
-                self.cycle = 8;
-            }
-            0x75 => {
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(self.registers.get_hl(), self.registers.l);
-                self.cycle = 8;
-            }
-
-            0x0A => {
-                self.registers.a = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_bc());
-                self.cycle = 8;
-            }
-            0x1A => {
-                self.registers.a = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_de());
-                self.cycle = 8;
-            }
-            0xFA => {
-                byte = self.get_next_byte16();
-                self.registers.a = self.memory.lock().unwrap().get_addr(byte);
-                self.cycle = 16;
-            }
-
-            0x47 => {
-                self.registers.b = self.registers.a;
-                self.cycle = 4;
-            }
-            0x4F => {
-                self.registers.c = self.registers.a;
-                self.cycle = 4;
-            }
-            0x57 => {
-                self.registers.d = self.registers.a;
-                self.cycle = 4;
-            }
-            0x5F => {
-                self.registers.e = self.registers.a;
-                self.cycle = 4;
-            }
-            0x67 => {
-                self.registers.h = self.registers.a;
-                self.cycle = 4;
-            }
-            0x6F => {
-                self.registers.l = self.registers.a;
-                self.cycle = 4;
-            }
-            0x02 => {
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(self.registers.get_bc(), self.registers.a);
-                self.cycle = 8;
-            }
-            0x12 => {
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(self.registers.get_de(), self.registers.a);
-                self.cycle = 8;
-            }
-            0x77 => {
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(self.registers.get_hl(), self.registers.a);
-                self.cycle = 8;
-            }
-            0xEA => {
-                byte = self.get_next_byte16();
-                self.memory.lock().unwrap().set_addr(byte, self.registers.a);
-                self.cycle = 16;
-            }
-            0xF2 => {
-                self.registers.a = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(0xFF00 | (self.registers.c as u16));
-                self.cycle = 8;
-            }
-            0xE2 => {
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(0xFF00 + (self.registers.c as u16), self.registers.a);
-                self.cycle = 8;
-            }
-            0x3A => {
-                self.registers.a = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.registers.set_hl(self.registers.get_hl() - 0x1);
-                self.cycle = 8;
-            }
-            0x32 => {
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(self.registers.get_hl(), self.registers.a);
-                self.registers.set_hl(self.registers.get_hl() - 1);
-                self.cycle = 8;
-            }
-            0x2A => {
-                self.registers.a = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.registers.set_hl(self.registers.get_hl() + 0x1);
-                self.cycle = 8;
-            }
-            0x22 => {
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(self.registers.get_hl(), self.registers.a);
-                self.registers.set_hl(self.registers.get_hl() + 0x0001);
-                self.cycle = 8;
-            }
-            0xE0 => {
-                byte8 = self.get_next_byte8();
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(0xFF00 + (byte8 as u16), self.registers.a);
-                self.cycle = 12;
-            }
-            0xF0 => {
-                byte8 = self.get_next_byte8();
-                self.registers.a = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(0xFF00 + (byte8 as u16));
-                self.cycle = 12;
-            }
-
-            //16 bit LD
-            0x01 => {
-                byte = self.get_next_byte16();
-                self.registers.set_bc(byte);
-                self.cycle = 12;
-            }
-            0x11 => {
-                byte = self.get_next_byte16();
-                self.registers.set_de(byte);
-                self.cycle = 12;
-            }
-            0x21 => {
-                byte = self.get_next_byte16();
-                self.registers.set_hl(byte);
-                self.cycle = 12;
-            }
-            0x31 => {
-                byte = self.get_next_byte16();
-                self.registers.sp = byte;
-                self.cycle = 12;
-            }
-            0xF9 => {
-                self.registers.sp = self.registers.get_hl();
-                self.cycle = 8
-            }
-            0xF8 => {
-                byte = self.get_next_byte16();
-                byte = self.add16(self.registers.sp, byte, false);
-                self.registers.set_hl(byte);
-                self.registers.reset_z();
-                self.registers.reset_n();
-                self.cycle = 12;
-            }
-
-            //To be reviewed
-            0x08 => {
-                byte = self.get_next_byte16();
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(byte, (self.registers.sp & 0x00ff) as u8);
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(byte + 1, (self.registers.sp >> 8) as u8);
-                self.cycle = 20;
-            }
-
-            // SECTION Stack Operations
-            // SECTION PUSH
-            // 0xF5 -> PUSH AF | [- - - -] | 1 | 16
-            0xF5 => {
-                self.push(self.registers.get_af());
-                self.cycle = 16;
-            }
-            // 0xC5 -> PUSH BC | [- - - -] | 1 | 16
-            0xC5 => {
-                self.push(self.registers.get_bc());
-                self.cycle = 16;
-            }
-            // 0xD5 -> PUSH DE | [- - - -] | 1 | 16
-            0xD5 => {
-                self.push(self.registers.get_de());
-                self.cycle = 16;
-            }
-            // 0xE5 -> PUSH HL | [- - - -] | 1 | 16
-            0xE5 => {
-                self.push(self.registers.get_hl());
-                self.cycle = 16;
-            }
-            // !SECTION
-            // SECTION POP
-            // 0xF1 -> POP AF | [- - - -] | 1 | 12
-            0xF1 => {
-                byte = self.pop();
-                self.registers.set_af(byte);
-                self.cycle = 12;
-            }
-            // 0xC1 -> POP BC | [- - - -] | 1 | 12
-            0xC1 => {
-                byte = self.pop();
-                self.registers.set_bc(byte);
-                self.cycle = 12;
-            }
-            // 0xD1 -> POP DE | [- - - -] | 1 | 12
-            0xD1 => {
-                byte = self.pop();
-                self.registers.set_de(byte);
-                self.cycle = 12;
-            }
-            // 0xE1 -> POP HL | [- - - -] | 1 | 12
-            0xE1 => {
-                byte = self.pop();
-                self.registers.set_hl(byte);
-                self.cycle = 12;
-            }
-            // !SECTION
-            // !SECTION
-            // 8 BIT ALU
-            //ADD
-            0x87 => {
-                self.registers.a = self.add8(self.registers.a, self.registers.a, false);
-                self.cycle = 4;
-            }
-            0x80 => {
-                self.registers.a = self.add8(self.registers.a, self.registers.b, false);
-                self.cycle = 4;
-            }
-            0x81 => {
-                self.registers.a = self.add8(self.registers.a, self.registers.c, false);
-                self.cycle = 4;
-            }
-            0x82 => {
-                self.registers.a = self.add8(self.registers.a, self.registers.d, false);
-                self.cycle = 4;
-            }
-            0x83 => {
-                self.registers.a = self.add8(self.registers.a, self.registers.e, false);
-                self.cycle = 4;
-            }
-            0x84 => {
-                self.registers.a = self.add8(self.registers.a, self.registers.h, false);
-                self.cycle = 4;
-            }
-            0x85 => {
-                self.registers.a = self.add8(self.registers.a, self.registers.l, false);
-                self.cycle = 4;
-            }
-            0x86 => {
-                byte8 = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.registers.a = self.add8(self.registers.a, byte8, false);
-                self.cycle = 8;
-            }
-            0xC6 => {
-                byte8 = self.get_next_byte8();
-                self.registers.a = self.add8(self.registers.a, byte8, false);
-                self.cycle = 8;
-            }
-
-            //ADD WITH CARRY
-            0x8F => {
-                self.registers.a = self.add8(self.registers.a, self.registers.a, true);
-                self.cycle = 4;
-            }
-            0x88 => {
-                self.registers.a = self.add8(self.registers.a, self.registers.b, true);
-                self.cycle = 4;
-            }
-            0x89 => {
-                self.registers.a = self.add8(self.registers.a, self.registers.c, true);
-                self.cycle = 4;
-            }
-            0x8A => {
-                self.registers.a = self.add8(self.registers.a, self.registers.d, true);
-                self.cycle = 4;
-            }
-            0x8B => {
-                self.registers.a = self.add8(self.registers.a, self.registers.e, true);
-                self.cycle = 4;
-            }
-            0x8C => {
-                self.registers.a = self.add8(self.registers.a, self.registers.h, true);
-                self.cycle = 4;
-            }
-            0x8D => {
-                self.registers.a = self.add8(self.registers.a, self.registers.l, true);
-                self.cycle = 4;
-            }
-            0x8E => {
-                byte8 = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.registers.a = self.add8(self.registers.a, byte8, true);
-                self.cycle = 8;
-            }
-            0xCE => {
-                byte8 = self.get_next_byte8();
-                self.registers.a = self.add8(self.registers.a, byte8, true);
-                self.cycle = 8;
-            }
-
-            //SUB
-            0x97 => {
-                self.registers.a = self.sub8(self.registers.a, self.registers.a, false);
-                self.cycle = 4;
-            }
-            0x90 => {
-                self.registers.a = self.sub8(self.registers.a, self.registers.b, false);
-                self.cycle = 4;
-            }
-            0x91 => {
-                self.registers.a = self.sub8(self.registers.a, self.registers.c, false);
-                self.cycle = 4;
-            }
-            0x92 => {
-                self.registers.a = self.sub8(self.registers.a, self.registers.d, false);
-                self.cycle = 4;
-            }
-            0x93 => {
-                self.registers.a = self.sub8(self.registers.a, self.registers.e, false);
-                self.cycle = 4;
-            }
-            0x94 => {
-                self.registers.a = self.sub8(self.registers.a, self.registers.h, false);
-                self.cycle = 4;
-            }
-            0x95 => {
-                self.registers.a = self.sub8(self.registers.a, self.registers.l, false);
-                self.cycle = 4;
-            }
-            0x96 => {
-                byte8 = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.registers.a = self.sub8(self.registers.a, byte8, false);
-                self.cycle = 8;
-            }
-            0xD6 => {
-                byte8 = self.get_next_byte8();
-                self.registers.a = self.sub8(self.registers.a, byte8, false);
-                self.cycle = 8;
-            }
-
-            //SUB WITH BORROW
-            0x9F => {
-                self.registers.a = self.sub8(self.registers.a, self.registers.a, true);
-                self.cycle = 4;
-            }
-            0x98 => {
-                self.registers.a = self.sub8(self.registers.a, self.registers.b, true);
-                self.cycle = 4;
-            }
-            0x99 => {
-                self.registers.a = self.sub8(self.registers.a, self.registers.c, true);
-                self.cycle = 4;
-            }
-            0x9A => {
-                self.registers.a = self.sub8(self.registers.a, self.registers.d, true);
-                self.cycle = 4;
-            }
-            0x9B => {
-                self.registers.a = self.sub8(self.registers.a, self.registers.e, true);
-                self.cycle = 4;
-            }
-            0x9C => {
-                self.registers.a = self.sub8(self.registers.a, self.registers.h, true);
-                self.cycle = 4;
-            }
-            0x9D => {
-                self.registers.a = self.sub8(self.registers.a, self.registers.l, true);
-                self.cycle = 4;
-            }
-            0x9E => {
-                byte8 = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.registers.a = self.sub8(self.registers.a, byte8, true);
-                self.cycle = 8;
-            }
-            0xDE => {
-                byte8 = self.get_next_byte8();
-                self.registers.a = self.sub8(self.registers.a, byte as u8, true);
-                self.cycle = 8;
+    fn get_next_byte16<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        let byte: u16 = self.get_next_byte8(bus) as u16;
+        byte | (self.get_next_byte8(bus) as u16) << 8
+    }
+    fn jump_relative(&mut self, offset: i8) {
+        self.registers.pc = self.registers.pc.wrapping_add(offset as u16);
+    }
+    pub fn execute<B: Bus>(&mut self, bus: &mut B) -> Result<(), GumBoiError> {
+        self.cycle = 0;
+        // STOP freezes the system clock until one of the joypad lines P10-P13 goes low
+        if self.state == CPUState::Stop {
+            if bus.get_joypad_lines() == 0x0F {
+                return Ok(());
+            }
+            self.state = CPUState::Active;
+        }
+        // A halted CPU idles until an interrupt is both requested and enabled, regardless of IME
+        if self.state == CPUState::Halt {
+            if bus.interrupts().pending().is_none() {
+                self.tick(bus);
+                return Ok(());
             }
+            self.state = CPUState::Active;
+        }
+        if self.service_interrupt(bus) {
+            return Ok(());
+        }
+        // An EI from the previous instruction takes effect now, after the interrupt check
+        if self.ime_scheduled {
+            self.ime_scheduled = false;
+            self.ime = true;
+        }
+        let opcode: u8 = self.get_next_byte8(bus);
+        if self.halt_bug {
+            // The byte following HALT is read twice
+            self.halt_bug = false;
+            self.registers.pc = self.registers.pc.wrapping_sub(1);
+        }
+        // Opcode bit fields [x:7-6 | y:5-3 | z:2-0] with p = y >> 1 and q = y & 1
+        let x = opcode >> 6;
+        let y = (opcode >> 3) & 0x07;
+        let z = opcode & 0x07;
+        let p = y >> 1;
+        let q = y & 0x01;
+        let byte: u16;
+        let mut byte8: u8;
 
-            //LOGICAL OPERATIONS
-            //AND
-            0xA7 => {
-                self.registers.a &= self.registers.a;
-                self.registers.reset_flags();
-                self.registers.set_h();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
-            }
-            0xA0 => {
-                self.registers.a &= self.registers.b;
-                self.registers.reset_flags();
-                self.registers.set_h();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
-            }
-            0xA1 => {
-                self.registers.a &= self.registers.c;
-                self.registers.reset_flags();
-                self.registers.set_h();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
-            }
-            0xA2 => {
-                self.registers.a &= self.registers.d;
-                self.registers.reset_flags();
-                self.registers.set_h();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
-            }
-            0xA3 => {
-                self.registers.a &= self.registers.e;
-                self.registers.reset_flags();
-                self.registers.set_h();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
-            }
-            0xA4 => {
-                self.registers.a &= self.registers.h;
-                self.registers.reset_flags();
-                self.registers.set_h();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
-            }
-            0xA5 => {
-                self.registers.a &= self.registers.l;
-                self.registers.reset_flags();
-                self.registers.set_h();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
+        // SECTION CPU Instructions
+        match (x, z) {
+            // SECTION x = 0
+            (0, 0) => match y {
+                // ANCHOR 0x00 | NOP | [- - - -] | 1 | 4
+                0 => {}
+                // ANCHOR 0x08 | LD (a16), SP | [- - - -] | 3 | 20
+                1 => {
+                    byte = self.get_next_byte16(bus);
+                    self.write8(bus, byte, (self.registers.sp & 0x00ff) as u8);
+                    self.write8(bus, byte.wrapping_add(1), (self.registers.sp >> 8) as u8);
+                }
+                // ANCHOR 0x10 | STOP | [- - - -] | 2 | 4
+                // Resets DIV and enters low power mode (the CGB speed switch hooks in here).
+                // The padding byte is skipped without a fetch cycle
+                2 => {
+                    self.registers.pc = self.registers.pc.wrapping_add(1);
+                    bus.reset_divider();
+                    self.state = CPUState::Stop;
+                }
+                // ANCHOR 0x18 | JR r8 | [- - - -] | 2 | 12
+                3 => {
+                    byte8 = self.get_next_byte8(bus);
+                    self.jump_relative(byte8 as i8);
+                    self.tick(bus);
+                }
+                // ANCHOR 0x20 0x28 0x30 0x38 | JR cc, r8 | [- - - -] | 2 | 12/8
+                _ => {
+                    byte8 = self.get_next_byte8(bus);
+                    if self.condition(y - 4) {
+                        self.jump_relative(byte8 as i8);
+                        self.tick(bus);
+                    }
                 }
-                self.cycle = 4;
-            }
-            0xA6 => {
-                self.registers.a &= self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.registers.reset_flags();
-                self.registers.set_h();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
+            },
+            (0, 1) => {
+                if q == 0 {
+                    // ANCHOR 0x01 0x11 0x21 0x31 | LD rp, d16 | [- - - -] | 3 | 12
+                    byte = self.get_next_byte16(bus);
+                    self.set_rp(p, byte);
+                } else {
+                    // ANCHOR 0x09 0x19 0x29 0x39 | ADD HL, rp | [- 0 H C] | 1 | 8
+                    byte = self.add16(self.registers.get_hl(), self.get_rp(p), false);
+                    self.registers.set_hl(byte);
+                    self.tick(bus);
+                }
+            }
+            // ANCHOR 0x02 0x12 0x22 0x32 | LD (rp), A | [- - - -] | 1 | 8
+            // ANCHOR 0x0A 0x1A 0x2A 0x3A | LD A, (rp) | [- - - -] | 1 | 8
+            (0, 2) => {
+                byte = match p {
+                    0 => self.registers.get_bc(),
+                    1 => self.registers.get_de(),
+                    _ => self.registers.get_hl(),
+                };
+                match p {
+                    2 => self.registers.set_hl(byte.wrapping_add(1)),
+                    3 => self.registers.set_hl(byte.wrapping_sub(1)),
+                    _ => {}
                 }
-                self.cycle = 8;
-            }
-            0xE6 => {
-                self.registers.a &= self.get_next_byte8();
-                self.registers.reset_flags();
-                self.registers.set_h();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
+                if q == 0 {
+                    self.write8(bus, byte, self.registers.a);
+                } else {
+                    self.registers.a = self.read8(bus, byte);
                 }
-                self.cycle = 8;
             }
-
-            //OR
-            0xB7 => {
-                self.registers.a |= self.registers.a;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
+            // ANCHOR 0x03 0x13 0x23 0x33 | INC rp | [- - - -] | 1 | 8
+            // ANCHOR 0x0B 0x1B 0x2B 0x3B | DEC rp | [- - - -] | 1 | 8
+            (0, 3) => {
+                byte = match q {
+                    0 => self.get_rp(p).wrapping_add(1),
+                    _ => self.get_rp(p).wrapping_sub(1),
+                };
+                self.set_rp(p, byte);
+                self.tick(bus);
             }
-            0xB0 => {
-                self.registers.a |= self.registers.b;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
+            // ANCHOR 0x04 0x0C .. 0x3C | INC r | [Z 0 H -] | 1 | 4 (12 for (HL))
+            (0, 4) => {
+                let carry = self.registers.is_set_c();
+                byte8 = self.get_operand8(bus, y);
+                byte8 = self.add8(byte8, 0x01, false);
+                self.set_operand8(bus, y, byte8);
+                self.restore_c(carry);
             }
-            0xB1 => {
-                self.registers.a |= self.registers.c;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
+            // ANCHOR 0x05 0x0D .. 0x3D | DEC r | [Z 1 H -] | 1 | 4 (12 for (HL))
+            (0, 5) => {
+                let carry = self.registers.is_set_c();
+                byte8 = self.get_operand8(bus, y);
+                byte8 = self.sub8(byte8, 0x01, false);
+                self.set_operand8(bus, y, byte8);
+                self.restore_c(carry);
+            }
+            // ANCHOR 0x06 0x0E .. 0x3E | LD r, d8 | [- - - -] | 2 | 8 (12 for (HL))
+            (0, 6) => {
+                byte8 = self.get_next_byte8(bus);
+                self.set_operand8(bus, y, byte8);
+            }
+            (0, _) => match y {
+                // ANCHOR 0x07 | RLCA | [0 0 0 C] | 1 | 4
+                0 => {
+                    self.registers.a = self.rlc(self.registers.a);
+                    self.registers.reset_z();
                 }
-                self.cycle = 4;
-            }
-            0xB2 => {
-                self.registers.a |= self.registers.d;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
+                // ANCHOR 0x0F | RRCA | [0 0 0 C] | 1 | 4
+                1 => {
+                    self.registers.a = self.rrc(self.registers.a);
+                    self.registers.reset_z();
                 }
-                self.cycle = 4;
-            }
-            0xB3 => {
-                self.registers.a |= self.registers.e;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
+                // ANCHOR 0x17 | RLA | [0 0 0 C] | 1 | 4
+                2 => {
+                    self.registers.a = self.rl(self.registers.a);
+                    self.registers.reset_z();
                 }
-                self.cycle = 4;
-            }
-            0xB4 => {
-                self.registers.a |= self.registers.h;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
+                // ANCHOR 0x1F | RRA | [0 0 0 C] | 1 | 4
+                3 => {
+                    self.registers.a = self.rr(self.registers.a);
+                    self.registers.reset_z();
                 }
-                self.cycle = 4;
-            }
-            0xB5 => {
-                self.registers.a |= self.registers.l;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
+                // ANCHOR 0x27 | DAA | [Z - 0 C] | 1 | 4
+                4 => self.registers.a = self.daa(self.registers.a),
+                // ANCHOR 0x2F | CPL | [- 1 1 -] | 1 | 4
+                5 => {
+                    self.registers.a = !self.registers.a;
+                    self.registers.set_n();
+                    self.registers.set_h();
                 }
-                self.cycle = 4;
-            }
-            0xB6 => {
-                self.registers.a |= self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
+                // ANCHOR 0x37 | SCF | [- 0 0 1] | 1 | 4
+                6 => {
+                    self.registers.set_c();
+                    self.registers.reset_n();
+                    self.registers.reset_h();
                 }
-                self.cycle = 8;
-            }
-            0xF6 => {
-                byte8 = self.get_next_byte8();
-                self.registers.a |= byte8;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
+                // ANCHOR 0x3F | CCF | [- 0 0 C] | 1 | 4
+                _ => {
+                    let carry = self.registers.is_set_c();
+                    self.restore_c(!carry);
+                    self.registers.reset_n();
+                    self.registers.reset_h();
                 }
-                self.cycle = 8;
-            }
+            },
+            // !SECTION
 
-            //XOR
-            0xAF => {
-                self.registers.a ^= self.registers.a;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
-            }
-            0xA8 => {
-                self.registers.a ^= self.registers.b;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
-            }
-            0xA9 => {
-                self.registers.a ^= self.registers.c;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
-            }
-            0xAA => {
-                self.registers.a ^= self.registers.d;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
-            }
-            0xAB => {
-                self.registers.a ^= self.registers.e;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
-            }
-            0xAC => {
-                self.registers.a ^= self.registers.h;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
-            }
-            0xAD => {
-                self.registers.a ^= self.registers.l;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 4;
-            }
-            0xAE => {
-                self.registers.a ^= self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
+            // SECTION x = 1
+            // ANCHOR 0x76 | HALT | [- - - -] | 1 | 4
+            (1, 6) if y == 6 => {
+                if !self.ime && bus.interrupts().pending().is_some() {
+                    self.halt_bug = true;
+                } else {
+                    self.state = CPUState::Halt;
                 }
-                self.cycle = 8;
+                return Ok(());
             }
-            0xEE => {
-                byte8 = self.get_next_byte8();
-                self.registers.a ^= byte8;
-                self.registers.reset_flags();
-                if self.registers.a == 0x0 {
-                    self.registers.set_z();
-                }
-                self.cycle = 8;
+            // ANCHOR 0x40 .. 0x7F | LD r, r' | [- - - -] | 1 | 4 (8 for (HL))
+            (1, _) => {
+                byte8 = self.get_operand8(bus, z);
+                self.set_operand8(bus, y, byte8);
             }
+            // !SECTION
 
-            //CP
-            0xBF => {
-                self.sub8(self.registers.a, self.registers.a, false);
-                self.cycle = 4;
-            }
-            0xB8 => {
-                self.sub8(self.registers.a, self.registers.b, false);
-                self.cycle = 4;
-            }
-            0xB9 => {
-                self.sub8(self.registers.a, self.registers.c, false);
-                self.cycle = 4;
-            }
-            0xBA => {
-                self.sub8(self.registers.a, self.registers.d, false);
-                self.cycle = 4;
-            }
-            0xBB => {
-                self.sub8(self.registers.a, self.registers.e, false);
-                self.cycle = 4;
-            }
-            0xBC => {
-                self.sub8(self.registers.a, self.registers.h, false);
-                self.cycle = 4;
-            }
-            0xBD => {
-                self.sub8(self.registers.a, self.registers.l, false);
-                self.cycle = 4;
-            }
-            0xBE => {
-                byte8 = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                self.sub8(self.registers.a, byte8, false);
-                self.cycle = 8;
-            }
-            0xFE => {
-                byte8 = self.get_next_byte8();
-                self.sub8(self.registers.a, byte8 as u8, false);
-                self.cycle = 8;
+            // SECTION x = 2
+            // ANCHOR 0x80 .. 0xBF | ALU A, r | [Z N H C] | 1 | 4 (8 for (HL))
+            (2, _) => {
+                byte8 = self.get_operand8(bus, z);
+                self.alu8(y, byte8);
             }
+            // !SECTION
 
-            //INC
-            // INC A [Z 0 H -]
-            0x3C => {
-                flag = self.registers.is_set_c();
-                self.registers.a = self.add8(self.registers.a, 0x01, false);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
+            // SECTION x = 3
+            (3, 0) => match y {
+                // ANCHOR 0xC0 0xC8 0xD0 0xD8 | RET cc | [- - - -] | 1 | 20/8
+                0..=3 => {
+                    self.tick(bus);
+                    if self.condition(y) {
+                        self.registers.pc = self.pop(bus);
+                        self.tick(bus);
+                    }
                 }
-                self.registers.reset_n();
-                self.cycle = 4;
-            }
-            0x04 => {
-                flag = self.registers.is_set_c();
-                self.registers.b = self.add8(self.registers.b, 0x01, false);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
+                // ANCHOR 0xE0 | LDH (a8), A | [- - - -] | 2 | 12
+                4 => {
+                    byte8 = self.get_next_byte8(bus);
+                    self.write8(bus, 0xFF00 | byte8 as u16, self.registers.a);
+                }
+                // ANCHOR 0xE8 | ADD SP, r8 | [0 0 H C] | 2 | 16
+                5 => {
+                    byte8 = self.get_next_byte8(bus);
+                    self.registers.sp = self.add_sp(self.registers.sp, byte8 as i8);
+                    self.tick(bus);
+                    self.tick(bus);
+                }
+                // ANCHOR 0xF0 | LDH A, (a8) | [- - - -] | 2 | 12
+                6 => {
+                    byte8 = self.get_next_byte8(bus);
+                    self.registers.a = self.read8(bus, 0xFF00 | byte8 as u16);
+                }
+                // ANCHOR 0xF8 | LD HL, SP+r8 | [0 0 H C] | 2 | 12
+                _ => {
+                    byte8 = self.get_next_byte8(bus);
+                    byte = self.add_sp(self.registers.sp, byte8 as i8);
+                    self.registers.set_hl(byte);
+                    self.tick(bus);
                 }
-                self.registers.reset_n();
-                self.cycle = 4;
-            }
-            0x0C => {
-                flag = self.registers.is_set_c();
-                self.registers.c = self.add8(self.registers.c, 0x01, false);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
+            },
+            (3, 1) => match (q, p) {
+                // ANCHOR 0xC1 0xD1 0xE1 0xF1 | POP rp2 | [- - - -] | 1 | 12
+                (0, _) => {
+                    byte = self.pop(bus);
+                    self.set_rp2(p, byte);
+                }
+                // ANCHOR 0xC9 | RET | [- - - -] | 1 | 16
+                (_, 0) => {
+                    self.registers.pc = self.pop(bus);
+                    self.tick(bus);
+                }
+                // ANCHOR 0xD9 | RETI | [- - - -] | 1 | 16
+                (_, 1) => {
+                    self.registers.pc = self.pop(bus);
+                    self.ime = true;
+                    self.tick(bus);
+                }
+                // ANCHOR 0xE9 | JP (HL) | [- - - -] | 1 | 4
+                (_, 2) => self.registers.pc = self.registers.get_hl(),
+                // ANCHOR 0xF9 | LD SP, HL | [- - - -] | 1 | 8
+                _ => {
+                    self.registers.sp = self.registers.get_hl();
+                    self.tick(bus);
                 }
-                self.registers.reset_n();
-                self.cycle = 4;
-            }
-            0x14 => {
-                flag = self.registers.is_set_c();
-                self.registers.d = self.add8(self.registers.d, 0x01, false);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
+            },
+            (3, 2) => match y {
+                // ANCHOR 0xC2 0xCA 0xD2 0xDA | JP cc, a16 | [- - - -] | 3 | 16/12
+                0..=3 => {
+                    byte = self.get_next_byte16(bus);
+                    if self.condition(y) {
+                        self.registers.pc = byte;
+                        self.tick(bus);
+                    }
                 }
-                self.registers.reset_n();
-                self.cycle = 4;
-            }
-            0x1C => {
-                flag = self.registers.is_set_c();
-                self.registers.e = self.add8(self.registers.e, 0x01, false);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
+                // ANCHOR 0xE2 | LD (C), A | [- - - -] | 1 | 8
+                4 => self.write8(bus, 0xFF00 | self.registers.c as u16, self.registers.a),
+                // ANCHOR 0xEA | LD (a16), A | [- - - -] | 3 | 16
+                5 => {
+                    byte = self.get_next_byte16(bus);
+                    self.write8(bus, byte, self.registers.a);
                 }
-                self.registers.reset_n();
-                self.cycle = 4;
-            }
-            0x24 => {
-                flag = self.registers.is_set_c();
-                self.registers.h = self.add8(self.registers.h, 0x01, false);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
+                // ANCHOR 0xF2 | LD A, (C) | [- - - -] | 1 | 8
+                6 => self.registers.a = self.read8(bus, 0xFF00 | self.registers.c as u16),
+                // ANCHOR 0xFA | LD A, (a16) | [- - - -] | 3 | 16
+                _ => {
+                    byte = self.get_next_byte16(bus);
+                    self.registers.a = self.read8(bus, byte);
                 }
-                self.registers.reset_n();
-                self.cycle = 4;
-            }
-            0x2C => {
-                flag = self.registers.is_set_c();
-                self.registers.l = self.add8(self.registers.l, 0x01, false);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
+            },
+            (3, 3) => match y {
+                // ANCHOR 0xC3 | JP a16 | [- - - -] | 3 | 16
+                0 => {
+                    self.registers.pc = self.get_next_byte16(bus);
+                    self.tick(bus);
+                }
+                // ANCHOR 0xCB | PREFIX CB
+                1 => {
+                    self.execute_cb(bus);
+                    return Ok(());
+                }
+                // ANCHOR 0xF3 | DI | [- - - -] | 1 | 4
+                6 => {
+                    self.ime = false;
+                    self.ime_scheduled = false;
+                }
+                // ANCHOR 0xFB | EI | [- - - -] | 1 | 4
+                7 => self.ime_scheduled = true,
+                // ANCHOR 0xD3 0xDB 0xE3 0xEB | Illegal
+                _ => return Err(self.lock(opcode)),
+            },
+            (3, 4) => match y {
+                // ANCHOR 0xC4 0xCC 0xD4 0xDC | CALL cc, a16 | [- - - -] | 3 | 24/12
+                0..=3 => {
+                    byte = self.get_next_byte16(bus);
+                    if self.condition(y) {
+                        self.rst(bus, byte);
+                    }
                 }
-                self.registers.reset_n();
-                self.cycle = 4;
-            }
-            0x34 => {
-                flag = self.registers.is_set_c();
-                byte8 = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                byte8 = self.add8(byte8, 0x01, false);
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(self.registers.get_hl(), byte8);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
-                }
-                self.registers.reset_n();
-                self.cycle = 12;
-            }
-            //DEC
-            0x3D => {
-                flag = self.registers.is_set_c();
-                self.registers.a = self.sub8(self.registers.a, 0x01, false);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
-                }
-                self.cycle = 4;
-            }
-            0x05 => {
-                flag = self.registers.is_set_c();
-                self.registers.b = self.sub8(self.registers.b, 0x01, false);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
-                }
-                self.cycle = 4;
-            }
-            0x0D => {
-                flag = self.registers.is_set_c();
-                self.registers.c = self.sub8(self.registers.c, 0x01, false);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
-                }
-                self.cycle = 4;
-            }
-            0x15 => {
-                flag = self.registers.is_set_c();
-                self.registers.d = self.sub8(self.registers.d, 0x01, false);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
-                }
-                self.cycle = 4;
-            }
-            0x1D => {
-                flag = self.registers.is_set_c();
-                self.registers.e = self.sub8(self.registers.e, 0x01, false);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
-                }
-                self.cycle = 4;
-            }
-            0x25 => {
-                flag = self.registers.is_set_c();
-                self.registers.h = self.sub8(self.registers.h, 0x01, false);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
-                }
-                self.cycle = 4;
-            }
-            0x2D => {
-                flag = self.registers.is_set_c();
-                self.registers.l = self.sub8(self.registers.l, 0x01, false);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
-                }
-                self.cycle = 4;
-            }
-            0x35 => {
-                flag = self.registers.is_set_c();
-                byte8 = self
-                    .memory
-                    .lock()
-                    .unwrap()
-                    .get_addr(self.registers.get_hl());
-                byte8 = self.sub8(byte8, 0x01, false);
-                self.memory
-                    .lock()
-                    .unwrap()
-                    .set_addr(self.registers.get_hl(), byte8);
-                if flag {
-                    self.registers.set_c();
-                } else {
-                    self.registers.reset_c();
-                }
-                self.cycle = 12;
-            }
-
-            //16 BIT ALU
-
-            //ADD HL
-            0x09 => {
-                flag = self.registers.is_set_z();
-                byte = self.add16(self.registers.get_hl(), self.registers.get_bc(), false);
-                self.registers.set_hl(byte);
-                if flag {
-                    self.registers.set_z();
-                } else {
-                    self.registers.reset_z();
-                }
-                self.cycle = 8;
-            }
-            0x19 => {
-                flag = self.registers.is_set_z();
-                byte = self.add16(self.registers.get_hl(), self.registers.get_de(), false);
-                self.registers.set_hl(byte);
-                if flag {
-                    self.registers.set_z();
-                } else {
-                    self.registers.reset_z();
-                }
-                self.cycle = 8;
-            }
-            0x29 => {
-                flag = self.registers.is_set_z();
-                byte = self.add16(self.registers.get_hl(), self.registers.get_hl(), false);
-                self.registers.set_hl(byte);
-                if flag {
-                    self.registers.set_z();
-                } else {
-                    self.registers.reset_z();
-                }
-                self.cycle = 8;
-            }
-            0x39 => {
-                flag = self.registers.is_set_z();
-                byte = self.add16(self.registers.get_hl(), self.registers.sp, false);
-                self.registers.set_hl(byte);
-                if flag {
-                    self.registers.set_z();
-                } else {
-                    self.registers.reset_z();
-                }
-                self.cycle = 8;
-            }
-
-            //ADD SP
-            0xE8 => {
-                byte8 = self.get_next_byte8();
-                self.registers.sp = self.add16(self.registers.sp, byte8 as u16, false);
-                self.registers.reset_z();
-                self.registers.reset_n();
-                self.cycle = 16;
-            }
-
-            //INC
-            0x03 => {
-                byte8 = self.registers.f;
-                byte = self.add16(self.registers.get_bc(), 0x0001, false);
-                self.registers.set_bc(byte);
-                self.registers.f = byte8;
-                self.cycle = 8;
-            }
-            0x13 => {
-                byte8 = self.registers.f;
-                byte = self.add16(self.registers.get_de(), 0x0001, false);
-                self.registers.set_de(byte);
-                self.registers.f = byte8;
-                self.cycle = 8;
-            }
-            0x23 => {
-                byte8 = self.registers.f;
-                byte = self.add16(self.registers.get_hl(), 0x0001, false);
-                self.registers.set_hl(byte);
-                self.registers.f = byte8;
-                self.cycle = 8;
-            }
-            0x33 => {
-                byte8 = self.registers.f;
-                byte = self.add16(self.registers.sp, 0x0001, false);
-                self.registers.sp = byte;
-                self.registers.f = byte8;
-                self.cycle = 8;
-            }
-
-            //DEC
-            0x0B => {
-                byte8 = self.registers.f;
-                byte = self.sub16(self.registers.get_bc(), 0x0001, false);
-                self.registers.set_bc(byte);
-                self.registers.f = byte8;
-                self.cycle = 8;
-            }
-            0x1B => {
-                byte8 = self.registers.f;
-                byte = self.sub16(self.registers.get_de(), 0x0001, false);
-                self.registers.set_de(byte);
-                self.registers.f = byte8;
-                self.cycle = 8;
-            }
-            0x2B => {
-                byte8 = self.registers.f;
-                byte = self.sub16(self.registers.get_hl(), 0x0001, false);
-                self.registers.set_hl(byte);
-                self.registers.f = byte8;
-                self.cycle = 8;
-            }
-            0x3B => {
-                byte8 = self.registers.f;
-                byte = self.sub16(self.registers.sp, 0x0001, false);
-                self.registers.sp = byte;
-                self.registers.f = byte8;
-                self.cycle = 8;
-            }
-
-            //MISCELLANEOUS
-            //SWAP
-            0xCB => {
-                opcode_cb = self.get_next_byte8();
-                match opcode_cb {
-                    //RL C [] (check)
-                    0x11 => {
-                        self.registers.reset_flags();
-                        self.registers.c = self.registers.c.rotate_left(1);
-                        if self.registers.c == 0x0 {
-                            self.registers.set_z();
-                        }
-                        if self.registers.c & 0x1 == 0x1 {
-                            self.registers.set_c();
-                        }
-                        self.cycle = 8;
-                    }
-
-                    0x37 => {
-                        self.registers.reset_flags();
-                        self.registers.a =
-                            ((self.registers.a & 0x0f) << 4) | ((self.registers.a & 0xf0) >> 4);
-                        if self.registers.a == 0x0 {
-                            self.registers.set_z();
-                        }
-                        self.cycle = 8;
-                    }
-                    0x30 => {
-                        self.registers.reset_flags();
-                        self.registers.b =
-                            ((self.registers.b & 0x0f) << 4) | ((self.registers.b & 0xf0) >> 4);
-                        if self.registers.b == 0x0 {
-                            self.registers.set_z();
-                        }
-                        self.cycle = 8;
-                    }
-                    0x31 => {
-                        self.registers.reset_flags();
-                        self.registers.c =
-                            ((self.registers.c & 0x0f) << 4) | ((self.registers.c & 0xf0) >> 4);
-                        if self.registers.c == 0x0 {
-                            self.registers.set_z();
-                        }
-                        self.cycle = 8;
-                    }
-                    0x32 => {
-                        self.registers.reset_flags();
-                        self.registers.d =
-                            ((self.registers.d & 0x0f) << 4) | ((self.registers.d & 0xf0) >> 4);
-                        if self.registers.d == 0x0 {
-                            self.registers.set_z();
-                        }
-                        self.cycle = 8;
-                    }
-                    0x33 => {
-                        self.registers.reset_flags();
-                        self.registers.e =
-                            ((self.registers.e & 0x0f) << 4) | ((self.registers.e & 0xf0) >> 4);
-                        if self.registers.e == 0x0 {
-                            self.registers.set_z();
-                        }
-                        self.cycle = 8;
-                    }
-                    0x34 => {
-                        self.registers.reset_flags();
-                        self.registers.h =
-                            ((self.registers.h & 0x0f) << 4) | ((self.registers.h & 0xf0) >> 4);
-                        if self.registers.h == 0x0 {
-                            self.registers.set_z();
-                        }
-                        self.cycle = 8;
-                    }
-                    0x35 => {
-                        self.registers.reset_flags();
-                        self.registers.l =
-                            ((self.registers.l & 0x0f) << 4) | ((self.registers.l & 0xf0) >> 4);
-                        if self.registers.l == 0x0 {
-                            self.registers.set_z();
-                        }
-                        self.cycle = 8;
-                    }
-                    //CHECK
-                    0x36 => {
-                        self.registers.reset_flags();
-                        byte = self.registers.get_hl();
-                        byte8 = self.memory.lock().unwrap().get_addr(byte);
-                        byte8 = ((byte8 & 0x0f) << 4) | ((byte8 & 0xf0) >> 4);
-                        self.memory.lock().unwrap().set_addr(byte, byte8);
-                        if byte8 == 0 {
-                            self.registers.set_z();
-                        }
-                        self.cycle = 16;
-                    }
-                    //BIT 7 H [- 1 0 CP]
-                    0x7C => {
-                        if (self.registers.h >> 7) == 0x0 {
-                            self.registers.set_z();
-                        } else {
-                            self.registers.reset_z();
-                        }
-                        self.registers.set_h();
-                        self.registers.reset_n();
-                        self.cycle = 8;
-                    }
-                    _ => panic!("Opcode missing in CPU CB {:#0x?}", opcode_cb),
-                };
-            }
-            //DAA
-            0x27 => {
-                self.registers.a = self.daa(self.registers.a);
-                self.registers.reset_h();
-                self.cycle = 4;
-            }
-            //CPL
-            0x2F => {
-                self.registers.a = !self.registers.a;
-                self.registers.set_n();
-                self.registers.set_h();
-                self.cycle = 4;
-            }
-            //CCF
-            0x3F => {
-                if self.registers.is_set_c() {
-                    self.registers.reset_c();
-                } else {
-                    self.registers.set_c();
-                }
-                self.registers.reset_n();
-                self.registers.reset_h();
-                self.cycle = 4;
-            }
-            //SCF
-            0x37 => {
-                self.registers.set_c();
-                self.registers.reset_n();
-                self.registers.reset_h();
-                self.cycle = 4;
-            }
-            //NOP (there just for formality)
-            0x00 => {
-                self.cycle = 4;
-            }
-            //HALT
-            0x76 => {
-                self.state = CPUState::Halt;
-                //self.cycle = 4;
-            }
-            //JP NN (check)
-            0xC3 => {
-                byte = self.get_next_byte16();
-                self.registers.pc = byte;
-                self.cycle = 16;
-            }
-            //JR NZ i8 (check)
-            0x20 => {
-                let flag = self.registers.get_flags();
-                let byte = self.get_next_byte8() as i8;
-                if !self.registers.is_set_z() {
-                    if byte.is_negative() {
-                        self.registers.pc = self.sub16(self.registers.pc, byte.abs() as u16, false)
-                    } else {
-                        self.registers.pc = self.add16(self.registers.pc, byte as u16, false);
-                    }
-                    self.registers.set_flags(flag);
-                    self.cycle = 12;
-                } else {
-                    self.cycle = 8;
-                }
-            }
-            //JR Z,i8 [- - - -]
-            0x28 => {
-                let flag = self.registers.get_flags();
-                let byte = self.get_next_byte8() as i8;
-                if self.registers.is_set_z() {
-                    if byte.is_negative() {
-                        self.registers.pc = self.sub16(self.registers.pc, byte.abs() as u16, false)
-                    } else {
-                        self.registers.pc = self.add16(self.registers.pc, byte as u16, false);
-                    }
-                    self.registers.set_flags(flag);
-                    self.cycle = 12;
-                } else {
-                    self.cycle = 8;
-                }
-            }
-            // JR i8 [- - - -]
-            0x18 => {
-                let flag = self.registers.get_flags();
-                let byte8 = self.get_next_byte8() as i8;
-                if byte8.is_negative() {
-                    self.registers.pc = self.sub16(self.registers.pc, byte8.abs() as u16, false);
-                } else {
-                    self.registers.pc = self.add16(self.registers.pc, byte8 as u16, false);
-                }
-                self.registers.set_flags(flag);
-                self.cycle = 12;
-            }
-            // SECTION CALL Instructions
-            // ANCHOR CALL a16 | [- - - -] | 3 | 24
-            0xCD => {
-                byte = self.get_next_byte16();
-                self.push(self.registers.pc);
-                self.registers.pc = byte;
-                self.cycle = 24;
-            }
-            // ANCHOR CALL NZ, a16 | [- - - -] | 3 | 24/12
-            0xC4 => {
-                byte = self.get_next_byte16();
-                if !self.registers.is_set_z() {
-                    self.push(self.registers.pc);
-                    self.registers.pc = byte;
-                    self.cycle = 24;
-                } else {
-                    self.cycle = 12;
-                }
-            }
-            // ANCHOR CALL Z, a16 | [- - - -] | 3 | 24/12
-            0xCC => {
-                byte = self.get_next_byte16();
-                if self.registers.is_set_z() {
-                    self.push(self.registers.pc);
-                    self.registers.pc = byte;
-                    self.cycle = 24;
-                } else {
-                    self.cycle = 12;
-                }
-            }
-            // ANCHOR CALL NC, a16 | [- - - -] | 3 | 24/12
-            0xD4 => {
-                byte = self.get_next_byte16();
-                if !self.registers.is_set_c() {
-                    self.push(self.registers.pc);
-                    self.registers.pc = byte;
-                    self.cycle = 24;
-                } else {
-                    self.cycle = 12;
-                }
-            }
-            // ANCHOR CALL C, a16 | [- - - -] | 3 | 24/12
-            0xDC => {
-                byte = self.get_next_byte16();
-                if self.registers.is_set_c() {
-                    self.push(self.registers.pc);
-                    self.registers.pc = byte;
-                    self.cycle = 24;
-                } else {
-                    self.cycle = 12;
-                }
-            }
-            // !SECTION
-            //RLA
-            0x17 => {
-                byte8 = self.registers.get_flags();
-                self.registers.set_flags(0x0);
-                if self.registers.a >> 7 == 0x1 {
-                    self.registers.set_c();
-                }
-                self.registers.a = self.registers.a << 1 | (byte8 & 0b00010000) >> 4;
-                self.cycle = 4;
-            }
-            // SECTION Return Instructions
-            // ANCHOR RET | [- - - -] | 1 | 16
-            0xC9 => {
-                byte = self.pop();
-                self.registers.pc = byte;
-                self.cycle = 16;
-            }
-            // ANCHOR RET NZ | [- - - -] | 1 | 20/8
-            0xC0 => {
-                if !self.registers.is_set_z() {
-                    byte = self.pop();
-                    self.registers.pc = byte;
-                    self.cycle = 20
-                } else {
-                    self.cycle = 8;
-                }
-            }
-            // ANCHOR RET Z | [- - - -] | 1 | 20/8
-            0xC8 => {
-                if self.registers.is_set_z() {
-                    byte = self.pop();
-                    self.registers.pc = byte;
-                    self.cycle = 20
-                } else {
-                    self.cycle = 8;
-                }
-            }
-            // ANCHOR RET NC | [- - - -] | 1 | 20/8
-            0xD0 => {
-                if !self.registers.is_set_c() {
-                    byte = self.pop();
-                    self.registers.pc = byte;
-                    self.cycle = 20
-                } else {
-                    self.cycle = 8;
-                }
-            }
-            // ANCHOR RET C | [- - - -] | 1 | 20/8
-            0xD8 => {
-                if self.registers.is_set_c() {
-                    byte = self.pop();
-                    self.registers.pc = byte;
-                    self.cycle = 20
-                } else {
-                    self.cycle = 8;
-                }
-            }
-            // ANCHOR RETI | [- - - -] | 1 | 16
-            0xD9 => {
-                byte = self.pop();
-                self.registers.pc = byte;
-                *self.ime.lock().unwrap() = true;
-                self.cycle = 16;
-            }
-            // !SECTION
-
-            // SECTION Reset
-            // ANCHOR RST 00H | [- - - -] | 1 | 16
-            0xC7 => {
-                self.rst(0x00);
-                self.cycle = 16;
-            }
-            // ANCHOR RST 08H | [- - - -] | 1 | 16
-            0xCF => {
-                self.rst(0x08);
-                self.cycle = 16;
-            }
-            // ANCHOR RST 10H | [- - - -] | 1 | 16
-            0xD7 => {
-                self.rst(0x10);
-                self.cycle = 16;
-            }
-            // ANCHOR RST 18H | [- - - -] | 1 | 16
-            0xDF => {
-                self.rst(0x18);
-                self.cycle = 16;
-            }
-            // ANCHOR RST 20H | [- - - -] | 1 | 16
-            0xE7 => {
-                self.rst(0x20);
-                self.cycle = 16;
-            }
-            // ANCHOR RST 28H | [- - - -] | 1 | 16
-            0xEF => {
-                self.rst(0x28);
-                self.cycle = 16;
-            }
-            // ANCHOR RST 30H | [- - - -] | 1 | 16
-            0xF7 => {
-                self.rst(0x30);
-                self.cycle = 16;
-            }
-            // ANCHOR RST 38H | [- - - -] | 1 | 16
-            0xFF => {
-                self.rst(0x38);
-                self.cycle = 16;
+                // ANCHOR 0xE4 0xEC 0xF4 0xFC | Illegal
+                _ => return Err(self.lock(opcode)),
+            },
+            (3, 5) => match (q, p) {
+                // ANCHOR 0xC5 0xD5 0xE5 0xF5 | PUSH rp2 | [- - - -] | 1 | 16
+                (0, _) => {
+                    self.tick(bus);
+                    self.push(bus, self.get_rp2(p));
+                }
+                // ANCHOR 0xCD | CALL a16 | [- - - -] | 3 | 24
+                (_, 0) => {
+                    byte = self.get_next_byte16(bus);
+                    self.rst(bus, byte);
+                }
+                // ANCHOR 0xDD 0xED 0xFD | Illegal
+                _ => return Err(self.lock(opcode)),
+            },
+            // ANCHOR 0xC6 0xCE .. 0xFE | ALU A, d8 | [Z N H C] | 2 | 8
+            (3, 6) => {
+                byte8 = self.get_next_byte8(bus);
+                self.alu8(y, byte8);
             }
+            // ANCHOR 0xC7 0xCF .. 0xFF | RST y * 8 | [- - - -] | 1 | 16
+            _ => self.rst(bus, (y as u16) << 3),
             // !SECTION
-            _ => (panic!("Opcode missing in CPU : {:#0x?}", opcode)),
         }
         // !SECTION
+        Ok(())
     }
-    //[Z 0 H C]
-
-    fn print_flags(&self) {
-        println!(
-            "Z:{} N:{} H:{} C:{}",
-            self.registers.get_z(),
-            self.registers.get_n(),
-            self.registers.get_h(),
-            self.registers.get_c()
-        );
+    // SECTION CB Prefixed Instructions
+    // x = 0 : rotate/shift[y] r[z] | x = 1 : BIT y, r[z]
+    // x = 2 : RES y, r[z]          | x = 3 : SET y, r[z]
+    fn execute_cb<B: Bus>(&mut self, bus: &mut B) {
+        let opcode_cb: u8 = self.get_next_byte8(bus);
+        let x = opcode_cb >> 6;
+        let y = (opcode_cb >> 3) & 0x07;
+        let z = opcode_cb & 0x07;
+        let mut byte8: u8 = self.get_operand8(bus, z);
+        match x {
+            0 => {
+                byte8 = match y {
+                    // ANCHOR RLC r | [Z 0 0 C]
+                    0 => self.rlc(byte8),
+                    // ANCHOR RRC r | [Z 0 0 C]
+                    1 => self.rrc(byte8),
+                    // ANCHOR RL r | [Z 0 0 C]
+                    2 => self.rl(byte8),
+                    // ANCHOR RR r | [Z 0 0 C]
+                    3 => self.rr(byte8),
+                    // ANCHOR SLA r | [Z 0 0 C]
+                    4 => self.sla(byte8),
+                    // ANCHOR SRA r | [Z 0 0 C]
+                    5 => self.sra(byte8),
+                    // ANCHOR SWAP r | [Z 0 0 0]
+                    6 => self.swap(byte8),
+                    // ANCHOR SRL r | [Z 0 0 C]
+                    _ => self.srl(byte8),
+                };
+                self.set_operand8(bus, z, byte8);
+            }
+            // ANCHOR BIT b, r | [Z 0 1 -]
+            1 => self.bit(y, byte8),
+            // ANCHOR RES b, r | [- - - -]
+            2 => self.set_operand8(bus, z, byte8 & !(1 << y)),
+            // ANCHOR SET b, r | [- - - -]
+            _ => self.set_operand8(bus, z, byte8 | (1 << y)),
+        }
+    }
+    // !SECTION
+    // SECTION Interrupt Dispatch
+    // With IME set, the highest priority interrupt that is both requested (IF) and enabled (IE)
+    // clears IME and its IF bit, then PC is pushed and replaced by the vector : 5 M-cycles
+    fn service_interrupt<B: Bus>(&mut self, bus: &mut B) -> bool {
+        if !self.ime {
+            return false;
+        }
+        match bus.interrupts().pending() {
+            Some(interrupt) => {
+                self.ime = false;
+                bus.interrupts().acknowledge(interrupt);
+                // Two wait states, the push and a final cycle to load the vector into PC
+                self.tick(bus);
+                self.rst(bus, interrupt.get_rst_addr());
+                self.tick(bus);
+                true
+            }
+            None => false,
+        }
+    }
+    // !SECTION
+    // The SM83 hangs on illegal opcodes; PC is left pointing at the offending opcode
+    fn lock(&mut self, opcode: u8) -> GumBoiError {
+        self.registers.pc = self.registers.pc.wrapping_sub(1);
+        self.state = CPUState::Lock;
+        GumBoiError::IllegalOpcode {
+            pc: self.registers.pc,
+            opcode,
+        }
     }
+
+    #[cfg(test)]
     pub fn get_registers(&self) -> Registers {
         self.registers
     }
@@ -1766,190 +497,309 @@ impl CPU {
     pub fn get_state(&self) -> CPUState {
         self.state
     }
-    pub fn rst(&mut self, addr: u16) {
-        self.push(self.registers.pc);
+    #[cfg(test)]
+    pub fn is_set_ime(&self) -> bool {
+        self.ime
+    }
+    // Internal delay, then PC is pushed and replaced by addr
+    pub fn rst<B: Bus>(&mut self, bus: &mut B, addr: u16) {
+        self.tick(bus);
+        self.push(bus, self.registers.pc);
         self.registers.pc = addr;
     }
+    // SECTION Operand Decoding
+    // r : B C D E H L (HL) A
+    fn get_operand8<B: Bus>(&mut self, bus: &mut B, operand: u8) -> u8 {
+        match operand {
+            0x0 => self.registers.b,
+            0x1 => self.registers.c,
+            0x2 => self.registers.d,
+            0x3 => self.registers.e,
+            0x4 => self.registers.h,
+            0x5 => self.registers.l,
+            0x6 => self.read8(bus, self.registers.get_hl()),
+            _ => self.registers.a,
+        }
+    }
+    fn set_operand8<B: Bus>(&mut self, bus: &mut B, operand: u8, value: u8) {
+        match operand {
+            0x0 => self.registers.b = value,
+            0x1 => self.registers.c = value,
+            0x2 => self.registers.d = value,
+            0x3 => self.registers.e = value,
+            0x4 => self.registers.h = value,
+            0x5 => self.registers.l = value,
+            0x6 => self.write8(bus, self.registers.get_hl(), value),
+            _ => self.registers.a = value,
+        }
+    }
+    // rp : BC DE HL SP
+    fn get_rp(&self, operand: u8) -> u16 {
+        match operand {
+            0x0 => self.registers.get_bc(),
+            0x1 => self.registers.get_de(),
+            0x2 => self.registers.get_hl(),
+            _ => self.registers.sp,
+        }
+    }
+    fn set_rp(&mut self, operand: u8, value: u16) {
+        match operand {
+            0x0 => self.registers.set_bc(value),
+            0x1 => self.registers.set_de(value),
+            0x2 => self.registers.set_hl(value),
+            _ => self.registers.sp = value,
+        }
+    }
+    // rp2 : BC DE HL AF
+    fn get_rp2(&self, operand: u8) -> u16 {
+        match operand {
+            0x3 => self.registers.get_af(),
+            _ => self.get_rp(operand),
+        }
+    }
+    fn set_rp2(&mut self, operand: u8, value: u16) {
+        match operand {
+            // The low nibble of F is hardwired to zero
+            0x3 => self.registers.set_af(value & 0xFFF0),
+            _ => self.set_rp(operand, value),
+        }
+    }
+    // cc : NZ Z NC C
+    fn condition(&self, operand: u8) -> bool {
+        match operand {
+            0x0 => !self.registers.is_set_z(),
+            0x1 => self.registers.is_set_z(),
+            0x2 => !self.registers.is_set_c(),
+            _ => self.registers.is_set_c(),
+        }
+    }
+    // alu : ADD ADC SUB SBC AND XOR OR CP
+    fn alu8(&mut self, operation: u8, value: u8) {
+        match operation {
+            0 => self.registers.a = self.add8(self.registers.a, value, false),
+            1 => self.registers.a = self.add8(self.registers.a, value, true),
+            2 => self.registers.a = self.sub8(self.registers.a, value, false),
+            3 => self.registers.a = self.sub8(self.registers.a, value, true),
+            4 => self.registers.a = self.and8(self.registers.a, value),
+            5 => self.registers.a = self.xor8(self.registers.a, value),
+            6 => self.registers.a = self.or8(self.registers.a, value),
+            _ => {
+                self.sub8(self.registers.a, value, false);
+            }
+        }
+    }
+    // !SECTION
+    fn restore_c(&mut self, carry: bool) {
+        if carry {
+            self.registers.set_c();
+        } else {
+            self.registers.reset_c();
+        }
+    }
+    //[Z 0 0 C] : shared flag update for the rotate and shift group
+    fn set_shift_flags(&mut self, result: u8, carry: bool) -> u8 {
+        self.registers.reset_flags();
+        if result == 0x0 {
+            self.registers.set_z();
+        }
+        if carry {
+            self.registers.set_c();
+        }
+        result
+    }
 }
 // SECTION CPU ALU Trait
+#[allow(clippy::upper_case_acronyms)]
 trait ALU {
     fn add8(&mut self, a: u8, b: u8, carry: bool) -> u8;
     fn add16(&mut self, a: u16, b: u16, carry: bool) -> u16;
+    fn add_sp(&mut self, sp: u16, offset: i8) -> u16;
     fn sub8(&mut self, a: u8, b: u8, carry: bool) -> u8;
-    fn sub16(&mut self, a: u16, b: u16, carry: bool) -> u16;
     fn daa(&mut self, a: u8) -> u8;
+    fn and8(&mut self, a: u8, b: u8) -> u8;
+    fn xor8(&mut self, a: u8, b: u8) -> u8;
+    fn or8(&mut self, a: u8, b: u8) -> u8;
+    fn rlc(&mut self, a: u8) -> u8;
+    fn rrc(&mut self, a: u8) -> u8;
+    fn rl(&mut self, a: u8) -> u8;
+    fn rr(&mut self, a: u8) -> u8;
+    fn sla(&mut self, a: u8) -> u8;
+    fn sra(&mut self, a: u8) -> u8;
+    fn srl(&mut self, a: u8) -> u8;
+    fn swap(&mut self, a: u8) -> u8;
+    fn bit(&mut self, bit: u8, a: u8);
 }
 impl ALU for CPU {
+    //[Z 0 H C]
     fn add8(&mut self, a: u8, b: u8, carry: bool) -> u8 {
         let mut carry_val: u8 = 0;
-        if carry == true && (self.registers.is_set_c()) {
+        if carry && self.registers.is_set_c() {
             carry_val = 0x1;
         }
+        let result: u16 = (a as u16) + (b as u16) + (carry_val as u16);
         self.registers.reset_flags();
+        if result & 0x00ff == 0x0 {
+            self.registers.set_z();
+        }
         if (a & 0x0f) + (b & 0x0f) + carry_val > 0x0f {
             self.registers.set_h();
         }
-        match a.checked_add(b) {
-            Some(x) => match x.checked_add(carry_val) {
-                Some(x) => {
-                    if x == 0x0 {
-                        self.registers.set_z();
-                    }
-                    x
-                }
-                None => {
-                    self.registers.set_c();
-                    (((a as u16) + (carry_val as u16)) & (0x00ff)) as u8
-                }
-            },
-            None => {
-                self.registers.set_c();
-                let byte = (((a as u16) + (b as u16)) & (0x00ff)) as u8;
-                match byte {
-                    0 => {
-                        self.registers.set_z();
-                        0x0
-                    }
-                    _ => byte,
-                }
-            }
+        if result > 0x00ff {
+            self.registers.set_c();
         }
+        result as u8
     }
+    //[- 0 H C] : half carry from bit 11, carry from bit 15
     fn add16(&mut self, a: u16, b: u16, carry: bool) -> u16 {
-        let mut carry_val: u16 = 0;
-        if carry == true && (self.registers.is_set_c()) {
+        let mut carry_val: u32 = 0;
+        if carry && self.registers.is_set_c() {
             carry_val = 0x1;
         }
-        self.registers.reset_flags();
-        if (a & 0xff) + (b & 0xff) + carry_val > 0xff {
+        let result: u32 = (a as u32) + (b as u32) + carry_val;
+        self.registers.reset_n();
+        self.registers.reset_h();
+        self.registers.reset_c();
+        if (a as u32 & 0x0fff) + (b as u32 & 0x0fff) + carry_val > 0x0fff {
             self.registers.set_h();
         }
-        match a.checked_add(b) {
-            Some(x) => match x.checked_add(carry_val) {
-                Some(x) => {
-                    if x == 0x0 {
-                        self.registers.set_z();
-                    }
-                    x
-                }
-                None => {
-                    self.registers.set_c();
-                    (((a as u32) + (carry_val as u32)) & (0x00ff)) as u16
-                }
-            },
-            None => {
-                self.registers.set_c();
-                let byte = (((a as u32) + (b as u32)) & (0x00ff)) as u16;
-                match byte {
-                    0 => {
-                        self.registers.set_z();
-                        0x0
-                    }
-                    _ => byte,
-                }
-            }
+        if result > 0xffff {
+            self.registers.set_c();
         }
+        result as u16
     }
-    fn sub8(&mut self, a: u8, b: u8, carry: bool) -> u8 {
-        let mut carry_val: u8 = 0;
-        if carry == true && (self.registers.is_set_c()) {
-            carry_val = 0x1;
-        }
+    //[0 0 H C] : flags are computed on the unsigned low byte
+    fn add_sp(&mut self, sp: u16, offset: i8) -> u16 {
+        let offset = offset as u16;
         self.registers.reset_flags();
-        self.registers.set_n();
-        if a & 0x0f < (b + carry_val) & 0x0f {
+        if (sp & 0x000f) + (offset & 0x000f) > 0x000f {
             self.registers.set_h();
         }
-        if a < ((((b as u16) + (carry_val as u16)) & (0x00ff)) as u8) {
+        if (sp & 0x00ff) + (offset & 0x00ff) > 0x00ff {
             self.registers.set_c();
         }
-        let result: u8 = a.wrapping_sub(b).wrapping_sub(carry_val);
-        match result {
-            0 => {
-                self.registers.set_z();
-                0
-            }
-            _ => result,
-        }
+        sp.wrapping_add(offset)
     }
-    fn sub16(&mut self, a: u16, b: u16, carry: bool) -> u16 {
-        let mut carry_val: u16 = 0;
-        if carry == true && (self.registers.is_set_c()) {
-            println!("inside carry");
+    //[Z 1 H C]
+    fn sub8(&mut self, a: u8, b: u8, carry: bool) -> u8 {
+        let mut carry_val: u8 = 0;
+        if carry && self.registers.is_set_c() {
             carry_val = 0x1;
         }
         self.registers.reset_flags();
         self.registers.set_n();
-        if a & 0xff < (b + carry_val) & 0xff {
+        if a & 0x0f < (b & 0x0f) + carry_val {
             self.registers.set_h();
-            println!("h set");
         }
-        if a < ((((b as u32) + (carry_val as u32)) & (0x00ff)) as u16) {
+        if (a as u16) < (b as u16) + (carry_val as u16) {
             self.registers.set_c();
         }
-        let result: u16 = a.wrapping_sub(b).wrapping_sub(carry_val);
-        match result {
-            0 => {
-                self.registers.set_z();
-                0
-            }
-            _ => result,
+        let result: u8 = a.wrapping_sub(b).wrapping_sub(carry_val);
+        if result == 0x0 {
+            self.registers.set_z();
         }
+        result
     }
+    //[Z - 0 C]
     fn daa(&mut self, a: u8) -> u8 {
-        let mut byte_ms: u8 = a >> 4;
-        let mut byte_ls: u8 = a & 0x0f;
-        if (byte_ls > 9) | self.registers.is_set_h() {
-            byte_ls += 0x06;
-            if byte_ls & 0x10 == 0x10 {
-                byte_ls = byte_ls & 0x0f;
-                byte_ms += 0x01;
-                if byte_ms & 0x10 == 0x10 {
-                    byte_ms = byte_ms & 0x0f;
-                    self.registers.set_c();
-                }
-            }
+        let mut correction: u8 = 0x0;
+        let mut carry = self.registers.is_set_c();
+        let subtract = self.registers.is_set_n();
+        if self.registers.is_set_h() || (!subtract && a & 0x0f > 0x09) {
+            correction |= 0x06;
+        }
+        if carry || (!subtract && a > 0x99) {
+            correction |= 0x60;
+            carry = true;
+        }
+        let byte = if subtract {
+            a.wrapping_sub(correction)
+        } else {
+            a.wrapping_add(correction)
+        };
+        self.registers.reset_z();
+        self.registers.reset_h();
+        self.registers.reset_c();
+        if byte == 0x0 {
+            self.registers.set_z();
         }
-        if (byte_ms > 9) | self.registers.is_set_c() {
-            byte_ms += 0x06;
+        if carry {
             self.registers.set_c();
         }
-        let byte = byte_ms << 4 | byte_ls;
-        match byte {
-            0 => {
-                self.registers.set_z();
-                0x0
-            }
-            _ => byte,
+        byte
+    }
+    //[Z 0 1 0]
+    fn and8(&mut self, a: u8, b: u8) -> u8 {
+        let result = self.set_shift_flags(a & b, false);
+        self.registers.set_h();
+        result
+    }
+    //[Z 0 0 0]
+    fn xor8(&mut self, a: u8, b: u8) -> u8 {
+        self.set_shift_flags(a ^ b, false)
+    }
+    //[Z 0 0 0]
+    fn or8(&mut self, a: u8, b: u8) -> u8 {
+        self.set_shift_flags(a | b, false)
+    }
+    //[Z 0 0 C]
+    fn rlc(&mut self, a: u8) -> u8 {
+        self.set_shift_flags(a.rotate_left(1), a >> 7 == 0x1)
+    }
+    fn rrc(&mut self, a: u8) -> u8 {
+        self.set_shift_flags(a.rotate_right(1), a & 0x1 == 0x1)
+    }
+    fn rl(&mut self, a: u8) -> u8 {
+        let carry_in = self.registers.is_set_c() as u8;
+        self.set_shift_flags(a << 1 | carry_in, a >> 7 == 0x1)
+    }
+    fn rr(&mut self, a: u8) -> u8 {
+        let carry_in = self.registers.is_set_c() as u8;
+        self.set_shift_flags(a >> 1 | carry_in << 7, a & 0x1 == 0x1)
+    }
+    fn sla(&mut self, a: u8) -> u8 {
+        self.set_shift_flags(a << 1, a >> 7 == 0x1)
+    }
+    fn sra(&mut self, a: u8) -> u8 {
+        self.set_shift_flags(a >> 1 | (a & 0x80), a & 0x1 == 0x1)
+    }
+    fn srl(&mut self, a: u8) -> u8 {
+        self.set_shift_flags(a >> 1, a & 0x1 == 0x1)
+    }
+    //[Z 0 0 0]
+    fn swap(&mut self, a: u8) -> u8 {
+        self.set_shift_flags((a & 0x0f) << 4 | (a & 0xf0) >> 4, false)
+    }
+    //[Z 0 1 -]
+    fn bit(&mut self, bit: u8, a: u8) {
+        if (a >> bit) & 0x1 == 0x0 {
+            self.registers.set_z();
+        } else {
+            self.registers.reset_z();
         }
+        self.registers.reset_n();
+        self.registers.set_h();
     }
 }
 // !SECTION
 // SECTION CPU Stack Trait
 pub trait Stack {
-    fn push(&mut self, a16: u16);
-    fn pop(&mut self) -> u16;
+    fn push<B: Bus>(&mut self, bus: &mut B, a16: u16);
+    fn pop<B: Bus>(&mut self, bus: &mut B) -> u16;
 }
 
 impl Stack for CPU {
-    fn push(&mut self, a16: u16) {
-        self.registers.sp -= 1;
-        self.memory
-            .lock()
-            .unwrap()
-            .set_addr(self.registers.sp, (a16 >> 8) as u8);
-        self.registers.sp -= 1;
-        self.memory
-            .lock()
-            .unwrap()
-            .set_addr(self.registers.sp, (a16 & 0x00ff) as u8);
-    }
-    fn pop(&mut self) -> u16 {
+    fn push<B: Bus>(&mut self, bus: &mut B, a16: u16) {
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.write8(bus, self.registers.sp, (a16 >> 8) as u8);
+        self.registers.sp = self.registers.sp.wrapping_sub(1);
+        self.write8(bus, self.registers.sp, (a16 & 0x00ff) as u8);
+    }
+    fn pop<B: Bus>(&mut self, bus: &mut B) -> u16 {
         let mut byte: u16;
-        byte = self.memory.lock().unwrap().get_addr(self.registers.sp) as u16;
-        self.registers.sp += 1;
-        byte |= (self.memory.lock().unwrap().get_addr(self.registers.sp) as u16) << 8;
-        self.registers.sp += 1;
+        byte = self.read8(bus, self.registers.sp) as u16;
+        self.registers.sp = self.registers.sp.wrapping_add(1);
+        byte |= (self.read8(bus, self.registers.sp) as u16) << 8;
+        self.registers.sp = self.registers.sp.wrapping_add(1);
         byte
     }
 }
@@ -1957,6 +807,7 @@ impl Stack for CPU {
 
 // SECTION CPU Test Cases
 #[cfg(test)]
+#[allow(non_snake_case)] // Tests are named after their opcodes, as Ox3E
 mod cpu_intruction_tests {
 
     const SET_Z: u8 = 0b10000000;
@@ -1964,11 +815,6 @@ mod cpu_intruction_tests {
     const SET_H: u8 = 0b00100000;
     const SET_C: u8 = 0b00010000;
 
-    const RESET_Z: u8 = 0b01111111;
-    const RESET_N: u8 = 0b10111111;
-    const RESET_H: u8 = 0b11011111;
-    const RESET_C: u8 = 0b11101111;
-
     const EMPTY_REGISTERS: Registers = Registers {
         a: 0x0,
         b: 0x0,
@@ -1982,12 +828,15 @@ mod cpu_intruction_tests {
         pc: 0x0,
     };
 
+    use super::Bus;
     use super::CPUState;
-    use super::Memory;
+    use super::GumBoiError;
     use super::Registers;
     use super::CPU;
-    use std::convert::TryInto;
-    use std::sync::{Arc, Mutex};
+    use crate::bus::FlatBus;
+    use crate::interrupt::InterruptController;
+    use crate::memory::Memory;
+    use crate::opcodes::{OpcodeInfo, CB_OPCODES, OPCODES};
 
     // SECTION Macros
     macro_rules! registers {
@@ -2006,7 +855,7 @@ mod cpu_intruction_tests {
     macro_rules! memory {
         ($($addr:expr=>$value:expr),*) => {
             {
-                let mut mem = Memory::new();
+                let mut mem = FlatBus::new();
                 $(
                     mem.set_addr($addr, $value);
                 )*
@@ -2024,753 +873,1626 @@ mod cpu_intruction_tests {
         };
     }
     // !SECTION
-    fn get_next_state(current_state: (Registers, Memory, usize)) -> (Registers, Memory, usize) {
-        let memory = Arc::new(Mutex::new(current_state.1));
-        let memory_1 = Arc::clone(&memory);
+    // HALT marks the end of a test program, the reported cycles are those of the instruction
+    // before it
+    fn run_until_halt(cpu: &mut CPU, memory: &mut FlatBus) {
+        let mut cycle = cpu.cycle;
+        while cpu.state == CPUState::Active {
+            cpu.execute(memory).unwrap();
+            if cpu.state != CPUState::Halt {
+                cycle = cpu.cycle;
+            }
+        }
+        cpu.cycle = cycle;
+    }
+    fn get_next_state(current_state: (Registers, FlatBus, usize)) -> (Registers, FlatBus, usize) {
+        let mut memory = current_state.1;
+
+        let mut cpu = CPU {
+            registers: current_state.0,
+            cycle: current_state.2,
+            state: CPUState::Active,
+            ime: false,
+            ime_scheduled: false,
+            halt_bug: false,
+        };
+        run_until_halt(&mut cpu, &mut memory);
+        let mem = memory;
+        (cpu.get_registers(), mem, cpu.get_cycles())
+    }
+
+    // ANCHOR 0x76 | HALT | [- - - -] | 1 | 4
+    test_case![
+        Ox76 | (registers!(), memory!(0x0=>0x76), 0),
+        (registers!(pc:1), memory!(0x0=>0x76), 0)
+    ];
+    // ANCHOR 0xCB 0x11 | RL C | [Z 0 0 C] | 2 | 8
+    test_case![
+        OxCB_Ox11
+            | (
+                registers!(c: 0x85),
+                memory!(0x0=>0xCB,0x1=>0x11,0x2=>0x76),
+                0
+            ),
+        (
+            registers!(c: 0x0A,f:SET_C,pc:0x3),
+            memory!(0x0=>0xCB,0x1=>0x11,0x2=>0x76),
+            8
+        )
+    ];
+    test_case![
+        OxCB_Ox11_with_C_set
+            | (
+                registers!(c: 0x05,f:SET_C),
+                memory!(0x0=>0xCB,0x1=>0x11,0x2=>0x76),
+                0
+            ),
+        (
+            registers!(c: 0x0B,pc:0x3),
+            memory!(0x0=>0xCB,0x1=>0x11,0x2=>0x76),
+            8
+        )
+    ];
+    // ANCHOR 0x17 | RLA | [0 0 0 C] | 1 | 4
+    test_case![
+        Ox17 | (registers!(a:0x95), memory!(0x0=>0x17,0x1=>0x76), 0),
+        (
+            registers!(a:0x2A,f:SET_C,pc:2),
+            memory!(0x0=>0x17,0x1=>0x76),
+            4
+        )
+    ];
+    // ANCHOR 0x21 | LD HL, d16 | [- - - -] | 3 | 12
+    test_case![
+        Ox21 | (
+            registers!(),
+            memory!(0x0=>0x21,0x1=>0xff,0x2=>0x9f,0x3=>0x76),
+            0
+        ),
+        (
+            registers!(h:0x9F,l:0xFF,pc:4),
+            memory!(0x0=>0x21,0x1=>0xff,0x2=>0x9f,0x3=>0x76),
+            12
+        )
+    ];
+    // ANCHOR 0x32 | LD (HL-), A | 1 | 8
+    test_case![
+        Ox32 | (
+            registers!(a:0x0,h:0x1,l:0x1),
+            memory!(0x0=>0x32,0x1=>0x76),
+            0
+        ),
+        (
+            registers!(a:0x0,h:0x1,l:0x0,pc:0x2),
+            memory!(0x0=>0x32,0x1=>0x76),
+            8
+        )
+    ];
+    // ANCHOR 0x20 | JR NZ, r8 | 2 | 12/8
+    test_case![
+        Ox20_with_Z_set
+            | (
+                registers!(f: SET_Z),
+                memory!(0x0=>0x20,0x1=>0x03,0x2=>0x76,0x3=>0xFF,0x4=>0xFF),
+                0
+            ),
+        (
+            registers!(f:SET_Z,pc:3),
+            memory!(0x0=>0x20,0x1=>0x03,0x2=>0x76,0x3=>0xFF,0x4=>0xFF),
+            8
+        )
+    ];
+    test_case![
+        Ox20_with_Z_not_set
+            | (
+                registers!(),
+                memory!(0x0=>0x20,0x1=>0x03,0x2=>0xFF,0x3=>0xFF,0x4=>0xFF,0x5=>0x76),
+                0
+            ),
+        (
+            registers!(pc:6),
+            memory!(0x0=>0x20,0x1=>0x03,0x2=>0xFF,0x3=>0xFF,0x4=>0xFF,0x5=>0x76),
+            12
+        )
+    ];
+    // ANCHOR 0xCB 0x7C | BIT 7, H | [Z 0 1 -] | 2 | 8
+    test_case![
+        OxCB_Ox7C
+            | (
+                registers!(h:0x80),
+                memory!(0x0=>0xcb,0x1=>0x7c,0x2=>0x76),
+                0
+            ),
+        (
+            registers!(h:0x80,pc:3,f:SET_H),
+            memory!(0x0=>0xcb,0x1=>0x7c,0x2=>0x76),
+            8
+        )
+    ];
+    // ANCHOR 0x28 | JR Z, r8 | [- - - -] | 12/8
+    test_case![
+        Ox28_with_Z_set
+            | (
+                registers!(f: SET_Z),
+                memory!(0x0=>0x28,0x1=>0x02,0x2=>0x76,0x3=>0x76,0x4=>0x76),
+                0
+            ),
+        (
+            registers!(pc:5,f:SET_Z),
+            memory!(0x0=>0x28,0x1=>0x02,0x2=>0x76,0x3=>0x76,0x4=>0x76),
+            12
+        )
+    ];
+    test_case![
+        Ox28_with_Z_not_set
+            | (
+                registers!(),
+                memory!(0x0=>0x28,0x1=>0x02,0x2=>0x76,0x3=>0x76,0x4=>0x76),
+                0
+            ),
+        (
+            registers!(pc:3),
+            memory!(0x0=>0x28,0x1=>0x02,0x2=>0x76,0x3=>0x76,0x4=>0x76),
+            8
+        )
+    ];
+    // ANCHOR 0x18 | JR i8 | [- - - -] | 2 | 12
+    test_case![
+        Ox18_negative_jump
+            | (
+                registers!(a:0x00,pc:3),
+                memory!(0x0=>0x76,0x1=>0x3C,0x2=>0x76,0x3=>0x18,0x4=>0xFC,0x5=>0x76,0x6=>0x76),
+                0
+            ),
+        (
+            registers!(a:0x01,pc:3),
+            memory!(0x0=>0x76,0x1=>0x3C,0x2=>0x76,0x3=>0x18,0x4=>0xFC,0x5=>0x76,0x6=>0x76),
+            4
+        )
+    ];
+    test_case![
+        Ox18_positive_jump
+            | (
+                registers!(a:0x00,pc:3),
+                memory!(0x0=>0x76,0x1=>0x3C,0x2=>0x76,0x3=>0x18,0x4=>0x01,0x5=>0x76,0x6=>0x3c,0x7=>0x76),
+                0
+            ),
+        (
+            registers!(a:0x01,pc:8),
+            memory!(0x0=>0x76,0x1=>0x3C,0x2=>0x76,0x3=>0x18,0x4=>0x01,0x5=>0x76,0x6=>0x3c,0x7=>0x76),
+            4
+        )
+    ];
+    // ANCHOR 0x3C | INC A | [Z 0 H -] | 1 | 4
+    test_case![
+        Ox3C_with_overflow
+            | (
+                registers!(a:0xFF,f: SET_N | SET_C),
+                memory!(0x0=>0x3c,0x1=>0x76),
+                0
+            ),
+        (
+            registers!(a:0x00,pc:2,f:SET_H | SET_Z | SET_C),
+            memory!(0x0=>0x3c,0x1=>0x76),
+            4
+        )
+    ];
+    test_case![
+        Ox3C_without_overflow
+            | (
+                registers!(a:0x0E,f:SET_C | SET_H | SET_N | SET_Z),
+                memory!(0x0=>0x3C,0x1=>0x76),
+                0
+            ),
+        (
+            registers!(a:0x0F,f:SET_C,pc:2),
+            memory!(0x0=>0x3C,0x1=>0x76),
+            4
+        )
+    ];
+    // SECTION 8 Bit Load Operations
+    // ANCHOR 0x3E | LD A, d8 | [- - - -] | 2 | 8
+    test_case![
+        Ox3E | (registers!(), memory!(0x0=>0x3E,0x1=>0xFE,0x2=>0x76), 0),
+        (
+            registers!(a:0xFE,pc:3),
+            memory!(0x0=>0x3E,0x1=>0xFE,0x2=>0x76),
+            8
+        )
+    ];
+    // ANCHOR 0x06 | LD B, d8 | [- - - -] | 2 | 8
+    test_case![
+        Ox06 | (registers!(), memory!(0x0=>0x06,0x1=>0xFE,0x2=>0x76), 0),
+        (
+            registers!(b:0xFE,pc:3),
+            memory!(0x0=>0x06,0x1=>0xFE,0x2=>0x76),
+            8
+        )
+    ];
+    // ANCHOR 0x0E | LD E, d8 | [- - - -] | 2 | 8
+    test_case![
+        Ox0E | (registers!(), memory!(0x0=>0x0E,0x1=>0xFE,0x2=>0x76), 0),
+        (
+            registers!(c:0xFE,pc:3),
+            memory!(0x0=>0x0E,0x1=>0xFE,0x2=>0x76),
+            8
+        )
+    ];
+    // ANCHOR 0x16 | LD D, d8 | [- - - -] | 2 | 8
+    test_case![
+        Ox16 | (registers!(), memory!(0x0=>0x16,0x1=>0xFE,0x2=>0x76), 0),
+        (
+            registers!(d:0xFE,pc:3),
+            memory!(0x0=>0x16,0x1=>0xFE,0x2=>0x76),
+            8
+        )
+    ];
+    // ANCHOR 0x1E | LD E, d8 | [- - - -] | 2 | 8
+    test_case![
+        Ox1E | (registers!(), memory!(0x0=>0x1E,0x1=>0xFE,0x2=>0x76), 0),
+        (
+            registers!(e:0xFE,pc:3),
+            memory!(0x0=>0x1E,0x1=>0xFE,0x2=>0x76),
+            8
+        )
+    ];
+    // ANCHOR 0x26 | LD (HL), d8 | [- - - -] | 2 | 8
+    test_case![
+        Ox26 | (registers!(), memory!(0x0=>0x26,0x1=>0xFE,0x2=>0x76), 0),
+        (
+            registers!(h:0xFE,pc:3),
+            memory!(0x0=>0x26,0x1=>0xFE,0x2=>0x76),
+            8
+        )
+    ];
+    // ANCHOR 0x2E | LD L, d8 | [- - - -] | 2 | 8
+    test_case![
+        Ox2E | (registers!(), memory!(0x0=>0x2E,0x1=>0xFE,0x2=>0x76), 0),
+        (
+            registers!(l:0xFE,pc:3),
+            memory!(0x0=>0x2E,0x1=>0xFE,0x2=>0x76),
+            8
+        )
+    ];
+    // ANCHOR 0x36 | LD (HL), d8 | [- - - -] | 2 | 12
+    test_case![
+        Ox36 | (
+            registers!(h:0x01,l:0x00),
+            memory!(0x0=>0x36,0x1=>0xFE,0x2=>0x76),
+            0
+        ),
+        (
+            registers!(h:0x01,l:0x00,pc:3),
+            memory!(0x0=>0x36,0x1=>0xFE,0x2=>0x76,0x100=>0xFE),
+            12
+        )
+    ];
+    // !SECTION
+    // ANCHOR 0x87 | ADD A, A | [Z 0 H C] | 1 | 4
+    test_case![
+        Ox87_Zero | (registers!(a:0x0,f:SET_N), memory!(0x0=>0x87,0x1=>0x76), 0),
+        (
+            registers!(a:0x0,f:SET_Z,pc:2),
+            memory!(0x0=>0x87,0x1=>0x76),
+            4
+        )
+    ];
+    test_case![
+        Ox87_With_Half_Carry | (registers!(a:0x0f,f:SET_N), memory!(0x0=>0x87,0x1=>0x76), 0),
+        (
+            registers!(a:0x1e,f:SET_H,pc:2),
+            memory!(0x0=>0x87,0x1=>0x76),
+            4
+        )
+    ];
+    test_case![
+        Ox87_With_Carry | (registers!(a:0x80,f:SET_N), memory!(0x0=>0x87,0x1=>0x76), 0),
+        (
+            registers!(a:0x00,f:SET_Z|SET_C,pc:2),
+            memory!(0x0=>0x87,0x1=>0x76),
+            4
+        )
+    ];
+    test_case![
+        Ox87_With_Both_Carry | (registers!(a:0x88,f:SET_N), memory!(0x0=>0x87,0x1=>0x76), 0),
+        (
+            registers!(a:0x10,f:SET_H|SET_C,pc:2),
+            memory!(0x0=>0x87,0x1=>0x76),
+            4
+        )
+    ];
+    test_case![
+        Ox80_Zero
+            | (
+                registers!(a:0x0,b:0x0,f:SET_N),
+                memory!(0x0=>0x80,0x1=>0x76),
+                0
+            ),
+        (
+            registers!(a:0x0,f:SET_Z,pc:2),
+            memory!(0x0=>0x80,0x1=>0x76),
+            4
+        )
+    ];
+    test_case![
+        Ox80_With_Half_Carry
+            | (
+                registers!(a:0x0f,b:0x01,f:SET_N),
+                memory!(0x0=>0x80,0x1=>0x76),
+                0
+            ),
+        (
+            registers!(a:0x10,b:0x01,f:SET_H,pc:2),
+            memory!(0x0=>0x80,0x1=>0x76),
+            4
+        )
+    ];
+    test_case![
+        Ox80_With_Carry
+            | (
+                registers!(a:0xf0,b:0x10,f:SET_N),
+                memory!(0x0=>0x80,0x1=>0x76),
+                0
+            ),
+        (
+            registers!(a:0x00,b:0x10,f:SET_Z|SET_C,pc:2),
+            memory!(0x0=>0x80,0x1=>0x76),
+            4
+        )
+    ];
+    test_case![
+        Ox80_With_Both_Carry
+            | (
+                registers!(a:0xff,b:0x01,f:SET_N),
+                memory!(0x0=>0x80,0x1=>0x76),
+                0
+            ),
+        (
+            registers!(a:0x00,b:0x01,f:SET_H|SET_C|SET_Z,pc:2),
+            memory!(0x0=>0x80,0x1=>0x76),
+            4
+        )
+    ];
+    // SECTION Stack Operations
+    // 0xC5 | PUSH BC | [- - - -] | 1 | 16
+    test_case![
+        OxC5 | (
+            registers!(sp: 0xfffe,b:0x01,c:0x01),
+            memory!(0x0=>0xC5,0x1=>0x76),
+            0
+        ),
+        (
+            registers!(sp: 0xfffc,b:0x01,c:0x01,pc:0x02),
+            memory!(0x0=>0xC5,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x01),
+            16
+        )
+    ];
+    // 0xD5 | PUSH DE | [- - - -] | 1 | 16
+    test_case![
+        OxD5 | (
+            registers!(sp: 0xfffe,d:0x01,e:0x01),
+            memory!(0x0=>0xD5,0x1=>0x76),
+            0
+        ),
+        (
+            registers!(sp: 0xfffc,d:0x01,e:0x01,pc:0x02),
+            memory!(0x0=>0xD5,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x01),
+            16
+        )
+    ];
+    // 0xE5 | PUSH HL | [- - - -] | 1 | 16
+    test_case![
+        OxE5 | (
+            registers!(sp: 0xfffe,h:0x01,l:0x01),
+            memory!(0x0=>0xE5,0x1=>0x76),
+            0
+        ),
+        (
+            registers!(sp: 0xfffc,h:0x01,l:0x01,pc:0x02),
+            memory!(0x0=>0xE5,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x01),
+            16
+        )
+    ];
+    // 0xF5 | PUSH AF | [- - - -] | 1 | 16
+    test_case![
+        OxF5 | (
+            registers!(sp: 0xfffe,a:0x01,f:0x01),
+            memory!(0x0=>0xF5,0x1=>0x76),
+            0
+        ),
+        (
+            registers!(sp: 0xfffc,a:0x01,f:0x01,pc:0x02),
+            memory!(0x0=>0xF5,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x01),
+            16
+        )
+    ];
+    // 0xC1 | POP BC | [- - - -] | 1 | 12
+    test_case![
+        OxC1 | (
+            registers!(sp:0xFFFC),
+            memory!(0x0=>0xC1,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x02),
+            0
+        ),
+        (
+            registers!(sp:0xFFFE,b:0x02,c:0x01,pc:2),
+            memory!(0x0=>0xC1,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x02),
+            12
+        )
+    ];
+    // 0xD1 | POP DE | [- - - -] | 1 | 12
+    test_case![
+        OxD1 | (
+            registers!(sp:0xFFFC),
+            memory!(0x0=>0xD1,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x02),
+            0
+        ),
+        (
+            registers!(sp:0xFFFE,d:0x02,e:0x01,pc:2),
+            memory!(0x0=>0xD1,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x02),
+            12
+        )
+    ];
+    // 0xE1 | POP HL | [- - - -] | 1 | 12
+    test_case![
+        OxE1 | (
+            registers!(sp:0xFFFC),
+            memory!(0x0=>0xE1,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x02),
+            0
+        ),
+        (
+            registers!(sp:0xFFFE,h:0x02,l:0x01,pc:2),
+            memory!(0x0=>0xE1,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x02),
+            12
+        )
+    ];
+    // 0xF1 | POP AF | [- - - -] | 1 | 12
+    test_case![
+        OxF1 | (
+            registers!(sp:0xFFFC),
+            memory!(0x0=>0xF1,0x1=>0x76,0xFFFC=>0xF1,0xFFFD=>0x02),
+            0
+        ),
+        (
+            registers!(sp:0xFFFE,a:0x02,f:0xF0,pc:2),
+            memory!(0x0=>0xF1,0x1=>0x76,0xFFFC=>0xF1,0xFFFD=>0x02),
+            12
+        )
+    ];
+    // !SECTION
 
-        let ime = Arc::new(Mutex::new(false));
-        let mut cpu = CPU {
-            registers: current_state.0,
-            memory: memory,
-            cycle: current_state.2,
-            state: CPUState::Active,
-            ime: ime,
-        };
-        while cpu.state == CPUState::Active {
-            cpu.execute();
-        }
-        let mem = *cpu.memory.lock().unwrap();
-        (cpu.get_registers(), mem, cpu.get_cycles())
-    }
-
-    // ANCHOR 0x76 | HALT | [- - - -] | 1 | 4
+    // SECTION CALL Instructions
+    // ANCHOR 0xCD | CALL a16 | [- - - -] | 3 | 24
     test_case![
-        Ox76 | (registers!(), memory!(0x0=>0x76), 0),
-        (registers!(pc:1), memory!(0x0=>0x76), 0)
+        OxCD | (
+            registers!(sp:0xFFFE),
+            memory!(0x0=>0xCD,0x1=>0xFE,0x2=>0x7F,0x7FFE=>0x76),
+            0
+        ),
+        (
+            registers!(sp:0xFFFC,pc:0x7FFF),
+            memory!(0x0=>0xCD,0x1=>0xFE,0x2=>0x7F,0x7FFE=>0x76,0xFFFC=>0x03),
+            24
+        )
     ];
-    // ANCHOR 0xCB 0x11 | RL C | [Z 0 0 C] | 2 | 8
+    // ANCHOR 0XC4 | CALL NZ, a16 | [- - - -] | 1 | 24/12
     test_case![
-        OxCB_Ox11
+        OxC4_with_Z_reset
             | (
-                registers!(c: 0x85),
-                memory!(0x0=>0xCB,0x1=>0x11,0x2=>0x76),
+                registers!(sp:0xFFFE),
+                memory!(0x0=>0xC4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
                 0
             ),
         (
-            registers!(c: 0x0B,f:SET_C,pc:0x3),
-            memory!(0x0=>0xCB,0x1=>0x11,0x2=>0x76),
-            8
+            registers!(sp:0xFFFC,pc:0x7FFF),
+            memory!(0x0=>0xC4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76,0xFFFC=>0x03),
+            24
         )
     ];
-    // ANCHOR 0x17 | RLA | [0 0 0 C] | 1 | 4
     test_case![
-        Ox17 | (registers!(a:0x95), memory!(0x0=>0x17,0x1=>0x76), 0),
+        OxC4_with_Z_set
+            | (
+                registers!(sp:0xFFFE,f: SET_Z),
+                memory!(0x0=>0xC4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+                0
+            ),
         (
-            registers!(a:0x2A,f:SET_C,pc:2),
-            memory!(0x0=>0x17,0x1=>0x76),
-            4
+            registers!(sp:0xFFFE,f: SET_Z,pc:0x04),
+            memory!(0x0=>0xC4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+            12
         )
     ];
-    // ANCHOR 0x21 | LD HL, d16 | [- - - -] | 3 | 12
+    // ANCHOR 0xCC | CALL Z, a16 | [- - - -] | 1 | 24/12
     test_case![
-        Ox21 | (
-            registers!(),
-            memory!(0x0=>0x21,0x1=>0xff,0x2=>0x9f,0x3=>0x76),
-            0
-        ),
+        OxCC_with_Z_set
+            | (
+                registers!(sp:0xFFFE,f:SET_Z),
+                memory!(0x0=>0xCC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+                0
+            ),
         (
-            registers!(h:0x9F,l:0xFF,pc:4),
-            memory!(0x0=>0x21,0x1=>0xff,0x2=>0x9f,0x3=>0x76),
-            12
+            registers!(sp:0xFFFC,f:SET_Z,pc:0x7FFF),
+            memory!(0x0=>0xCC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76,0xFFFC=>0x03),
+            24
         )
     ];
-    // ANCHOR 0x32 | LD (HL-), A | 1 | 8
     test_case![
-        Ox32 | (
-            registers!(a:0x0,h:0x1,l:0x1),
-            memory!(0x0=>0x32,0x1=>0x76),
-            0
-        ),
+        OxCC_with_Z_reset
+            | (
+                registers!(sp:0xFFFE),
+                memory!(0x0=>0xCC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+                0
+            ),
         (
-            registers!(a:0x0,h:0x1,l:0x0,pc:0x2),
-            memory!(0x0=>0x32,0x1=>0x76),
-            8
+            registers!(sp:0xFFFE,pc:0x04),
+            memory!(0x0=>0xCC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+            12
         )
     ];
-    // ANCHOR 0x20 | JR NZ, r8 | 2 | 12/8
+    // ANCHOR 0xD4 | CALL NC, a16 | [- - - -] | 1 | 24/12
     test_case![
-        Ox20_with_Z_set
+        OxD4_with_C_reset
             | (
-                registers!(f: SET_Z),
-                memory!(0x0=>0x20,0x1=>0x03,0x2=>0x76,0x3=>0xFF,0x4=>0xFF),
+                registers!(sp:0xFFFE),
+                memory!(0x0=>0xD4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
                 0
             ),
         (
-            registers!(f:SET_Z,pc:3),
-            memory!(0x0=>0x20,0x1=>0x03,0x2=>0x76,0x3=>0xFF,0x4=>0xFF),
-            8
+            registers!(sp:0xFFFC,pc:0x7FFF),
+            memory!(0x0=>0xD4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76,0xFFFC=>0x03),
+            24
         )
     ];
     test_case![
-        Ox20_with_Z_not_set
+        OxD4_with_C_set
             | (
-                registers!(),
-                memory!(0x0=>0x20,0x1=>0x03,0x2=>0xFF,0x3=>0xFF,0x4=>0xFF,0x5=>0x76),
+                registers!(sp:0xFFFE,f: SET_C),
+                memory!(0x0=>0xD4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
                 0
             ),
         (
-            registers!(pc:6),
-            memory!(0x0=>0x20,0x1=>0x03,0x2=>0xFF,0x3=>0xFF,0x4=>0xFF,0x5=>0x76),
+            registers!(sp:0xFFFE,f: SET_C,pc:0x04),
+            memory!(0x0=>0xD4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
             12
         )
     ];
-    // ANCHOR 0xCB 0x7C | BIT 7, H | [Z 0 1 -] | 2 | 8
+    // ANCHOR 0xDC | CALL C, a16 | [- - - -] | 1 | 24/12
     test_case![
-        OxCB_Ox7C
+        OxDC_with_C_set
             | (
-                registers!(h:0x80),
-                memory!(0x0=>0xcb,0x1=>0x7c,0x2=>0x76),
+                registers!(sp:0xFFFE,f:SET_C),
+                memory!(0x0=>0xDC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
                 0
             ),
         (
-            registers!(h:0x80,pc:3,f:SET_H),
-            memory!(0x0=>0xcb,0x1=>0x7c,0x2=>0x76),
-            8
+            registers!(sp:0xFFFC,f:SET_C,pc:0x7FFF),
+            memory!(0x0=>0xDC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76,0xFFFC=>0x03),
+            24
         )
     ];
-    // ANCHOR 0x28 | JR Z, r8 | [- - - -] | 12/8
     test_case![
-        Ox28_with_Z_set
+        OxDC_with_C_reset
             | (
-                registers!(f: SET_Z),
-                memory!(0x0=>0x28,0x1=>0x02,0x2=>0x76,0x3=>0x76,0x4=>0x76),
+                registers!(sp:0xFFFE),
+                memory!(0x0=>0xDC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
                 0
             ),
         (
-            registers!(pc:5,f:SET_Z),
-            memory!(0x0=>0x28,0x1=>0x02,0x2=>0x76,0x3=>0x76,0x4=>0x76),
+            registers!(sp:0xFFFE,pc:0x04),
+            memory!(0x0=>0xDC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
             12
         )
     ];
+    // !SECTION
+
+    // SECTION Return Instructions
+    // ANCHOR 0XC9 | RET | [- - - -] | 1 | 16
     test_case![
-        Ox28_with_Z_not_set
+        OxC9 | (
+            registers!(sp:0xFFFC),
+            memory!(0x0=>0xC9,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+            0
+        ),
+        (
+            registers!(sp:0xFFFE,pc:0x8000),
+            memory!(0x0=>0xC9,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+            16
+        )
+    ];
+    // ANCHOR 0xC0 | RET NZ | [- - - -] | 1 | 20/8
+    test_case![
+        OxC0_with_Z_reset
             | (
-                registers!(),
-                memory!(0x0=>0x28,0x1=>0x02,0x2=>0x76,0x3=>0x76,0x4=>0x76),
+                registers!(sp:0xFFFC),
+                memory!(0x0=>0xC0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
                 0
             ),
         (
-            registers!(pc:3),
-            memory!(0x0=>0x28,0x1=>0x02,0x2=>0x76,0x3=>0x76,0x4=>0x76),
+            registers!(sp:0xFFFE,pc:0x8000),
+            memory!(0x0=>0xC0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+            20
+        )
+    ];
+    test_case![
+        OxC0_with_Z_set
+            | (
+                registers!(sp:0xFFFC,f:SET_Z),
+                memory!(0x0=>0xC0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+                0
+            ),
+        (
+            registers!(sp:0xFFFC,f:SET_Z,pc:0x02),
+            memory!(0x0=>0xC0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
             8
         )
     ];
-    // ANCHOR 0x18 | JR i8 | [- - - -] | 2 | 12
+    // ANCHOR 0xC8 | RET Z | [- - - -] | 1 | 20/8
     test_case![
-        Ox18_negative_jump
+        OxC8_with_Z_set
             | (
-                registers!(a:0x00,pc:3),
-                memory!(0x0=>0x76,0x1=>0x3C,0x2=>0x76,0x3=>0x18,0x4=>0xFC,0x5=>0x76,0x6=>0x76),
+                registers!(sp:0xFFFC,f:SET_Z),
+                memory!(0x0=>0xC8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
                 0
             ),
         (
-            registers!(a:0x01,pc:3),
-            memory!(0x0=>0x76,0x1=>0x3C,0x2=>0x76,0x3=>0x18,0x4=>0xFC,0x5=>0x76,0x6=>0x76),
-            4
+            registers!(sp:0xFFFE,f:SET_Z,pc:0x8000),
+            memory!(0x0=>0xC8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+            20
         )
     ];
     test_case![
-        Ox18_positive_jump
+        OxC8_with_Z_reset
             | (
-                registers!(a:0x00,pc:3),
-                memory!(0x0=>0x76,0x1=>0x3C,0x2=>0x76,0x3=>0x18,0x4=>0x01,0x5=>0x76,0x6=>0x3c,0x7=>0x76),
+                registers!(sp:0xFFFC),
+                memory!(0x0=>0xC8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
                 0
             ),
         (
-            registers!(a:0x01,pc:8),
-            memory!(0x0=>0x76,0x1=>0x3C,0x2=>0x76,0x3=>0x18,0x4=>0x01,0x5=>0x76,0x6=>0x3c,0x7=>0x76),
-            4
+            registers!(sp:0xFFFC,pc:0x02),
+            memory!(0x0=>0xC8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+            8
         )
     ];
-    // ANCHOR 0x3C | INC A | [Z 0 H -] | 1 | 4
+    // ANCHOR 0xD0 | RET NC | [- - - -] | 1 | 20/8
     test_case![
-        Ox3C_with_overflow
+        OxD0_with_C_reset
             | (
-                registers!(a:0xFF,f: SET_N | SET_C),
-                memory!(0x0=>0x3c,0x1=>0x76),
+                registers!(sp:0xFFFC),
+                memory!(0x0=>0xD0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
                 0
             ),
         (
-            registers!(a:0x00,pc:2,f:SET_H | SET_Z | SET_C),
-            memory!(0x0=>0x3c,0x1=>0x76),
-            4
+            registers!(sp:0xFFFE,pc:0x8000),
+            memory!(0x0=>0xD0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+            20
         )
     ];
     test_case![
-        Ox3C_without_overflow
+        OxC0_with_C_set
             | (
-                registers!(a:0x0E,f:SET_C | SET_H | SET_N | SET_Z),
-                memory!(0x0=>0x3C,0x1=>0x76),
+                registers!(sp:0xFFFC,f:SET_C),
+                memory!(0x0=>0xD0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
                 0
             ),
         (
-            registers!(a:0x0F,f:SET_C,pc:2),
-            memory!(0x0=>0x3C,0x1=>0x76),
-            4
+            registers!(sp:0xFFFC,f:SET_C,pc:0x02),
+            memory!(0x0=>0xD0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+            8
         )
     ];
-    // SECTION 8 Bit Load Operations
-    // ANCHOR 0x3E | LD A, d8 | [- - - -] | 2 | 8
+    // ANCHOR 0xD8 | RET C | [- - - -] | 1 | 20/8
     test_case![
-        Ox3E | (registers!(), memory!(0x0=>0x3E,0x1=>0xFE,0x2=>0x76), 0),
+        OxD8_with_C_set
+            | (
+                registers!(sp:0xFFFC,f:SET_C),
+                memory!(0x0=>0xD8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+                0
+            ),
         (
-            registers!(a:0xFE,pc:3),
-            memory!(0x0=>0x3E,0x1=>0xFE,0x2=>0x76),
+            registers!(sp:0xFFFE,f:SET_C,pc:0x8000),
+            memory!(0x0=>0xD8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+            20
+        )
+    ];
+    test_case![
+        OxD8_with_Z_reset
+            | (
+                registers!(sp:0xFFFC),
+                memory!(0x0=>0xD8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+                0
+            ),
+        (
+            registers!(sp:0xFFFC,pc:0x02),
+            memory!(0x0=>0xD8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
             8
         )
     ];
-    // ANCHOR 0x06 | LD B, d8 | [- - - -] | 2 | 8
+    // ANCHOR RETI | [- - - -] | 1 | 16
+    #[test]
+    fn OxD9() {
+        let current_state = (
+            registers!(sp:0xFFFC),
+            memory!(0x0=>0xD9,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+            0,
+            false,
+        );
+        let expected_state = (
+            registers!(sp:0xFFFE,pc:0x8000),
+            memory!(0x0=>0xD9,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+            16,
+            true,
+        );
+
+        let mut memory = current_state.1;
+        let mut cpu = CPU {
+            registers: current_state.0,
+            cycle: current_state.2,
+            state: CPUState::Active,
+            ime: false,
+            ime_scheduled: false,
+            halt_bug: false,
+        };
+        run_until_halt(&mut cpu, &mut memory);
+
+        let mem = memory;
+        let ime_ = cpu.ime;
+        assert_eq!(
+            (cpu.get_registers(), mem, cpu.get_cycles(), ime_),
+            expected_state
+        );
+    }
+    // !SECTION
+
+    // SECTION Accumulator Rotates
+    // ANCHOR 0x07 | RLCA | [0 0 0 C] | 1 | 4
     test_case![
-        Ox06 | (registers!(), memory!(0x0=>0x06,0x1=>0xFE,0x2=>0x76), 0),
+        Ox07 | (registers!(a:0x85,f:SET_Z), memory!(0x0=>0x07,0x1=>0x76), 0),
         (
-            registers!(b:0xFE,pc:3),
-            memory!(0x0=>0x06,0x1=>0xFE,0x2=>0x76),
-            8
+            registers!(a:0x0B,f:SET_C,pc:2),
+            memory!(0x0=>0x07,0x1=>0x76),
+            4
         )
     ];
-    // ANCHOR 0x0E | LD E, d8 | [- - - -] | 2 | 8
+    // ANCHOR 0x0F | RRCA | [0 0 0 C] | 1 | 4
     test_case![
-        Ox0E | (registers!(), memory!(0x0=>0x0E,0x1=>0xFE,0x2=>0x76), 0),
+        Ox0F | (registers!(a:0x01), memory!(0x0=>0x0F,0x1=>0x76), 0),
         (
-            registers!(c:0xFE,pc:3),
-            memory!(0x0=>0x0E,0x1=>0xFE,0x2=>0x76),
-            8
+            registers!(a:0x80,f:SET_C,pc:2),
+            memory!(0x0=>0x0F,0x1=>0x76),
+            4
         )
     ];
-    // ANCHOR 0x16 | LD D, d8 | [- - - -] | 2 | 8
+    // ANCHOR 0x1F | RRA | [0 0 0 C] | 1 | 4
     test_case![
-        Ox16 | (registers!(), memory!(0x0=>0x16,0x1=>0xFE,0x2=>0x76), 0),
+        Ox1F | (registers!(a:0x81,f:SET_Z), memory!(0x0=>0x1F,0x1=>0x76), 0),
         (
-            registers!(d:0xFE,pc:3),
-            memory!(0x0=>0x16,0x1=>0xFE,0x2=>0x76),
-            8
+            registers!(a:0x40,f:SET_C,pc:2),
+            memory!(0x0=>0x1F,0x1=>0x76),
+            4
         )
     ];
-    // ANCHOR 0x1E | LD E, d8 | [- - - -] | 2 | 8
     test_case![
-        Ox1E | (registers!(), memory!(0x0=>0x1E,0x1=>0xFE,0x2=>0x76), 0),
-        (
-            registers!(e:0xFE,pc:3),
-            memory!(0x0=>0x1E,0x1=>0xFE,0x2=>0x76),
-            8
-        )
+        Ox1F_with_C_set | (registers!(a:0x02,f:SET_C), memory!(0x0=>0x1F,0x1=>0x76), 0),
+        (registers!(a:0x81,pc:2), memory!(0x0=>0x1F,0x1=>0x76), 4)
     ];
-    // ANCHOR 0x26 | LD (HL), d8 | [- - - -] | 2 | 8
+    // !SECTION
+
+    // SECTION Jump Instructions
+    // ANCHOR 0x30 | JR NC, r8 | [- - - -] | 2 | 12/8
     test_case![
-        Ox26 | (registers!(), memory!(0x0=>0x26,0x1=>0xFE,0x2=>0x76), 0),
+        Ox30_with_C_reset
+            | (
+                registers!(),
+                memory!(0x0=>0x30,0x1=>0x02,0x2=>0x76,0x3=>0x76,0x4=>0x76),
+                0
+            ),
         (
-            registers!(h:0xFE,pc:3),
-            memory!(0x0=>0x26,0x1=>0xFE,0x2=>0x76),
-            8
+            registers!(pc:5),
+            memory!(0x0=>0x30,0x1=>0x02,0x2=>0x76,0x3=>0x76,0x4=>0x76),
+            12
         )
     ];
-    // ANCHOR 0x2E | LD L, d8 | [- - - -] | 2 | 8
     test_case![
-        Ox2E | (registers!(), memory!(0x0=>0x2E,0x1=>0xFE,0x2=>0x76), 0),
+        Ox30_with_C_set
+            | (
+                registers!(f:SET_C),
+                memory!(0x0=>0x30,0x1=>0x02,0x2=>0x76,0x3=>0x76,0x4=>0x76),
+                0
+            ),
         (
-            registers!(l:0xFE,pc:3),
-            memory!(0x0=>0x2E,0x1=>0xFE,0x2=>0x76),
+            registers!(f:SET_C,pc:3),
+            memory!(0x0=>0x30,0x1=>0x02,0x2=>0x76,0x3=>0x76,0x4=>0x76),
             8
         )
     ];
-    // ANCHOR 0x36 | LD (HL), d8 | [- - - -] | 2 | 12
+    // ANCHOR 0x38 | JR C, r8 | [- - - -] | 2 | 12/8
     test_case![
-        Ox36 | (
-            registers!(h:0x01,l:0x00),
-            memory!(0x0=>0x36,0x1=>0xFE,0x2=>0x76),
-            0
-        ),
+        Ox38_with_C_set
+            | (
+                registers!(f:SET_C,pc:3),
+                memory!(0x0=>0x76,0x1=>0x76,0x2=>0x76,0x3=>0x38,0x4=>0xFC),
+                0
+            ),
         (
-            registers!(h:0x01,l:0x00,pc:3),
-            memory!(0x0=>0x36,0x1=>0xFE,0x2=>0x76,0x100=>0xFE),
+            registers!(f:SET_C,pc:2),
+            memory!(0x0=>0x76,0x1=>0x76,0x2=>0x76,0x3=>0x38,0x4=>0xFC),
             12
         )
     ];
-    // !SECTION
-    // ANCHOR 0x87 | ADD A, A | [Z 0 H C] | 1 | 4
     test_case![
-        Ox87_Zero | (registers!(a:0x0,f:SET_N), memory!(0x0=>0x87,0x1=>0x76), 0),
-        (
-            registers!(a:0x0,f:SET_Z,pc:2),
-            memory!(0x0=>0x87,0x1=>0x76),
-            4
-        )
+        Ox38_with_C_reset | (registers!(pc:3), memory!(0x3=>0x38,0x4=>0xFC,0x5=>0x76), 0),
+        (registers!(pc:6), memory!(0x3=>0x38,0x4=>0xFC,0x5=>0x76), 8)
     ];
+    // ANCHOR 0xC2 | JP NZ, a16 | [- - - -] | 3 | 16/12
     test_case![
-        Ox87_With_Half_Carry | (registers!(a:0x0f,f:SET_N), memory!(0x0=>0x87,0x1=>0x76), 0),
+        OxC2_with_Z_reset
+            | (
+                registers!(),
+                memory!(0x0=>0xC2,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+                0
+            ),
         (
-            registers!(a:0x1e,f:SET_H,pc:2),
-            memory!(0x0=>0x87,0x1=>0x76),
-            4
+            registers!(pc:0x7FFF),
+            memory!(0x0=>0xC2,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+            16
         )
     ];
     test_case![
-        Ox87_With_Carry | (registers!(a:0x80,f:SET_N), memory!(0x0=>0x87,0x1=>0x76), 0),
+        OxC2_with_Z_set
+            | (
+                registers!(f:SET_Z),
+                memory!(0x0=>0xC2,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+                0
+            ),
         (
-            registers!(a:0x00,f:SET_Z|SET_C,pc:2),
-            memory!(0x0=>0x87,0x1=>0x76),
-            4
+            registers!(f:SET_Z,pc:0x04),
+            memory!(0x0=>0xC2,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+            12
         )
     ];
+    // ANCHOR 0xCA | JP Z, a16 | [- - - -] | 3 | 16/12
     test_case![
-        Ox87_With_Both_Carry | (registers!(a:0x88,f:SET_N), memory!(0x0=>0x87,0x1=>0x76), 0),
+        OxCA_with_Z_set
+            | (
+                registers!(f:SET_Z),
+                memory!(0x0=>0xCA,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+                0
+            ),
         (
-            registers!(a:0x10,f:SET_H|SET_C,pc:2),
-            memory!(0x0=>0x87,0x1=>0x76),
-            4
+            registers!(f:SET_Z,pc:0x7FFF),
+            memory!(0x0=>0xCA,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+            16
         )
     ];
     test_case![
-        Ox80_Zero
+        OxCA_with_Z_reset
             | (
-                registers!(a:0x0,b:0x0,f:SET_N),
-                memory!(0x0=>0x80,0x1=>0x76),
+                registers!(),
+                memory!(0x0=>0xCA,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
                 0
             ),
         (
-            registers!(a:0x0,f:SET_Z,pc:2),
-            memory!(0x0=>0x80,0x1=>0x76),
-            4
+            registers!(pc:0x04),
+            memory!(0x0=>0xCA,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+            12
         )
     ];
+    // ANCHOR 0xD2 | JP NC, a16 | [- - - -] | 3 | 16/12
     test_case![
-        Ox80_With_Half_Carry
+        OxD2_with_C_reset
             | (
-                registers!(a:0x0f,b:0x01,f:SET_N),
-                memory!(0x0=>0x80,0x1=>0x76),
+                registers!(),
+                memory!(0x0=>0xD2,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
                 0
             ),
         (
-            registers!(a:0x10,b:0x01,f:SET_H,pc:2),
-            memory!(0x0=>0x80,0x1=>0x76),
-            4
+            registers!(pc:0x7FFF),
+            memory!(0x0=>0xD2,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+            16
         )
     ];
     test_case![
-        Ox80_With_Carry
+        OxD2_with_C_set
             | (
-                registers!(a:0xf0,b:0x10,f:SET_N),
-                memory!(0x0=>0x80,0x1=>0x76),
+                registers!(f:SET_C),
+                memory!(0x0=>0xD2,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
                 0
             ),
         (
-            registers!(a:0x00,b:0x10,f:SET_Z|SET_C,pc:2),
-            memory!(0x0=>0x80,0x1=>0x76),
-            4
+            registers!(f:SET_C,pc:0x04),
+            memory!(0x0=>0xD2,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+            12
         )
     ];
+    // ANCHOR 0xDA | JP C, a16 | [- - - -] | 3 | 16/12
     test_case![
-        Ox80_With_Both_Carry
+        OxDA_with_C_set
             | (
-                registers!(a:0xff,b:0x01,f:SET_N),
-                memory!(0x0=>0x80,0x1=>0x76),
+                registers!(f:SET_C),
+                memory!(0x0=>0xDA,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
                 0
             ),
         (
-            registers!(a:0x00,b:0x01,f:SET_H|SET_C|SET_Z,pc:2),
-            memory!(0x0=>0x80,0x1=>0x76),
-            4
+            registers!(f:SET_C,pc:0x7FFF),
+            memory!(0x0=>0xDA,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+            16
         )
     ];
-    // SECTION Stack Operations
-    // 0xC5 | PUSH BC | [- - - -] | 1 | 16
     test_case![
-        OxC5 | (
-            registers!(sp: 0xfffe,b:0x01,c:0x01),
-            memory!(0x0=>0xC5,0x1=>0x76),
-            0
-        ),
+        OxDA_with_C_reset
+            | (
+                registers!(),
+                memory!(0x0=>0xDA,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+                0
+            ),
         (
-            registers!(sp: 0xfffc,b:0x01,c:0x01,pc:0x02),
-            memory!(0x0=>0xC5,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x01),
-            16
+            registers!(pc:0x04),
+            memory!(0x0=>0xDA,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+            12
         )
     ];
-    // 0xD5 | PUSH DE | [- - - -] | 1 | 16
+    // ANCHOR 0xE9 | JP (HL) | [- - - -] | 1 | 4
     test_case![
-        OxD5 | (
-            registers!(sp: 0xfffe,d:0x01,e:0x01),
-            memory!(0x0=>0xD5,0x1=>0x76),
+        OxE9 | (
+            registers!(h:0x7F,l:0xFE),
+            memory!(0x0=>0xE9,0x1=>0x76,0x7FFE=>0x76),
             0
         ),
         (
-            registers!(sp: 0xfffc,d:0x01,e:0x01,pc:0x02),
-            memory!(0x0=>0xD5,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x01),
-            16
+            registers!(h:0x7F,l:0xFE,pc:0x7FFF),
+            memory!(0x0=>0xE9,0x1=>0x76,0x7FFE=>0x76),
+            4
         )
     ];
-    // 0xE5 | PUSH HL | [- - - -] | 1 | 16
+    // !SECTION
+
+    // SECTION 16 Bit Arithmetic
+    // ANCHOR 0x09 | ADD HL, BC | [- 0 H C] | 1 | 8
     test_case![
-        OxE5 | (
-            registers!(sp: 0xfffe,h:0x01,l:0x01),
-            memory!(0x0=>0xE5,0x1=>0x76),
-            0
-        ),
+        Ox09_with_Half_Carry
+            | (
+                registers!(b:0x00,c:0x01,h:0x0F,l:0xFF,f:SET_Z|SET_N),
+                memory!(0x0=>0x09,0x1=>0x76),
+                0
+            ),
         (
-            registers!(sp: 0xfffc,h:0x01,l:0x01,pc:0x02),
-            memory!(0x0=>0xE5,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x01),
-            16
+            registers!(b:0x00,c:0x01,h:0x10,l:0x00,f:SET_Z|SET_H,pc:2),
+            memory!(0x0=>0x09,0x1=>0x76),
+            8
         )
     ];
-    // 0xF5 | PUSH AF | [- - - -] | 1 | 16
     test_case![
-        OxF5 | (
-            registers!(sp: 0xfffe,a:0x01,f:0x01),
-            memory!(0x0=>0xF5,0x1=>0x76),
-            0
-        ),
+        Ox09_with_Carry
+            | (
+                registers!(b:0x80,c:0x00,h:0x80,l:0x00),
+                memory!(0x0=>0x09,0x1=>0x76),
+                0
+            ),
         (
-            registers!(sp: 0xfffc,a:0x01,f:0x01,pc:0x02),
-            memory!(0x0=>0xF5,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x01),
-            16
+            registers!(b:0x80,c:0x00,h:0x00,l:0x00,f:SET_C,pc:2),
+            memory!(0x0=>0x09,0x1=>0x76),
+            8
         )
     ];
-    // 0xC1 | POP BC | [- - - -] | 1 | 12
+    // ANCHOR 0x03 | INC BC | [- - - -] | 1 | 8
     test_case![
-        OxC1 | (
-            registers!(sp:0xFFFC),
-            memory!(0x0=>0xC1,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x02),
+        Ox03 | (
+            registers!(b:0xFF,c:0xFF,f:SET_N),
+            memory!(0x0=>0x03,0x1=>0x76),
             0
         ),
-        (
-            registers!(sp:0xFFFE,b:0x02,c:0x01,pc:2),
-            memory!(0x0=>0xC1,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x02),
-            12
-        )
+        (registers!(f:SET_N,pc:2), memory!(0x0=>0x03,0x1=>0x76), 8)
     ];
-    // 0xD1 | POP DE | [- - - -] | 1 | 12
+    // ANCHOR 0x0B | DEC BC | [- - - -] | 1 | 8
     test_case![
-        OxD1 | (
-            registers!(sp:0xFFFC),
-            memory!(0x0=>0xD1,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x02),
-            0
-        ),
+        Ox0B | (registers!(f:SET_Z), memory!(0x0=>0x0B,0x1=>0x76), 0),
         (
-            registers!(sp:0xFFFE,d:0x02,e:0x01,pc:2),
-            memory!(0x0=>0xD1,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x02),
-            12
+            registers!(b:0xFF,c:0xFF,f:SET_Z,pc:2),
+            memory!(0x0=>0x0B,0x1=>0x76),
+            8
         )
     ];
-    // 0xE1 | POP HL | [- - - -] | 1 | 12
+    // ANCHOR 0xE8 | ADD SP, r8 | [0 0 H C] | 2 | 16
     test_case![
-        OxE1 | (
-            registers!(sp:0xFFFC),
-            memory!(0x0=>0xE1,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x02),
-            0
-        ),
+        OxE8_negative
+            | (
+                registers!(sp:0xFFF8,f:SET_Z|SET_N),
+                memory!(0x0=>0xE8,0x1=>0xFF,0x2=>0x76),
+                0
+            ),
         (
-            registers!(sp:0xFFFE,h:0x02,l:0x01,pc:2),
-            memory!(0x0=>0xE1,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x02),
-            12
+            registers!(sp:0xFFF7,f:SET_H|SET_C,pc:3),
+            memory!(0x0=>0xE8,0x1=>0xFF,0x2=>0x76),
+            16
         )
     ];
-    // 0xF1 | POP AF | [- - - -] | 1 | 12
+    // ANCHOR 0xF8 | LD HL, SP+r8 | [0 0 H C] | 2 | 12
     test_case![
-        OxF1 | (
-            registers!(sp:0xFFFC),
-            memory!(0x0=>0xF1,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x02),
+        OxF8 | (
+            registers!(sp:0xFFF8),
+            memory!(0x0=>0xF8,0x1=>0x02,0x2=>0x76),
             0
         ),
         (
-            registers!(sp:0xFFFE,a:0x02,f:0x01,pc:2),
-            memory!(0x0=>0xF1,0x1=>0x76,0xFFFC=>0x01,0xFFFD=>0x02),
+            registers!(sp:0xFFF8,h:0xFF,l:0xFA,pc:3),
+            memory!(0x0=>0xF8,0x1=>0x02,0x2=>0x76),
             12
         )
     ];
     // !SECTION
 
-    // SECTION CALL Instructions
-    // ANCHOR 0xCD | CALL a16 | [- - - -] | 3 | 24
+    // SECTION Miscellaneous
+    // ANCHOR 0x27 | DAA | [Z - 0 C] | 1 | 4
     test_case![
-        OxCD | (
-            registers!(sp:0xFFFE),
-            memory!(0x0=>0xCD,0x1=>0xFE,0x2=>0x7F,0x7FFE=>0x76),
-            0
-        ),
-        (
-            registers!(sp:0xFFFC,pc:0x7FFF),
-            memory!(0x0=>0xCD,0x1=>0xFE,0x2=>0x7F,0x7FFE=>0x76,0xFFFC=>0x03),
-            24
-        )
+        Ox27_after_add | (registers!(a:0x3C,f:SET_H), memory!(0x0=>0x27,0x1=>0x76), 0),
+        (registers!(a:0x42,pc:2), memory!(0x0=>0x27,0x1=>0x76), 4)
     ];
-    // ANCHOR 0XC4 | CALL NZ, a16 | [- - - -] | 1 | 24/12
     test_case![
-        OxC4_with_Z_reset
-            | (
-                registers!(sp:0xFFFE),
-                memory!(0x0=>0xC4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
-                0
-            ),
+        Ox27_after_add_with_carry | (registers!(a:0x9A), memory!(0x0=>0x27,0x1=>0x76), 0),
         (
-            registers!(sp:0xFFFC,pc:0x7FFF),
-            memory!(0x0=>0xC4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76,0xFFFC=>0x03),
-            24
+            registers!(a:0x00,f:SET_Z|SET_C,pc:2),
+            memory!(0x0=>0x27,0x1=>0x76),
+            4
         )
     ];
     test_case![
-        OxC4_with_Z_set
+        Ox27_after_sub
             | (
-                registers!(sp:0xFFFE,f: SET_Z),
-                memory!(0x0=>0xC4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+                registers!(a:0x0F,f:SET_N|SET_H),
+                memory!(0x0=>0x27,0x1=>0x76),
                 0
             ),
         (
-            registers!(sp:0xFFFE,f: SET_Z,pc:0x04),
-            memory!(0x0=>0xC4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
-            12
+            registers!(a:0x09,f:SET_N,pc:2),
+            memory!(0x0=>0x27,0x1=>0x76),
+            4
         )
     ];
-    // ANCHOR 0xCC | CALL Z, a16 | [- - - -] | 1 | 24/12
+    // ANCHOR 0xDE | SBC A, d8 | [Z 1 H C] | 2 | 8
     test_case![
-        OxCC_with_Z_set
-            | (
-                registers!(sp:0xFFFE,f:SET_Z),
-                memory!(0x0=>0xCC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
-                0
-            ),
+        OxDE | (
+            registers!(a:0x10,f:SET_C),
+            memory!(0x0=>0xDE,0x1=>0x0F,0x2=>0x76),
+            0
+        ),
         (
-            registers!(sp:0xFFFC,f:SET_Z,pc:0x7FFF),
-            memory!(0x0=>0xCC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76,0xFFFC=>0x03),
-            24
+            registers!(a:0x00,f:SET_Z|SET_N|SET_H,pc:3),
+            memory!(0x0=>0xDE,0x1=>0x0F,0x2=>0x76),
+            8
         )
     ];
     test_case![
-        OxCC_with_Z_reset
+        OxDE_with_borrow_overflow
             | (
-                registers!(sp:0xFFFE),
-                memory!(0x0=>0xCC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+                registers!(a:0x00,f:SET_C),
+                memory!(0x0=>0xDE,0x1=>0xFF,0x2=>0x76),
                 0
             ),
         (
-            registers!(sp:0xFFFE,pc:0x04),
-            memory!(0x0=>0xCC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
-            12
+            registers!(a:0x00,f:SET_Z|SET_N|SET_H|SET_C,pc:3),
+            memory!(0x0=>0xDE,0x1=>0xFF,0x2=>0x76),
+            8
         )
     ];
-    // ANCHOR 0xD4 | CALL NC, a16 | [- - - -] | 1 | 24/12
+    // ANCHOR 0x8F | ADC A, A | [Z 0 H C] | 1 | 4
     test_case![
-        OxD4_with_C_reset
-            | (
-                registers!(sp:0xFFFE),
-                memory!(0x0=>0xD4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
-                0
-            ),
+        Ox8F_carry_into_overflow | (registers!(a:0x80,f:SET_C), memory!(0x0=>0x8F,0x1=>0x76), 0),
         (
-            registers!(sp:0xFFFC,pc:0x7FFF),
-            memory!(0x0=>0xD4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76,0xFFFC=>0x03),
-            24
+            registers!(a:0x01,f:SET_C,pc:2),
+            memory!(0x0=>0x8F,0x1=>0x76),
+            4
         )
     ];
+    // ANCHOR 0x10 | STOP | [- - - -] | 2 | 4
     test_case![
-        OxD4_with_C_set
+        Ox10 | (registers!(), memory!(0x0=>0x10,0x1=>0x00), 0),
+        (registers!(pc:2), memory!(0x0=>0x10,0x1=>0x00), 4)
+    ];
+    // ANCHOR 0xF3 | DI | [- - - -] | 1 | 4
+    #[test]
+    fn OxF3() {
+        let mut memory = memory!(0x0=>0xF3,0x1=>0x76);
+        let mut cpu = CPU::new();
+        cpu.ime = true;
+        run_until_halt(&mut cpu, &mut memory);
+        let ime = cpu.ime;
+        assert_eq!(
+            (cpu.get_registers(), cpu.get_cycles(), ime),
+            (registers!(pc:2), 4, false)
+        );
+    }
+    // ANCHOR 0xFB | EI | [- - - -] | 1 | 4
+    #[test]
+    fn OxFB() {
+        let mut memory = memory!(0x0=>0xFB,0x1=>0x76);
+        let mut cpu = CPU::new();
+        run_until_halt(&mut cpu, &mut memory);
+        let ime = cpu.ime;
+        assert_eq!(
+            (cpu.get_registers(), cpu.get_cycles(), ime),
+            (registers!(pc:2), 4, true)
+        );
+    }
+    // !SECTION
+
+    // SECTION Illegal Opcodes
+    // ANCHOR 0xD3 0xDB 0xDD 0xE3 0xE4 0xEB 0xEC 0xED 0xF4 0xFC 0xFD | Lock up
+    #[test]
+    fn illegal_opcodes_lock_cpu() {
+        for opcode in [
+            0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+        ]
+        .iter()
+        {
+            let mut memory = memory!(0x0=>0x00,0x1=>*opcode);
+            let mut cpu = CPU::new();
+            cpu.execute(&mut memory).unwrap();
+            let error = cpu.execute(&mut memory);
+            assert_eq!(
+                (cpu.get_state(), cpu.get_registers().pc, error),
+                (
+                    CPUState::Lock,
+                    0x1,
+                    Err(GumBoiError::IllegalOpcode {
+                        pc: 0x1,
+                        opcode: *opcode
+                    })
+                ),
+                "opcode {:#04X}",
+                opcode
+            );
+        }
+    }
+    // !SECTION
+
+    // SECTION CB Prefixed Instructions
+    // ANCHOR 0xCB 0x00 | RLC B | [Z 0 0 C] | 2 | 8
+    test_case![
+        OxCB_Ox00
             | (
-                registers!(sp:0xFFFE,f: SET_C),
-                memory!(0x0=>0xD4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+                registers!(b:0x80),
+                memory!(0x0=>0xCB,0x1=>0x00,0x2=>0x76),
                 0
             ),
         (
-            registers!(sp:0xFFFE,f: SET_C,pc:0x04),
-            memory!(0x0=>0xD4,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
-            12
+            registers!(b:0x01,f:SET_C,pc:3),
+            memory!(0x0=>0xCB,0x1=>0x00,0x2=>0x76),
+            8
         )
     ];
-    // ANCHOR 0xDC | CALL C, a16 | [- - - -] | 1 | 24/12
+    // ANCHOR 0xCB 0x0E | RRC (HL) | [Z 0 0 C] | 2 | 16
     test_case![
-        OxDC_with_C_set
+        OxCB_Ox0E
             | (
-                registers!(sp:0xFFFE,f:SET_C),
-                memory!(0x0=>0xDC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+                registers!(h:0x80,l:0x00),
+                memory!(0x0=>0xCB,0x1=>0x0E,0x2=>0x76,0x8000=>0x01),
                 0
             ),
         (
-            registers!(sp:0xFFFC,f:SET_C,pc:0x7FFF),
-            memory!(0x0=>0xDC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76,0xFFFC=>0x03),
-            24
+            registers!(h:0x80,l:0x00,f:SET_C,pc:3),
+            memory!(0x0=>0xCB,0x1=>0x0E,0x2=>0x76,0x8000=>0x80),
+            16
         )
     ];
+    // ANCHOR 0xCB 0x1A | RR D | [Z 0 0 C] | 2 | 8
     test_case![
-        OxDC_with_C_reset
+        OxCB_Ox1A
             | (
-                registers!(sp:0xFFFE),
-                memory!(0x0=>0xDC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
+                registers!(d:0x01),
+                memory!(0x0=>0xCB,0x1=>0x1A,0x2=>0x76),
                 0
             ),
         (
-            registers!(sp:0xFFFE,pc:0x04),
-            memory!(0x0=>0xDC,0x1=>0xFE,0x2=>0x7F,0x3=>0x76,0x7FFE=>0x76),
-            12
-        )
-    ];
-    // !SECTION
-
-    // SECTION Return Instructions
-    // ANCHOR 0XC9 | RET | [- - - -] | 1 | 16
-    test_case![
-        OxC9 | (
-            registers!(sp:0xFFFC),
-            memory!(0x0=>0xC9,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
-            0
-        ),
-        (
-            registers!(sp:0xFFFE,pc:0x8000),
-            memory!(0x0=>0xC9,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
-            16
+            registers!(d:0x00,f:SET_Z|SET_C,pc:3),
+            memory!(0x0=>0xCB,0x1=>0x1A,0x2=>0x76),
+            8
         )
     ];
-    // ANCHOR 0xC0 | RET NZ | [- - - -] | 1 | 20/8
+    // ANCHOR 0xCB 0x23 | SLA E | [Z 0 0 C] | 2 | 8
     test_case![
-        OxC0_with_Z_reset
+        OxCB_Ox23
             | (
-                registers!(sp:0xFFFC),
-                memory!(0x0=>0xC0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+                registers!(e:0xC1),
+                memory!(0x0=>0xCB,0x1=>0x23,0x2=>0x76),
                 0
             ),
         (
-            registers!(sp:0xFFFE,pc:0x8000),
-            memory!(0x0=>0xC0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
-            20
+            registers!(e:0x82,f:SET_C,pc:3),
+            memory!(0x0=>0xCB,0x1=>0x23,0x2=>0x76),
+            8
         )
     ];
+    // ANCHOR 0xCB 0x2F | SRA A | [Z 0 0 C] | 2 | 8
     test_case![
-        OxC0_with_Z_set
+        OxCB_Ox2F
             | (
-                registers!(sp:0xFFFC,f:SET_Z),
-                memory!(0x0=>0xC0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+                registers!(a:0x81),
+                memory!(0x0=>0xCB,0x1=>0x2F,0x2=>0x76),
                 0
             ),
         (
-            registers!(sp:0xFFFC,f:SET_Z,pc:0x02),
-            memory!(0x0=>0xC0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+            registers!(a:0xC0,f:SET_C,pc:3),
+            memory!(0x0=>0xCB,0x1=>0x2F,0x2=>0x76),
             8
         )
     ];
-    // ANCHOR 0xC8 | RET Z | [- - - -] | 1 | 20/8
+    // ANCHOR 0xCB 0x37 | SWAP A | [Z 0 0 0] | 2 | 8
     test_case![
-        OxC8_with_Z_set
+        OxCB_Ox37
             | (
-                registers!(sp:0xFFFC,f:SET_Z),
-                memory!(0x0=>0xC8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+                registers!(a:0xF1,f:SET_C),
+                memory!(0x0=>0xCB,0x1=>0x37,0x2=>0x76),
                 0
             ),
         (
-            registers!(sp:0xFFFE,f:SET_Z,pc:0x8000),
-            memory!(0x0=>0xC8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
-            20
+            registers!(a:0x1F,pc:3),
+            memory!(0x0=>0xCB,0x1=>0x37,0x2=>0x76),
+            8
         )
     ];
+    // ANCHOR 0xCB 0x36 | SWAP (HL) | [Z 0 0 0] | 2 | 16
     test_case![
-        OxC8_with_Z_reset
+        OxCB_Ox36
             | (
-                registers!(sp:0xFFFC),
-                memory!(0x0=>0xC8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+                registers!(h:0x80,l:0x00),
+                memory!(0x0=>0xCB,0x1=>0x36,0x2=>0x76,0x8000=>0xAB),
                 0
             ),
         (
-            registers!(sp:0xFFFC,pc:0x02),
-            memory!(0x0=>0xC8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
-            8
+            registers!(h:0x80,l:0x00,pc:3),
+            memory!(0x0=>0xCB,0x1=>0x36,0x2=>0x76,0x8000=>0xBA),
+            16
         )
     ];
-    // ANCHOR 0xD0 | RET NC | [- - - -] | 1 | 20/8
+    // ANCHOR 0xCB 0x3C | SRL H | [Z 0 0 C] | 2 | 8
     test_case![
-        OxD0_with_C_reset
+        OxCB_Ox3C
             | (
-                registers!(sp:0xFFFC),
-                memory!(0x0=>0xD0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+                registers!(h:0x81),
+                memory!(0x0=>0xCB,0x1=>0x3C,0x2=>0x76),
                 0
             ),
         (
-            registers!(sp:0xFFFE,pc:0x8000),
-            memory!(0x0=>0xD0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
-            20
+            registers!(h:0x40,f:SET_C,pc:3),
+            memory!(0x0=>0xCB,0x1=>0x3C,0x2=>0x76),
+            8
         )
     ];
+    // ANCHOR 0xCB 0x46 | BIT 0, (HL) | [Z 0 1 -] | 2 | 12
     test_case![
-        OxC0_with_C_set
+        OxCB_Ox46
             | (
-                registers!(sp:0xFFFC,f:SET_C),
-                memory!(0x0=>0xD0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+                registers!(h:0x80,l:0x00,f:SET_N|SET_C),
+                memory!(0x0=>0xCB,0x1=>0x46,0x2=>0x76,0x8000=>0xFE),
                 0
             ),
         (
-            registers!(sp:0xFFFC,f:SET_C,pc:0x02),
-            memory!(0x0=>0xD0,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
-            8
+            registers!(h:0x80,l:0x00,f:SET_Z|SET_H|SET_C,pc:3),
+            memory!(0x0=>0xCB,0x1=>0x46,0x2=>0x76,0x8000=>0xFE),
+            12
         )
     ];
-    // ANCHOR 0xD8 | RET C | [- - - -] | 1 | 20/8
+    // ANCHOR 0xCB 0x9D | RES 3, L | [- - - -] | 2 | 8
     test_case![
-        OxD8_with_C_set
+        OxCB_Ox9D
             | (
-                registers!(sp:0xFFFC,f:SET_C),
-                memory!(0x0=>0xD8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+                registers!(l:0xFF,f:SET_Z),
+                memory!(0x0=>0xCB,0x1=>0x9D,0x2=>0x76),
                 0
             ),
         (
-            registers!(sp:0xFFFE,f:SET_C,pc:0x8000),
-            memory!(0x0=>0xD8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
-            20
+            registers!(l:0xF7,f:SET_Z,pc:3),
+            memory!(0x0=>0xCB,0x1=>0x9D,0x2=>0x76),
+            8
         )
     ];
+    // ANCHOR 0xCB 0xFE | SET 7, (HL) | [- - - -] | 2 | 16
     test_case![
-        OxD8_with_Z_reset
+        OxCB_OxFE
             | (
-                registers!(sp:0xFFFC),
-                memory!(0x0=>0xD8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
+                registers!(h:0x80,l:0x00),
+                memory!(0x0=>0xCB,0x1=>0xFE,0x2=>0x76,0x8000=>0x01),
                 0
             ),
         (
-            registers!(sp:0xFFFC,pc:0x02),
-            memory!(0x0=>0xD8,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
-            8
+            registers!(h:0x80,l:0x00,pc:3),
+            memory!(0x0=>0xCB,0x1=>0xFE,0x2=>0x76,0x8000=>0x81),
+            16
         )
     ];
-    // ANCHOR RETI | [- - - -] | 1 | 16
+    // !SECTION
+
+    // SECTION Interrupt Handling
+    fn execute_steps(
+        mut memory: FlatBus,
+        registers: Registers,
+        ime: bool,
+        steps: usize,
+    ) -> (CPU, FlatBus) {
+        let mut cpu = CPU::new();
+        cpu.registers = registers;
+        cpu.ime = ime;
+        for _ in 0..steps {
+            cpu.execute(&mut memory).unwrap();
+        }
+        (cpu, memory)
+    }
     #[test]
-    fn OxD9() {
-        let current_state = (
-            registers!(sp:0xFFFC),
-            memory!(0x0=>0xD9,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
-            0,
-            false,
+    fn interrupt_dispatch_pushes_pc() {
+        let (cpu, memory) = execute_steps(
+            memory!(0xFF0F=>0b00000101,0xFFFF=>0b00000100),
+            registers!(sp:0xFFFE,pc:0x1234),
+            true,
+            1,
         );
-        let expected_state = (
-            registers!(sp:0xFFFE,pc:0x8000),
-            memory!(0x0=>0xD9,0x1=>0x76,0x7FFF=>0x76,0xFFFC=>0xFF,0xFFFD=>0x7F),
-            16,
+        assert_eq!(
+            (
+                cpu.get_registers(),
+                cpu.get_cycles(),
+                cpu.is_set_ime(),
+                memory.get_addr(0xFF0F),
+                memory.get_addr(0xFFFC),
+                memory.get_addr(0xFFFD)
+            ),
+            (
+                registers!(sp:0xFFFC,pc:0x50),
+                20,
+                false,
+                0b11100001,
+                0x34,
+                0x12
+            )
+        );
+    }
+    #[test]
+    fn interrupt_dispatch_priority() {
+        let (cpu, memory) = execute_steps(
+            memory!(0xFF0F=>0b00011110,0xFFFF=>0b00011010),
+            registers!(sp:0xFFFE,pc:0x1234),
             true,
+            1,
+        );
+        let interrupt_requests = memory.get_addr(0xFF0F);
+        assert_eq!(
+            (cpu.get_registers().pc, interrupt_requests),
+            (0x48, 0b11111100)
         );
+    }
+    #[test]
+    fn interrupt_not_dispatched_with_ime_clear() {
+        let (cpu, _) = execute_steps(
+            memory!(0x0=>0x00,0xFF0F=>0b00000001,0xFFFF=>0b00000001),
+            registers!(sp:0xFFFE),
+            false,
+            1,
+        );
+        assert_eq!((cpu.get_registers().pc, cpu.get_cycles()), (0x1, 4));
+    }
+    // EI only enables interrupts after the instruction that follows it
+    #[test]
+    fn ei_is_delayed_by_one_instruction() {
+        let program = memory!(0x0=>0xFB,0x1=>0x00,0x2=>0x00,0xFF0F=>0b00000001,0xFFFF=>0b00000001);
+        let (cpu, _) = execute_steps(program, registers!(sp:0xFFFE), false, 2);
+        assert_eq!((cpu.get_registers().pc, cpu.is_set_ime()), (0x2, true));
+        let program = memory!(0x0=>0xFB,0x1=>0x00,0x2=>0x00,0xFF0F=>0b00000001,0xFFFF=>0b00000001);
+        let (cpu, _) = execute_steps(program, registers!(sp:0xFFFE), false, 3);
+        assert_eq!((cpu.get_registers().pc, cpu.is_set_ime()), (0x40, false));
+    }
+    #[test]
+    fn di_cancels_pending_ei() {
+        let program = memory!(0x0=>0xFB,0x1=>0xF3,0x2=>0x00,0xFF0F=>0b00000001,0xFFFF=>0b00000001);
+        let (cpu, _) = execute_steps(program, registers!(sp:0xFFFE), false, 3);
+        assert_eq!((cpu.get_registers().pc, cpu.is_set_ime()), (0x3, false));
+    }
+    #[test]
+    fn reti_enables_ime_immediately() {
+        let program = memory!(0x0=>0xD9,0x1000=>0x00,0xFFFC=>0x00,0xFFFD=>0x10,0xFF0F=>0b00000001,0xFFFF=>0b00000001);
+        let (cpu, _) = execute_steps(program, registers!(sp:0xFFFC), false, 2);
+        assert_eq!((cpu.get_registers().pc, cpu.get_cycles()), (0x40, 20));
+    }
+    // !SECTION
 
-        let memory = Arc::new(Mutex::new(current_state.1));
-        let memory_1 = Arc::clone(&memory);
-        let ime = Arc::new(Mutex::new(false));
-        let mut cpu = CPU {
-            registers: current_state.0,
-            memory: memory,
-            cycle: current_state.2,
-            state: CPUState::Active,
-            ime: ime,
-        };
-        while cpu.state == CPUState::Active {
-            cpu.execute();
+    // SECTION HALT
+    #[test]
+    fn halt_idles_without_pending_interrupt() {
+        let (cpu, _) = execute_steps(memory!(0x0=>0x76,0x1=>0x3C), registers!(), false, 3);
+        assert_eq!(
+            (cpu.get_state(), cpu.get_registers(), cpu.get_cycles()),
+            (CPUState::Halt, registers!(pc:1), 4)
+        );
+    }
+    #[test]
+    fn halt_wakes_without_dispatch_when_ime_clear() {
+        let (mut cpu, mut memory) =
+            execute_steps(memory!(0x0=>0x76,0x1=>0x3C), registers!(), false, 2);
+        memory.set_addr(0xFFFF, 0b00000100);
+        memory.set_addr(0xFF0F, 0b00000100);
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(
+            (cpu.get_state(), cpu.get_registers()),
+            (CPUState::Active, registers!(a:0x1,pc:2))
+        );
+    }
+    #[test]
+    fn halt_wakes_and_dispatches_when_ime_set() {
+        let (mut cpu, mut memory) =
+            execute_steps(memory!(0x0=>0x76,0x1=>0x3C), registers!(sp:0xFFFE), true, 2);
+        memory.set_addr(0xFFFF, 0b00000100);
+        memory.set_addr(0xFF0F, 0b00000100);
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(
+            (cpu.get_state(), cpu.get_registers(), cpu.get_cycles()),
+            (CPUState::Active, registers!(sp:0xFFFC,pc:0x50), 20)
+        );
+    }
+    // With IME clear and an interrupt already pending, HALT is skipped and PC fails to increment
+    #[test]
+    fn halt_bug_repeats_next_byte() {
+        let (cpu, _) = execute_steps(
+            memory!(0x0=>0x76,0x1=>0x3C,0x2=>0x00,0xFF0F=>0b00000001,0xFFFF=>0b00000001),
+            registers!(),
+            false,
+            3,
+        );
+        assert_eq!(
+            (cpu.get_state(), cpu.get_registers()),
+            (CPUState::Active, registers!(a:0x2,pc:2))
+        );
+    }
+    // !SECTION
+
+    // SECTION System Clock
+    // Test bus recording the byte at 0xC000 each time it is ticked
+    struct BusProbe {
+        memory: FlatBus,
+        samples: Vec<u8>,
+    }
+    impl Bus for BusProbe {
+        fn read(&self, addr: u16) -> u8 {
+            self.memory.get_addr(addr)
+        }
+        fn write(&mut self, addr: u16, value: u8) {
+            self.memory.set_addr(addr, value);
+        }
+        fn tick(&mut self) {
+            self.samples.push(self.memory.get_addr(0xC000));
+        }
+        fn interrupts(&mut self) -> &mut InterruptController {
+            self.memory.interrupts()
         }
+        fn reset_divider(&mut self) {
+            self.memory.reset_divider();
+        }
+        fn get_joypad_lines(&self) -> u8 {
+            self.memory.get_joypad_lines()
+        }
+    }
+    // ANCHOR 0x08 | LD (a16), SP | [- - - -] | 3 | 20
+    #[test]
+    fn memory_accesses_tick_the_bus() {
+        let mut probe = BusProbe {
+            memory: memory!(0x0=>0x08,0x1=>0x00,0x2=>0xC0),
+            samples: Vec::new(),
+        };
+        let mut cpu = CPU::new();
+        cpu.registers = registers!(sp:0xABCD);
+        cpu.execute(&mut probe).unwrap();
+        // The low byte of SP lands in the fourth M-cycle, the high byte in the fifth
+        assert_eq!(
+            (cpu.get_cycles(), probe.samples),
+            (20, vec![0x00, 0x00, 0x00, 0x00, 0xCD])
+        );
+    }
+    // !SECTION
 
-        let mem = *cpu.memory.lock().unwrap();
-        let ime_ = *cpu.ime.lock().unwrap();
+    // SECTION STOP
+    #[test]
+    fn stop_resets_div() {
+        // STOP runs from HRAM during an OAM DMA. TAC selects counter bit 3, which is set when
+        // STOP clears the counter, so TIMA sees a falling edge
+        let mut memory = Memory::new();
+        (0..258).for_each(|_| memory.tick());
+        memory.set_addr(0xFF80, 0x10);
+        memory.set_addr(0xFF07, 0x05);
+        memory.set_addr(0xFF46, 0xC1);
+        let div = memory.get_addr(0xFF04);
+        let mut cpu = CPU::new();
+        cpu.registers = registers!(pc:0xFF80);
+        cpu.execute(&mut memory).unwrap();
         assert_eq!(
-            (cpu.get_registers(), mem, cpu.get_cycles(), ime_),
-            expected_state
+            (
+                div,
+                cpu.get_state(),
+                memory.get_addr(0xFF04),
+                memory.get_addr(0xFF05)
+            ),
+            (0x04, CPUState::Stop, 0x0, 0x1)
+        );
+    }
+    #[test]
+    fn stop_idles_until_joypad_line_low() {
+        let (mut cpu, mut memory) = execute_steps(
+            memory!(0x0=>0x10,0x1=>0x00,0x2=>0x3C,0xFF00=>0xCF),
+            registers!(),
+            false,
+            3,
+        );
+        assert_eq!(
+            (cpu.get_state(), cpu.get_registers(), cpu.get_cycles()),
+            (CPUState::Stop, registers!(pc:2), 0)
         );
+        memory.set_addr(0xFF00, 0xC7);
+        cpu.execute(&mut memory).unwrap();
+        assert_eq!(
+            (cpu.get_state(), cpu.get_registers()),
+            (CPUState::Active, registers!(a:0x1,pc:3))
+        );
+    }
+    // !SECTION
+
+    // SECTION Opcode Table Consistency
+    // Runs a single instruction from a neutral state with every flag clear and every flag set,
+    // and checks the executor against the length, cycles and flag effects in the opcode table
+    fn check_against_table(opcode: &[u8], info: &OpcodeInfo) {
+        for initial_flags in [0x00, 0xF0].iter() {
+            let mut memory = FlatBus::new();
+            for (addr, byte) in opcode.iter().enumerate() {
+                memory.set_addr(addr as u16, *byte);
+            }
+            let registers = registers!(f:*initial_flags,h:0xC0,l:0x00,sp:0xFFF0);
+            let mut cpu = CPU::new();
+            cpu.registers = registers;
+            cpu.execute(&mut memory).unwrap();
+
+            let cycles = cpu.get_cycles() as u8;
+            assert!(
+                cycles == info.cycles || cycles == info.branch_cycles,
+                "{} took {} cycles",
+                info.mnemonic,
+                cycles
+            );
+            let control_flow = ["JP", "JR", "CALL", "RET", "RST"]
+                .iter()
+                .any(|prefix| info.mnemonic.starts_with(prefix));
+            if !control_flow {
+                assert_eq!(
+                    cpu.get_registers().pc,
+                    info.length as u16,
+                    "{} length",
+                    info.mnemonic
+                );
+            }
+            let f = cpu.get_registers().f;
+            for (index, effect) in info.flags.split(' ').enumerate() {
+                let mask = 0x80 >> index;
+                match effect {
+                    "0" => assert_eq!(f & mask, 0x0, "{} flag {}", info.mnemonic, index),
+                    "1" => assert_eq!(f & mask, mask, "{} flag {}", info.mnemonic, index),
+                    "-" => assert_eq!(
+                        f & mask,
+                        initial_flags & mask,
+                        "{} flag {}",
+                        info.mnemonic,
+                        index
+                    ),
+                    _ => {}
+                }
+            }
+        }
+    }
+    #[test]
+    fn opcode_table_matches_executor() {
+        for (opcode, info) in OPCODES.iter().enumerate() {
+            match info.mnemonic {
+                "ILLEGAL" | "HALT" | "PREFIX CB" => {}
+                _ => check_against_table(&[opcode as u8], info),
+            }
+        }
+    }
+    #[test]
+    fn cb_opcode_table_matches_executor() {
+        for (opcode, info) in CB_OPCODES.iter().enumerate() {
+            check_against_table(&[0xCB, opcode as u8], info);
+        }
     }
     // !SECTION
 
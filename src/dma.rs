@@ -0,0 +1,124 @@
+/* OAM DMA : 0xFF46
+Writing XX copies XX00 - XX9F to OAM, one byte per M-cycle for 160 M-cycles.
+Sources past 0xDFFF read the echo of WRAM.
+While the transfer runs the CPU loses OAM and the bus the source sits on : the VRAM bus for
+8000-9FFF, the external bus (ROM, cartridge RAM and WRAM) for everything else. HRAM, the I/O
+registers and IE stay reachable, which is why games copy their DMA routine to HRAM first.
+*/
+
+pub const DMA_ADDR: u16 = 0xFF46;
+const DMA_LENGTH: u16 = 0xA0;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct OamDma {
+    register: u8,
+    progress: Option<u16>, // Next byte to copy
+}
+
+impl OamDma {
+    pub fn new() -> OamDma {
+        OamDma {
+            register: 0xFF,
+            progress: None,
+        }
+    }
+    pub fn read_register(&self) -> u8 {
+        self.register
+    }
+    // A write mid-transfer restarts the copy from the new source
+    pub fn write_register(&mut self, value: u8) {
+        self.register = value;
+        self.progress = Some(0x0);
+    }
+    pub fn is_active(&self) -> bool {
+        self.progress.is_some()
+    }
+    // Whether a CPU access to addr conflicts with the running transfer
+    pub fn blocks(&self, addr: u16) -> bool {
+        let source_on_vram = matches!(self.register, 0x80..=0x9F);
+        self.is_active()
+            && match addr {
+                0xFE00..=0xFEFF => true,
+                0xFF00..=0xFFFF => false,
+                0x8000..=0x9FFF => source_on_vram,
+                _ => !source_on_vram,
+            }
+    }
+    // Advances the transfer by one M-cycle, returning the source address and OAM offset to copy
+    pub fn step(&mut self) -> Option<(u16, u16)> {
+        let index = self.progress?;
+        self.progress = match index + 1 {
+            DMA_LENGTH => None,
+            next => Some(next),
+        };
+        let source = (self.register as u16) << 8 | index;
+        match source {
+            0xE000..=0xFFFF => Some((source - 0x2000, index)),
+            _ => Some((source, index)),
+        }
+    }
+}
+
+impl Default for OamDma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod dma_tests {
+    use super::OamDma;
+
+    #[test]
+    fn transfer_lasts_160_m_cycles() {
+        let mut dma = OamDma::new();
+        dma.write_register(0xC1);
+        let copies = std::iter::from_fn(|| dma.step()).collect::<Vec<(u16, u16)>>();
+        assert_eq!(
+            (copies.len(), copies[0], copies[159], dma.is_active()),
+            (160, (0xC100, 0x00), (0xC19F, 0x9F), false)
+        );
+    }
+    #[test]
+    fn high_sources_read_echo_ram() {
+        let mut dma = OamDma::new();
+        dma.write_register(0xFE);
+        assert_eq!(dma.step(), Some((0xDE00, 0x00)));
+    }
+    #[test]
+    fn blocks_only_the_source_bus_and_oam() {
+        let mut dma = OamDma::new();
+        let addrs = [
+            0x0000, 0x8000, 0xA000, 0xC000, 0xFE00, 0xFF0F, 0xFF80, 0xFFFF,
+        ];
+        let blocked = |dma: &OamDma| {
+            addrs
+                .iter()
+                .map(|addr| dma.blocks(*addr))
+                .collect::<Vec<bool>>()
+        };
+        assert_eq!(blocked(&dma), vec![false; 8]);
+        dma.write_register(0xC0);
+        assert_eq!(
+            blocked(&dma),
+            vec![true, false, true, true, true, false, false, false]
+        );
+        dma.write_register(0x80);
+        assert_eq!(
+            blocked(&dma),
+            vec![false, true, false, false, true, false, false, false]
+        );
+    }
+    #[test]
+    fn write_restarts_transfer() {
+        let mut dma = OamDma::new();
+        dma.write_register(0xC0);
+        dma.step();
+        dma.step();
+        dma.write_register(0xD0);
+        assert_eq!(
+            (dma.step(), dma.read_register()),
+            (Some((0xD000, 0x00)), 0xD0)
+        );
+    }
+}
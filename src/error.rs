@@ -0,0 +1,82 @@
+use std::error::Error;
+use std::fmt;
+
+// Errors surfaced by the core instead of aborting the process. Every address decodes to
+// something (open bus, mirrors or ignored writes), so memory accesses cannot fail and no
+// variant carries a faulting address
+#[derive(PartialEq, Debug, Clone)]
+pub enum GumBoiError {
+    IllegalOpcode { pc: u16, opcode: u8 }, // CPU hit one of the 11 unused opcodes and locked up
+    CartridgeTooLarge { size: usize, max: usize },
+    CartridgeTooSmall { size: usize }, // Shorter than the 0x150 bytes needed for a header
+    InvalidCartridgeType { code: u8 },
+    UnsupportedCartridgeType { code: u8 }, // Valid, but its MBC is not emulated yet
+    InvalidRomSize { code: u8 },
+    InvalidRamSize { code: u8 },
+    RomSizeMismatch { expected: usize, size: usize }, // Dump is shorter than the header declares
+    HeaderChecksumMismatch { expected: u8, computed: u8 },
+    GlobalChecksumMismatch { expected: u16, computed: u16 },
+    SaveSizeMismatch { expected: usize, size: usize },
+    InvalidRtcFooter { size: usize },
+    SaveFile { path: String, message: String }, // I/O failure reading or writing a .sav
+    AudioFile { path: String, message: String }, // I/O failure writing a WAV recording
+}
+
+impl fmt::Display for GumBoiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GumBoiError::IllegalOpcode { pc, opcode } => {
+                write!(f, "Illegal opcode {:#04X} at {:#06X}", opcode, pc)
+            }
+            GumBoiError::CartridgeTooLarge { size, max } => write!(
+                f,
+                "Inserted cartridge is {} bytes, at most {} bytes are supported",
+                size, max
+            ),
+            GumBoiError::CartridgeTooSmall { size } => write!(
+                f,
+                "Inserted cartridge is {} bytes, too small to hold a header",
+                size
+            ),
+            GumBoiError::InvalidCartridgeType { code } => {
+                write!(f, "Unknown cartridge type {:#04X}", code)
+            }
+            GumBoiError::UnsupportedCartridgeType { code } => {
+                write!(f, "Cartridge type {:#04X} is not supported yet", code)
+            }
+            GumBoiError::InvalidRomSize { code } => write!(f, "Unknown ROM size {:#04X}", code),
+            GumBoiError::InvalidRamSize { code } => write!(f, "Unknown RAM size {:#04X}", code),
+            GumBoiError::RomSizeMismatch { expected, size } => write!(
+                f,
+                "Header declares a {} byte ROM, the dump is {} bytes",
+                expected, size
+            ),
+            GumBoiError::HeaderChecksumMismatch { expected, computed } => write!(
+                f,
+                "Header checksum is {:#04X}, computed {:#04X}",
+                expected, computed
+            ),
+            GumBoiError::GlobalChecksumMismatch { expected, computed } => write!(
+                f,
+                "Global checksum is {:#06X}, computed {:#06X}",
+                expected, computed
+            ),
+            GumBoiError::SaveSizeMismatch { expected, size } => write!(
+                f,
+                "Save data is {} bytes, the cartridge expects {} bytes",
+                size, expected
+            ),
+            GumBoiError::InvalidRtcFooter { size } => {
+                write!(f, "RTC footer is {} bytes, expected 44 or 48 bytes", size)
+            }
+            GumBoiError::SaveFile { path, message } => {
+                write!(f, "Save file {} : {}", path, message)
+            }
+            GumBoiError::AudioFile { path, message } => {
+                write!(f, "Audio file {} : {}", path, message)
+            }
+        }
+    }
+}
+
+impl Error for GumBoiError {}
@@ -1,9 +1,155 @@
-use super::GumBoi;
+/* Joypad : 0xFF00 (P1)
+Bit 5 : Select buttons (0 = selected)
+Bit 4 : Select d-pad (0 = selected)
+Bit 3 : Down  / Start
+Bit 2 : Up    / Select
+Bit 1 : Left  / B
+Bit 0 : Right / A
+Bits 0-3 are active low and read only. With both groups selected they are ANDed together.
+The JOYPAD interrupt fires when any of bits 0-3 goes from high to low.
+*/
 
-trait JoyPad {
-    //TODO
+use super::interrupt::{InterruptController, InterruptType};
+
+pub const P1_ADDR: u16 = 0xFF00;
+
+const SELECT_BUTTONS: u8 = 0b0010_0000;
+const SELECT_DPAD: u8 = 0b0001_0000;
+
+// Bit positions in the set_buttons mask : buttons in the low nibble, d-pad in the high nibble
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Button {
+    A,
+    B,
+    Select,
+    Start,
+    Right,
+    Left,
+    Up,
+    Down,
+}
+
+impl Button {
+    pub fn get_mask(&self) -> u8 {
+        1 << *self as u8
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct JoyPad {
+    select: u8,  // P1 bits 4-5
+    pressed: u8, // Button masks, 1 = pressed
 }
 
-impl JoyPad for GumBoi {
-    //TODO
+impl JoyPad {
+    pub fn new() -> JoyPad {
+        JoyPad {
+            select: SELECT_BUTTONS | SELECT_DPAD,
+            pressed: 0x0,
+        }
+    }
+    // Active low state of P10-P13
+    pub fn get_lines(&self) -> u8 {
+        let mut pressed = 0x0;
+        if self.select & SELECT_BUTTONS == 0 {
+            pressed |= self.pressed & 0x0F;
+        }
+        if self.select & SELECT_DPAD == 0 {
+            pressed |= self.pressed >> 4;
+        }
+        !pressed & 0x0F
+    }
+    // Applies a change to the inputs or the selection, requesting JOYPAD on a falling line
+    fn update_lines(
+        &mut self,
+        interrupts: &mut InterruptController,
+        update: impl FnOnce(&mut JoyPad),
+    ) {
+        let lines = self.get_lines();
+        update(self);
+        if lines & !self.get_lines() != 0x0 {
+            interrupts.request(InterruptType::JOYPAD);
+        }
+    }
+    pub fn read_register(&self) -> u8 {
+        0xC0 | self.select | self.get_lines()
+    }
+    pub fn write_register(&mut self, value: u8, interrupts: &mut InterruptController) {
+        self.update_lines(interrupts, |joypad| {
+            joypad.select = value & (SELECT_BUTTONS | SELECT_DPAD)
+        });
+    }
+    pub fn set_buttons(&mut self, buttons: u8, interrupts: &mut InterruptController) {
+        self.update_lines(interrupts, |joypad| joypad.pressed = buttons);
+    }
+    pub fn set_button(
+        &mut self,
+        button: Button,
+        pressed: bool,
+        interrupts: &mut InterruptController,
+    ) {
+        let buttons = match pressed {
+            true => self.pressed | button.get_mask(),
+            false => self.pressed & !button.get_mask(),
+        };
+        self.set_buttons(buttons, interrupts);
+    }
+}
+
+impl Default for JoyPad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod joypad_tests {
+    use super::{Button, JoyPad};
+    use crate::interrupt::{InterruptController, InterruptType, IE_ADDR};
+
+    fn joypad(select: u8) -> (JoyPad, InterruptController) {
+        let mut joypad = JoyPad::new();
+        let mut interrupts = InterruptController::new();
+        interrupts.write_register(IE_ADDR, 0xFF);
+        joypad.write_register(select, &mut interrupts);
+        (joypad, interrupts)
+    }
+
+    #[test]
+    fn reads_selected_group_active_low() {
+        let (mut joypad, mut interrupts) = joypad(0x10);
+        joypad.set_button(Button::Start, true, &mut interrupts);
+        joypad.set_button(Button::Left, true, &mut interrupts);
+        assert_eq!(joypad.read_register(), 0xD7);
+        joypad.write_register(0x20, &mut interrupts);
+        assert_eq!(joypad.read_register(), 0xED);
+        joypad.write_register(0x00, &mut interrupts);
+        assert_eq!(joypad.read_register(), 0xC5);
+        joypad.write_register(0x30, &mut interrupts);
+        assert_eq!(joypad.read_register(), 0xFF);
+    }
+    #[test]
+    fn press_requests_interrupt_on_selected_group() {
+        let (mut joypad, mut interrupts) = joypad(0x20);
+        joypad.set_button(Button::A, true, &mut interrupts);
+        assert_eq!(interrupts.pending(), None);
+        joypad.set_button(Button::Up, true, &mut interrupts);
+        assert_eq!(interrupts.pending(), Some(InterruptType::JOYPAD));
+    }
+    #[test]
+    fn release_does_not_request_interrupt() {
+        let (mut joypad, mut interrupts) = joypad(0x10);
+        joypad.set_buttons(0x01, &mut interrupts);
+        interrupts.acknowledge(InterruptType::JOYPAD);
+        joypad.set_buttons(0x00, &mut interrupts);
+        assert_eq!((interrupts.pending(), joypad.read_register()), (None, 0xDF));
+    }
+    #[test]
+    fn selecting_a_held_group_requests_interrupt() {
+        let (mut joypad, mut interrupts) = joypad(0x30);
+        joypad.set_button(Button::B, true, &mut interrupts);
+        assert_eq!(interrupts.pending(), None);
+        joypad.write_register(0x10, &mut interrupts);
+        assert_eq!(interrupts.pending(), Some(InterruptType::JOYPAD));
+    }
 }
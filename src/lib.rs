@@ -1,255 +1,691 @@
-#![allow(unused)]
-
+mod bus;
+mod cartridge;
 mod cpu;
+mod dma;
+mod error;
 mod interrupt;
 mod joypad;
+mod mbc;
 mod memory;
+mod opcodes;
 mod ppu;
 mod registers;
+mod rtc;
 mod sound;
 mod timer;
+mod wav;
 
-use cpu::CPUState;
-use cpu::CPU;
-use interrupt::{InterruptController, InterruptType};
+pub use cartridge::{Cartridge, CartridgeHeader, CartridgeType, CgbSupport, Licensee, Mapper};
+use cpu::{CPUState, CPU};
+pub use error::GumBoiError;
+pub use joypad::Button;
+pub use mbc::RumbleEvent;
 use memory::Memory;
-use ppu::PPU;
-use registers::Flag;
-use registers::Registers;
+pub use ppu::{Renderer, FRAME_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use rtc::{SystemClock, TimeSource};
+pub use sound::DEFAULT_SAMPLE_RATE;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use wav::AudioRecorder;
 
-use std::convert::TryInto;
-use std::sync::mpsc;
-use std::sync::{Arc, Mutex};
+// Battery RAM is flushed at most once per emulated second while the game keeps writing it,
+// audio recordings on the same schedule
+const SAVE_FLUSH_INTERVAL: usize = 4_194_304;
 
 #[derive(PartialEq, Debug)]
 enum GumBoiState {
     Active,
-    Halt,
     Exit,
 }
 
+// Why start handed control back to the frontend
+#[derive(PartialEq, Debug, Clone)]
+pub enum RunStatus {
+    Stopped,                      // The CPU executed STOP, only a button press wakes it up
+    Exited,                       // Quit was requested through the quit handle
+    RecordingFailed(GumBoiError), // The audio recording was closed, the game can carry on
+}
+
 pub struct GumBoi {
     cpu: CPU,
-    ppu: PPU,
-    interrupt_controller: InterruptController,
-    memory: Arc<Mutex<Memory>>,
+    memory: Memory, // The bus, lent to the CPU for each step
     cycle: usize,
     state: GumBoiState,
+    save_path: Option<PathBuf>,
+    last_save_flush: usize, // Cycle of the last periodic flush
+    audio_recorder: Option<AudioRecorder>,
+    recording_error: Option<GumBoiError>, // Why the recording was closed, until it is reported
+    quit: Arc<AtomicBool>,                // Set from other threads or signal handlers to end start
 }
 
 impl GumBoi {
     pub fn new() -> GumBoi {
-        let memory = Arc::new(Mutex::new(Memory::new()));
-        let ime = Arc::new(Mutex::new(false));
-        let (interrupt_tx, interrupt_rx): (
-            mpsc::Sender<InterruptType>,
-            mpsc::Receiver<InterruptType>,
-        ) = mpsc::channel();
-
+        GumBoi::with_renderer(Renderer::Scanline)
+    }
+    // Renderer::Fifo trades speed for mid-scanline accuracy
+    pub fn with_renderer(renderer: Renderer) -> GumBoi {
         GumBoi {
-            cpu: CPU::new(Arc::clone(&memory), Arc::clone(&ime)),
-            ppu: PPU::new(Arc::clone(&memory)),
-            interrupt_controller: InterruptController::new(
-                Arc::clone(&memory),
-                Arc::clone(&ime),
-                interrupt_rx,
-            ),
-            memory: memory,
-            state: GumBoiState::Active,
+            cpu: CPU::new(),
+            memory: Memory::with_renderer(renderer),
             cycle: 0,
+            state: GumBoiState::Active,
+            save_path: None,
+            last_save_flush: 0,
+            audio_recorder: None,
+            recording_error: None,
+            quit: Arc::new(AtomicBool::new(false)),
         }
     }
-    pub fn insert_cartridge(&mut self, cartridge_rom: Vec<u8>) {
-        self.memory.lock().unwrap().load_cartridge(cartridge_rom); //Load Catridge into GumBoi ROM
-    }
-    pub fn start(&mut self) {
-        //self.memory.set_addr(0xff44,0x90);
-        let rst_addr: u16;
-        while self.cpu.get_state() == CPUState::Active {
-            // CPU state step
-            self.cpu.execute();
-            // Check for interrupts
-            match self.interrupt_controller.execute() {
-                Some(rst_addr) => self.cpu.rst(rst_addr),
-                None => {}
+    // Rejects dumps with a malformed header, see Cartridge::new
+    pub fn insert_cartridge(&mut self, cartridge_rom: Vec<u8>) -> Result<(), GumBoiError> {
+        self.memory.load_cartridge(Cartridge::new(cartridge_rom)?); //Load Catridge into GumBoi ROM
+        Ok(())
+    }
+    // Runs until the CPU stops, the audio recording fails or the system exits, or until the CPU
+    // locks up on an illegal opcode. Call start again to carry on, after a button press for a
+    // stopped system
+    pub fn start(&mut self) -> Result<RunStatus, GumBoiError> {
+        while self.state == GumBoiState::Active && !self.quit.load(Ordering::Relaxed) {
+            self.step()?;
+            if self.cpu.get_state() == CPUState::Stop {
+                // Nothing changes until the frontend sends input, so bring the files up to date
+                self.flush_save()?;
+                self.flush_audio_recording();
+                if self.recording_error.is_none() {
+                    return Ok(RunStatus::Stopped);
+                }
+            }
+            if let Some(error) = self.recording_error.take() {
+                return Ok(RunStatus::RecordingFailed(error));
             }
         }
+        Ok(RunStatus::Exited)
+    }
+    // Storing true makes start return RunStatus::Exited, after which exit writes the save
+    pub fn get_quit_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.quit)
+    }
+    // Runs a single CPU step : one instruction, one interrupt dispatch or one idle HALT cycle.
+    // The rest of the system keeps ticking while the CPU is halted, but a stopped CPU
+    // never ticks the bus so the PPU and timer freeze along with it
+    pub fn step(&mut self) -> Result<(), GumBoiError> {
+        // CPU state step, pending interrupts are dispatched by the CPU itself
+        self.cpu.execute(&mut self.memory)?;
+        self.cycle += self.cpu.get_cycles();
+        if self.cycle - self.last_save_flush >= SAVE_FLUSH_INTERVAL {
+            self.last_save_flush = self.cycle;
+            self.flush_save()?;
+            self.flush_audio_recording();
+        }
+        Ok(())
+    }
+    // Ties battery RAM to a .sav file, loading it if it exists. A missing file is a fresh save
+    pub fn load_save(&mut self, path: impl AsRef<Path>) -> Result<(), GumBoiError> {
+        let path = path.as_ref();
+        let cartridge = match self.memory.get_cartridge_mut() {
+            Some(cartridge) if cartridge.has_battery() => cartridge,
+            _ => return Ok(()),
+        };
+        match fs::read(path) {
+            Ok(save_data) => cartridge.load_save_data(&save_data)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(save_file_error(path, error)),
+        }
+        self.save_path = Some(path.to_path_buf());
+        Ok(())
+    }
+    // Writes battery RAM back to the .sav file, if it changed since the last flush. The data
+    // goes to game.sav.tmp first and is renamed over the save, so a crash mid-write keeps the
+    // old one. A failed write leaves the cartridge dirty and the next flush tries again
+    pub fn flush_save(&mut self) -> Result<(), GumBoiError> {
+        let (path, cartridge) = match (&self.save_path, self.memory.get_cartridge_mut()) {
+            (Some(path), Some(cartridge)) if cartridge.is_save_dirty() => (path, cartridge),
+            _ => return Ok(()),
+        };
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+        fs::write(&temp_path, cartridge.get_save_data())
+            .and_then(|_| fs::rename(&temp_path, path))
+            .map_err(|error| save_file_error(path, error))?;
+        cartridge.mark_saved();
+        Ok(())
     }
-    pub fn exit(&self) {
-        //TODO
+    // Motor changes since the last call, always empty for carts without rumble
+    pub fn drain_rumble_events(&mut self) -> Vec<RumbleEvent> {
+        self.memory
+            .get_cartridge_mut()
+            .map_or_else(Vec::new, |cartridge| cartridge.drain_rumble_events())
+    }
+    // Input from frontends and bots. The set_buttons mask has A, B, Select and Start in bits 0-3
+    // and Right, Left, Up and Down in bits 4-7, a set bit meaning pressed
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.memory.set_button(button, pressed);
+    }
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.memory.set_buttons(buttons);
+    }
+    // Audio is generated at DEFAULT_SAMPLE_RATE unless the host asks for another rate
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.memory.get_apu_mut().set_sample_rate(sample_rate);
+    }
+    // Fills buffer with interleaved left/right samples produced since the last call, returning
+    // how many were written. The host pulls at its own pace, up to one second is kept queued
+    pub fn drain_audio(&mut self, buffer: &mut [i16]) -> usize {
+        self.memory.get_apu_mut().drain_samples(buffer)
+    }
+    // Streams the mixed stereo output to a WAV file at the current sample rate, which should not
+    // change until the recording stops. With split_channels each channel also gets a mono file
+    pub fn record_audio(
+        &mut self,
+        path: impl AsRef<Path>,
+        split_channels: bool,
+    ) -> Result<(), GumBoiError> {
+        self.stop_audio_recording()?;
+        let apu = self.memory.get_apu_mut();
+        self.audio_recorder = Some(AudioRecorder::create(
+            path.as_ref(),
+            split_channels,
+            apu.get_sample_rate(),
+        )?);
+        apu.start_recording();
+        Ok(())
+    }
+    // Also reports a failure start has not handed back yet
+    pub fn stop_audio_recording(&mut self) -> Result<(), GumBoiError> {
+        self.flush_audio_recording();
+        self.memory.get_apu_mut().stop_recording();
+        self.audio_recorder = None;
+        match self.recording_error.take() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+    // A recording that cannot be written (full disk, WAV size limit) is closed with what it has
+    // so far, instead of taking the game down with it
+    fn flush_audio_recording(&mut self) {
+        let recorder = match self.audio_recorder.as_mut() {
+            Some(recorder) => recorder,
+            None => return,
+        };
+        let frames = self.memory.get_apu_mut().drain_recorded();
+        if let Err(error) = recorder
+            .write_frames(&frames)
+            .and_then(|_| recorder.flush())
+        {
+            let _ = recorder.flush();
+            self.audio_recorder = None;
+            self.memory.get_apu_mut().stop_recording();
+            self.recording_error = Some(error);
+        }
+    }
+    // Last completed frame, row major 160x144 shades from 0 (lightest) to 3 (darkest).
+    // The frame count goes up by one at the start of every VBlank
+    pub fn get_framebuffer(&self) -> &[u8; FRAME_SIZE] {
+        self.memory.get_ppu().get_frame()
+    }
+    pub fn get_frame_count(&self) -> usize {
+        self.memory.get_ppu().get_frame_count()
+    }
+    // T-cycles elapsed since power on
+    pub fn get_cycles(&self) -> usize {
+        self.cycle
+    }
+    // Decodes the instruction at addr, returning its mnemonic and length in bytes
+    pub fn disassemble(&self, addr: u16) -> (String, u16) {
+        opcodes::disassemble(|addr| self.memory.get_addr(addr), addr)
+    }
+    pub fn exit(&mut self) -> Result<(), GumBoiError> {
+        self.state = GumBoiState::Exit;
+        self.flush_save()?;
+        self.stop_audio_recording()
+    }
+}
+
+fn save_file_error(path: &Path, error: io::Error) -> GumBoiError {
+    GumBoiError::SaveFile {
+        path: path.display().to_string(),
+        message: error.to_string(),
+    }
+}
+
+impl Default for GumBoi {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[cfg(test)]
 mod interrupt_tests {
-    use super::{GumBoi, GumBoiState, InterruptController, InterruptType, Memory, CPU, PPU};
-    use std::sync::{mpsc, Arc, Mutex};
+    use super::{Cartridge, GumBoi, Memory};
+    use crate::bus::Bus;
+    use crate::cartridge::fix_checksums;
+    use crate::cpu::CPUState;
+    use crate::interrupt::InterruptType;
 
+    // Bytes below 0x8000 make up the cartridge ROM, and the boot ROM is unmapped
     macro_rules! memory {
         ($($addr:expr=>$value:expr),*) => {
             {
+                let mut rom = vec![0u8; 0x8000];
                 let mut mem = Memory::new();
+                mem.set_addr(0xFF50, 0x01);
                 $(
-                    mem.set_addr($addr, $value);
+                    if $addr < 0x8000 {
+                        rom[$addr] = $value;
+                    } else {
+                        mem.set_addr($addr, $value);
+                    }
                 )*
+                fix_checksums(&mut rom);
+                mem.load_cartridge(Cartridge::new(rom).unwrap());
                 mem
             }
         }
     }
 
+    fn with_memory(memory: Memory) -> GumBoi {
+        let mut gumboi = GumBoi::new();
+        gumboi.memory = memory;
+        gumboi
+    }
+
+    // HALT no longer stops the run loop, so the tests step the system until the CPU halts
+    fn run_until_halt(gumboi: &mut GumBoi) {
+        while gumboi.cpu.get_state() == CPUState::Active {
+            gumboi.step().unwrap();
+        }
+    }
+
     #[test]
     fn test_interrupt_rst_joypad() {
-        let memory = Arc::new(Mutex::new(
-            memory!(0x0=>0x31,0x1=>0xFF,0x2=>0xFE,0x60=>0x76,0xFFFF=>0b00010000),
-        ));
-        let ime = Arc::new(Mutex::new(true));
-        let (interrupt_tx, interrupt_rx): (
-            mpsc::Sender<InterruptType>,
-            mpsc::Receiver<InterruptType>,
-        ) = mpsc::channel();
-
-        let mut gumboi = GumBoi {
-            cpu: CPU::new(Arc::clone(&memory), Arc::clone(&ime)),
-            ppu: PPU::new(Arc::clone(&memory)),
-            interrupt_controller: InterruptController::new(
-                Arc::clone(&memory),
-                Arc::clone(&ime),
-                interrupt_rx,
-            ),
-            memory: memory,
-            state: GumBoiState::Active,
-            cycle: 0,
-        };
+        let mut gumboi = with_memory(
+            memory!(0x0=>0x31,0x1=>0xFF,0x2=>0xFE,0x3=>0xFB,0x60=>0x76,0xFFFF=>0b00010000),
+        );
 
-        interrupt_tx.send(InterruptType::JOYPAD).unwrap();
-        interrupt_tx.send(InterruptType::EXIT).unwrap();
+        gumboi.memory.interrupts().request(InterruptType::JOYPAD);
 
-        gumboi.start();
+        run_until_halt(&mut gumboi);
 
         assert_eq!(gumboi.cpu.get_registers().pc, 0x61);
     }
     #[test]
     fn test_interrupt_rst_serial() {
-        let memory = Arc::new(Mutex::new(
-            memory!(0x0=>0x31,0x1=>0xFF,0x2=>0xFE,0x58=>0x76,0xFFFF=>0b00001000),
-        ));
-        let ime = Arc::new(Mutex::new(true));
-        let (interrupt_tx, interrupt_rx): (
-            mpsc::Sender<InterruptType>,
-            mpsc::Receiver<InterruptType>,
-        ) = mpsc::channel();
-
-        let mut gumboi = GumBoi {
-            cpu: CPU::new(Arc::clone(&memory), Arc::clone(&ime)),
-            ppu: PPU::new(Arc::clone(&memory)),
-            interrupt_controller: InterruptController::new(
-                Arc::clone(&memory),
-                Arc::clone(&ime),
-                interrupt_rx,
-            ),
-            memory: memory,
-            state: GumBoiState::Active,
-            cycle: 0,
-        };
+        let mut gumboi = with_memory(
+            memory!(0x0=>0x31,0x1=>0xFF,0x2=>0xFE,0x3=>0xFB,0x58=>0x76,0xFFFF=>0b00001000),
+        );
 
-        interrupt_tx.send(InterruptType::JOYPAD).unwrap();
-        interrupt_tx.send(InterruptType::EXIT).unwrap();
+        gumboi.memory.interrupts().request(InterruptType::SERIAL);
 
-        gumboi.start();
+        run_until_halt(&mut gumboi);
 
         assert_eq!(gumboi.cpu.get_registers().pc, 0x59);
     }
     #[test]
     fn test_interrupt_rst_timer() {
-        let memory = Arc::new(Mutex::new(
-            memory!(0x0=>0x31,0x1=>0xFF,0x2=>0xFE,0x50=>0x76,0xFFFF=>0b00000100),
-        ));
-        let ime = Arc::new(Mutex::new(true));
-        let (interrupt_tx, interrupt_rx): (
-            mpsc::Sender<InterruptType>,
-            mpsc::Receiver<InterruptType>,
-        ) = mpsc::channel();
-
-        let mut gumboi = GumBoi {
-            cpu: CPU::new(Arc::clone(&memory), Arc::clone(&ime)),
-            ppu: PPU::new(Arc::clone(&memory)),
-            interrupt_controller: InterruptController::new(
-                Arc::clone(&memory),
-                Arc::clone(&ime),
-                interrupt_rx,
-            ),
-            memory: memory,
-            state: GumBoiState::Active,
-            cycle: 0,
-        };
+        let mut gumboi = with_memory(
+            memory!(0x0=>0x31,0x1=>0xFF,0x2=>0xFE,0x3=>0xFB,0x50=>0x76,0xFFFF=>0b00000100),
+        );
 
-        interrupt_tx.send(InterruptType::TIMER).unwrap();
-        interrupt_tx.send(InterruptType::EXIT).unwrap();
+        gumboi.memory.interrupts().request(InterruptType::TIMER);
 
-        gumboi.start();
+        run_until_halt(&mut gumboi);
 
         assert_eq!(gumboi.cpu.get_registers().pc, 0x51);
     }
     #[test]
     fn test_interrupt_rst_lcd_stat() {
-        let memory = Arc::new(Mutex::new(
-            memory!(0x0=>0x31,0x1=>0xFF,0x2=>0xFE,0x48=>0x76,0xFFFF=>0b00000010),
-        ));
-        let ime = Arc::new(Mutex::new(true));
-        let (interrupt_tx, interrupt_rx): (
-            mpsc::Sender<InterruptType>,
-            mpsc::Receiver<InterruptType>,
-        ) = mpsc::channel();
-
-        let mut gumboi = GumBoi {
-            cpu: CPU::new(Arc::clone(&memory), Arc::clone(&ime)),
-            ppu: PPU::new(Arc::clone(&memory)),
-            interrupt_controller: InterruptController::new(
-                Arc::clone(&memory),
-                Arc::clone(&ime),
-                interrupt_rx,
-            ),
-            memory: memory,
-            state: GumBoiState::Active,
-            cycle: 0,
-        };
+        let mut gumboi = with_memory(
+            memory!(0x0=>0x31,0x1=>0xFF,0x2=>0xFE,0x3=>0xFB,0x48=>0x76,0xFFFF=>0b00000010),
+        );
 
-        interrupt_tx.send(InterruptType::LCD_STAT).unwrap();
-        interrupt_tx.send(InterruptType::EXIT).unwrap();
+        gumboi.memory.interrupts().request(InterruptType::LCD_STAT);
 
-        gumboi.start();
+        run_until_halt(&mut gumboi);
 
         assert_eq!(gumboi.cpu.get_registers().pc, 0x49);
     }
     #[test]
     fn test_interrupt_rst_vblank() {
-        let memory = Arc::new(Mutex::new(
-            memory!(0x0=>0x31,0x1=>0xFF,0x2=>0xFE,0x40=>0x76,0xFFFF=>0b00000001),
-        ));
-        let ime = Arc::new(Mutex::new(true));
-        let (interrupt_tx, interrupt_rx): (
-            mpsc::Sender<InterruptType>,
-            mpsc::Receiver<InterruptType>,
-        ) = mpsc::channel();
-
-        let mut gumboi = GumBoi {
-            cpu: CPU::new(Arc::clone(&memory), Arc::clone(&ime)),
-            ppu: PPU::new(Arc::clone(&memory)),
-            interrupt_controller: InterruptController::new(
-                Arc::clone(&memory),
-                Arc::clone(&ime),
-                interrupt_rx,
-            ),
-            memory: memory,
-            state: GumBoiState::Active,
-            cycle: 0,
-        };
+        let mut gumboi = with_memory(
+            memory!(0x0=>0x31,0x1=>0xFF,0x2=>0xFE,0x3=>0xFB,0x40=>0x76,0xFFFF=>0b00000001),
+        );
 
-        interrupt_tx.send(InterruptType::JOYPAD).unwrap();
-        interrupt_tx.send(InterruptType::EXIT).unwrap();
+        gumboi.memory.interrupts().request(InterruptType::VBLANK);
 
-        gumboi.start();
+        run_until_halt(&mut gumboi);
 
         assert_eq!(gumboi.cpu.get_registers().pc, 0x41);
     }
 }
+
+#[cfg(test)]
+mod stop_tests {
+    use super::{Button, GumBoi, RunStatus};
+    use crate::cartridge::fix_checksums;
+    use crate::cpu::CPUState;
+
+    // Runs program from 0x0000 with the boot ROM unmapped and the button group selected
+    fn with_program(program: &[u8]) -> GumBoi {
+        let mut rom = vec![0u8; 0x8000];
+        rom[..program.len()].copy_from_slice(program);
+        fix_checksums(&mut rom);
+        let mut gumboi = GumBoi::new();
+        gumboi.insert_cartridge(rom).unwrap();
+        gumboi.memory.set_addr(0xFF50, 0x01);
+        gumboi.memory.set_addr(0xFF00, 0x10);
+        gumboi
+    }
+
+    #[test]
+    fn start_returns_when_the_cpu_stops() {
+        // STOP, then INC A and HALT
+        let mut gumboi = with_program(&[0x10, 0x00, 0x3C, 0x76]);
+        assert_eq!(gumboi.start(), Ok(RunStatus::Stopped));
+        let cycles = gumboi.get_cycles();
+        assert_eq!(gumboi.start(), Ok(RunStatus::Stopped));
+        assert_eq!(gumboi.get_cycles(), cycles);
+    }
+    #[test]
+    fn button_press_wakes_stop() {
+        let mut gumboi = with_program(&[0x10, 0x00, 0x3C, 0x76]);
+        (0..10).for_each(|_| gumboi.step().unwrap());
+        assert_eq!(gumboi.cpu.get_state(), CPUState::Stop);
+        gumboi.set_button(Button::Start, true);
+        while gumboi.cpu.get_state() != CPUState::Halt {
+            gumboi.step().unwrap();
+        }
+        assert_eq!(gumboi.cpu.get_registers().a, 0x01);
+    }
+}
+
+#[cfg(test)]
+mod boot_tests {
+    use super::GumBoi;
+    use crate::cartridge::{fix_checksums, NINTENDO_LOGO};
+
+    // The boot ROM waits for LY to reach VBlank before scrolling the logo
+    #[test]
+    fn boot_rom_hands_over_to_the_cartridge() {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x104..0x134].copy_from_slice(&NINTENDO_LOGO);
+        fix_checksums(&mut rom);
+        let mut gumboi = GumBoi::new();
+        gumboi.insert_cartridge(rom).unwrap();
+        while gumboi.cpu.get_registers().pc != 0x100 {
+            gumboi.step().unwrap();
+            // Hardware hands over after about 23.4 million T-cycles
+            assert!(gumboi.get_cycles() < 24_000_000);
+        }
+        assert_eq!(gumboi.memory.get_addr(0xFF50), 0xFF);
+        assert_eq!(gumboi.memory.get_addr(0x0000), 0x00);
+    }
+}
+
+// dmg-acid2 is not vendored. Point DMG_ACID2_ROM at dmg-acid2.gb and DMG_ACID2_REFERENCE at
+// its reference-dmg.png converted to a binary PGM (convert reference-dmg.png reference.pgm),
+// then run cargo test -- --ignored
+#[cfg(test)]
+mod acid2_tests {
+    use super::{GumBoi, FRAME_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH};
+    use std::env;
+    use std::fs;
+
+    // Grey levels of a P5 PGM, header comments included
+    fn read_pgm(bytes: &[u8]) -> Vec<u8> {
+        let mut fields = Vec::new();
+        let mut index = 0;
+        while fields.len() < 4 {
+            match bytes[index] {
+                b'#' => {
+                    while bytes[index] != b'\n' {
+                        index += 1;
+                    }
+                }
+                byte if byte.is_ascii_whitespace() => {}
+                _ => {
+                    let start = index;
+                    while !bytes[index].is_ascii_whitespace() {
+                        index += 1;
+                    }
+                    fields.push(String::from_utf8_lossy(&bytes[start..index]).to_string());
+                }
+            }
+            index += 1;
+        }
+        assert_eq!(
+            fields,
+            vec![
+                "P5".to_string(),
+                SCREEN_WIDTH.to_string(),
+                SCREEN_HEIGHT.to_string(),
+                "255".to_string()
+            ]
+        );
+        bytes[index..index + FRAME_SIZE].to_vec()
+    }
+
+    #[test]
+    #[ignore]
+    fn dmg_acid2_matches_the_reference() {
+        let rom = fs::read(env::var("DMG_ACID2_ROM").expect("DMG_ACID2_ROM")).unwrap();
+        let reference = fs::read(env::var("DMG_ACID2_REFERENCE").expect("DMG_ACID2_REFERENCE"));
+        // The reference uses the 0xFF, 0xAA, 0x55, 0x00 greys for shades 0 to 3
+        let reference = read_pgm(&reference.unwrap())
+            .iter()
+            .map(|grey| 3 - ((*grey as u16 + 0x2A) / 0x55) as u8)
+            .collect::<Vec<u8>>();
+        let mut gumboi = GumBoi::new();
+        gumboi.insert_cartridge(rom).unwrap();
+        // The boot ROM takes about 6 seconds, the test screen is static well before 10
+        while gumboi.get_frame_count() < 600 {
+            gumboi.step().unwrap();
+        }
+        assert!(gumboi.get_framebuffer()[..] == reference[..]);
+    }
+}
+
+#[cfg(test)]
+mod error_tests {
+    use super::{GumBoi, GumBoiError};
+    use crate::cartridge::fix_checksums;
+
+    fn rom(size_code: u8) -> Vec<u8> {
+        let mut rom = vec![0x0; 0x8000 << size_code];
+        rom[0x148] = size_code;
+        fix_checksums(&mut rom);
+        rom
+    }
+
+    #[test]
+    fn insert_cartridge_rejects_oversized_rom() {
+        let mut gumboi = GumBoi::new();
+        assert_eq!(gumboi.insert_cartridge(rom(0x00)), Ok(()));
+        assert_eq!(
+            gumboi.insert_cartridge(rom(0x01)),
+            Err(GumBoiError::CartridgeTooLarge {
+                size: 0x10000,
+                max: 0x8000
+            })
+        );
+    }
+    #[test]
+    fn insert_cartridge_rejects_broken_header() {
+        let mut gumboi = GumBoi::new();
+        let mut cartridge_rom = rom(0x00);
+        cartridge_rom[0x14D] ^= 0xFF;
+        assert!(matches!(
+            gumboi.insert_cartridge(cartridge_rom),
+            Err(GumBoiError::HeaderChecksumMismatch { .. })
+        ));
+    }
+    #[test]
+    fn start_returns_illegal_opcode() {
+        let mut gumboi = GumBoi::new();
+        let mut cartridge_rom = rom(0x00);
+        cartridge_rom[0x0] = 0xD3;
+        gumboi.insert_cartridge(cartridge_rom).unwrap();
+        gumboi.memory.set_addr(0xFF50, 0x01);
+        assert_eq!(
+            gumboi.start(),
+            Err(GumBoiError::IllegalOpcode {
+                pc: 0x0,
+                opcode: 0xD3
+            })
+        );
+    }
+}
+
+#[cfg(test)]
+mod save_tests {
+    use super::{GumBoi, RunStatus, SAVE_FLUSH_INTERVAL};
+    use crate::cartridge::fix_checksums;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Duration;
+
+    // MBC1+RAM+BATTERY, spinning on JR -2 with the boot ROM unmapped
+    fn with_cartridge(cartridge_type: u8) -> GumBoi {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0] = 0x18;
+        rom[0x1] = 0xFE;
+        rom[0x147] = cartridge_type;
+        rom[0x149] = 0x02;
+        fix_checksums(&mut rom);
+        let mut gumboi = GumBoi::new();
+        gumboi.insert_cartridge(rom).unwrap();
+        gumboi.memory.set_addr(0xFF50, 0x01);
+        gumboi.memory.set_addr(0x0000, 0x0A);
+        gumboi
+    }
+
+    fn save_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("gumboi_{}_{}.sav", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn exit_flushes_and_load_restores() {
+        let path = save_path("exit");
+        let mut gumboi = with_cartridge(0x03);
+        gumboi.load_save(&path).unwrap();
+        gumboi.memory.set_addr(0xA123, 0x42);
+        gumboi.exit().unwrap();
+        assert_eq!(fs::read(&path).unwrap()[0x123], 0x42);
+
+        let mut gumboi = with_cartridge(0x03);
+        gumboi.load_save(&path).unwrap();
+        assert_eq!(gumboi.memory.get_addr(0xA123), 0x42);
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn quit_handle_ends_start_so_exit_can_save() {
+        let path = save_path("quit");
+        let mut gumboi = with_cartridge(0x03);
+        gumboi.load_save(&path).unwrap();
+        gumboi.memory.set_addr(0xA123, 0x42);
+        let quit = gumboi.get_quit_handle();
+        let signal = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            quit.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(gumboi.start(), Ok(RunStatus::Exited));
+        signal.join().unwrap();
+        gumboi.exit().unwrap();
+        assert_eq!(fs::read(&path).unwrap()[0x123], 0x42);
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn writes_are_flushed_periodically() {
+        let path = save_path("periodic");
+        let mut gumboi = with_cartridge(0x03);
+        gumboi.load_save(&path).unwrap();
+        gumboi.memory.set_addr(0xA000, 0x42);
+        while gumboi.get_cycles() < SAVE_FLUSH_INTERVAL {
+            assert!(!path.exists());
+            gumboi.step().unwrap();
+        }
+        assert_eq!(fs::read(&path).unwrap()[0x0], 0x42);
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn failed_flush_is_retried() {
+        let dir = std::env::temp_dir().join(format!("gumboi_retry_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("game.sav");
+        let mut gumboi = with_cartridge(0x03);
+        gumboi.load_save(&path).unwrap();
+        gumboi.memory.set_addr(0xA000, 0x42);
+        assert!(gumboi.flush_save().is_err());
+
+        fs::create_dir(&dir).unwrap();
+        gumboi.flush_save().unwrap();
+        assert_eq!(
+            (
+                fs::read(&path).unwrap()[0x0],
+                dir.join("game.sav.tmp").exists()
+            ),
+            (0x42, false)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn carts_without_battery_are_not_saved() {
+        let path = save_path("no_battery");
+        let mut gumboi = with_cartridge(0x02);
+        gumboi.load_save(&path).unwrap();
+        gumboi.memory.set_addr(0xA000, 0x42);
+        gumboi.exit().unwrap();
+        assert!(!path.exists());
+    }
+}
+
+#[cfg(test)]
+mod audio_tests {
+    use super::{GumBoi, GumBoiError, RunStatus, SAVE_FLUSH_INTERVAL};
+    use std::fs;
+    use std::sync::atomic::Ordering;
+
+    // The path a quitting frontend takes : the quit handle ends start, then exit runs
+    #[test]
+    fn quitting_finishes_the_recording() {
+        let path = std::env::temp_dir().join(format!("gumboi_record_{}.wav", std::process::id()));
+        let mut gumboi = GumBoi::new();
+        gumboi.set_sample_rate(8_000);
+        gumboi.record_audio(&path, false).unwrap();
+        while gumboi.get_cycles() < 4_194_304 / 10 {
+            gumboi.step().unwrap();
+        }
+        gumboi.get_quit_handle().store(true, Ordering::Relaxed);
+        assert_eq!(gumboi.start(), Ok(RunStatus::Exited));
+        gumboi.exit().unwrap();
+        let wav = fs::read(&path).unwrap();
+        let data_size = u32::from_le_bytes([wav[0x28], wav[0x29], wav[0x2A], wav[0x2B]]);
+        // 800 stereo samples of 2 bytes each
+        assert_eq!((wav.len() - 44, data_size), (3200, 3200));
+        fs::remove_file(&path).unwrap();
+    }
+    // Writes to /dev/full fail once the buffered header is flushed
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn failed_recording_is_reported_and_the_game_carries_on() {
+        let mut gumboi = GumBoi::new();
+        gumboi.record_audio("/dev/full", false).unwrap();
+        assert!(matches!(
+            gumboi.start(),
+            Ok(RunStatus::RecordingFailed(GumBoiError::AudioFile { .. }))
+        ));
+        assert!(gumboi.audio_recorder.is_none());
+        let cycles = gumboi.get_cycles();
+        while gumboi.get_cycles() < cycles + SAVE_FLUSH_INTERVAL {
+            gumboi.step().unwrap();
+        }
+        assert_eq!(gumboi.stop_audio_recording(), Ok(()));
+    }
+}
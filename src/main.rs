@@ -3,25 +3,130 @@ The syntax is 2015 specific but the core concept remains the same in 2018 as wel
 TL;DR : We need to explicitely build the module tree in Rust, there's no implicit
 mapping between file system to module tree*/
 
-#![allow(unused)]
-
-use std::convert::TryInto;
 use std::env;
+use std::error::Error;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use gumboi::{GumBoi, RunStatus};
 
-use gumboi::GumBoi;
+const USAGE: &str = "Usage : gumboi [--record-audio <file.wav> [--split-channels]] <cartridge rom>";
+
+struct Options {
+    catridge_rom_file_loc: String,
+    record_audio: Option<PathBuf>,
+    split_channels: bool, // Also record each APU channel on its own
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Option<Options> {
+    let mut catridge_rom_file_loc = None;
+    let mut record_audio = None;
+    let mut split_channels = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record-audio" => record_audio = Some(PathBuf::from(args.next()?)),
+            "--split-channels" => split_channels = true,
+            _ if catridge_rom_file_loc.is_none() => catridge_rom_file_loc = Some(arg),
+            _ => return None,
+        }
+    }
+    if split_channels && record_audio.is_none() {
+        return None;
+    }
+    Some(Options {
+        catridge_rom_file_loc: catridge_rom_file_loc?,
+        record_audio,
+        split_channels,
+    })
+}
 
 fn main() {
-    let catridge_rom_file_loc: String = env::args().nth(1).unwrap();
-    let catridge_rom: Vec<u8> = read_bin(catridge_rom_file_loc);
+    let options = match parse_args(env::args().skip(1)) {
+        Some(options) => options,
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(error) = run(options) {
+        eprintln!("GumBoi : {}", error);
+        process::exit(1);
+    }
+}
+
+fn run(options: Options) -> Result<(), Box<dyn Error>> {
+    let catridge_rom_file_loc = options.catridge_rom_file_loc;
+    // Battery saves live next to the ROM, as game.gb -> game.sav
+    let save_file_loc = Path::new(&catridge_rom_file_loc).with_extension("sav");
+    let catridge_rom: Vec<u8> = read_bin(catridge_rom_file_loc)?;
 
     let mut gumboi = GumBoi::new();
-    gumboi.insert_cartridge(catridge_rom);
-    gumboi.start();
-    gumboi.exit();
+    signals::install(gumboi.get_quit_handle());
+    gumboi.insert_cartridge(catridge_rom)?;
+    gumboi.load_save(save_file_loc)?;
+    if let Some(record_audio) = options.record_audio {
+        gumboi.record_audio(record_audio, options.split_channels)?;
+    }
+    // There is no input to wake a stopped CPU yet, so a stopped game idles until quit
+    let result = loop {
+        match gumboi.start() {
+            Ok(RunStatus::Stopped) => thread::sleep(Duration::from_millis(16)),
+            Ok(RunStatus::RecordingFailed(error)) => {
+                eprintln!("GumBoi : audio recording stopped, {}", error)
+            }
+            result => break result,
+        }
+    };
+    gumboi.exit()?;
+    result?;
+    Ok(())
+}
+
+fn read_bin(file_name: String) -> Result<Vec<u8>, std::io::Error> {
+    fs::read(file_name)
 }
 
-fn read_bin(file_name: String) -> Vec<u8> {
-    let binary = fs::read(file_name).unwrap();
-    binary
+// Ctrl-C and SIGTERM end the run loop, so the save and the audio recording get written on the
+// way out. Without a handler the process is killed before exit runs
+#[cfg(unix)]
+mod signals {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, OnceLock};
+
+    const SIGINT: i32 = 2;
+    const SIGTERM: i32 = 15;
+
+    static QUIT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+
+    // Only touches an atomic, which is safe from a signal handler
+    extern "C" fn request_quit(_signum: i32) {
+        if let Some(quit) = QUIT.get() {
+            quit.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn install(quit: Arc<AtomicBool>) {
+        if QUIT.set(quit).is_ok() {
+            // SAFETY: request_quit is async-signal-safe and lives for the whole program
+            unsafe {
+                signal(SIGINT, request_quit);
+                signal(SIGTERM, request_quit);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod signals {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    pub fn install(_quit: Arc<AtomicBool>) {}
 }
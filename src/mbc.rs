@@ -0,0 +1,651 @@
+/* Memory Bank Controllers
+0000-7FFF writes never reach the ROM, they program the controller's registers instead.
+The cartridge hands its ROM and RAM to the controller, which picks the bank for each access.
+*/
+
+use std::collections::VecDeque;
+
+use super::rtc::RTC;
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+pub const MBC2_RAM_SIZE: usize = 0x200;
+const MAX_RUMBLE_EVENTS: usize = 64; // Older motor changes are dropped if nobody drains them
+
+// Motor changes on rumble carts, queued until the frontend drains them
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum RumbleEvent {
+    Started,
+    Stopped,
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub trait MBC {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8;
+    fn write_rom(&mut self, addr: u16, value: u8);
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8;
+    // Whether the write landed, disabled or missing RAM ignores it
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, value: u8) -> bool;
+    // Only MBC3 carts with a timer have a clock to persist
+    fn rtc(&self) -> Option<&RTC> {
+        None
+    }
+    fn rtc_mut(&mut self) -> Option<&mut RTC> {
+        None
+    }
+    fn drain_rumble_events(&mut self) -> Vec<RumbleEvent> {
+        Vec::new()
+    }
+}
+
+// Banks wrap around the chips actually present, open bus when there is no chip at all
+fn read_banked(memory: &[u8], bank: usize, bank_size: usize, offset: usize) -> u8 {
+    match memory.len() {
+        0 => 0xFF,
+        len => memory[(bank * bank_size + offset) % len],
+    }
+}
+
+fn write_banked(
+    memory: &mut [u8],
+    bank: usize,
+    bank_size: usize,
+    offset: usize,
+    value: u8,
+) -> bool {
+    let len = memory.len();
+    if len != 0 {
+        memory[(bank * bank_size + offset) % len] = value;
+    }
+    len != 0
+}
+
+// SECTION No MBC : up to 32 KiB ROM and an optional unbanked 8 KiB RAM
+pub struct RomOnly;
+
+impl MBC for RomOnly {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        rom.get(addr as usize).copied().unwrap_or(0xFF)
+    }
+    fn write_rom(&mut self, _addr: u16, _value: u8) {}
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        read_banked(ram, 0, RAM_BANK_SIZE, offset as usize)
+    }
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, value: u8) -> bool {
+        write_banked(ram, 0, RAM_BANK_SIZE, offset as usize, value)
+    }
+}
+// !SECTION
+
+/* SECTION MBC1 : up to 2 MiB ROM and 32 KiB RAM
+0000-1FFF : RAMG, 0x_A in the low nibble enables RAM
+2000-3FFF : BANK1, low 5 bits of the ROM bank, 0 reads as 1
+4000-5FFF : BANK2, 2 bits, upper ROM bank bits or the RAM bank
+6000-7FFF : MODE, 1 also applies BANK2 to 0000-3FFF and to RAM
+MBC1M multicarts wire BANK2 one bit lower, leaving 4 bits of BANK1 for 16 bank games
+*/
+pub struct MBC1 {
+    ram_enabled: bool,
+    bank1: u8,
+    bank2: u8,
+    mode: bool,
+    multicart: bool,
+}
+
+impl MBC1 {
+    pub fn new(multicart: bool) -> MBC1 {
+        MBC1 {
+            ram_enabled: false,
+            bank1: 0x01,
+            bank2: 0x00,
+            mode: false,
+            multicart,
+        }
+    }
+    fn bank2_shift(&self) -> u8 {
+        match self.multicart {
+            true => 4,
+            false => 5,
+        }
+    }
+    fn rom_bank_low(&self) -> usize {
+        match self.mode {
+            true => (self.bank2 << self.bank2_shift()) as usize,
+            false => 0x0,
+        }
+    }
+    fn rom_bank_high(&self) -> usize {
+        let bank1 = match self.multicart {
+            true => self.bank1 & 0x0F,
+            false => self.bank1,
+        };
+        ((self.bank2 << self.bank2_shift()) | bank1) as usize
+    }
+    fn ram_bank(&self) -> usize {
+        match self.mode {
+            true => self.bank2 as usize,
+            false => 0x0,
+        }
+    }
+}
+
+impl MBC for MBC1 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => read_banked(rom, self.rom_bank_low(), ROM_BANK_SIZE, addr as usize),
+            _ => read_banked(
+                rom,
+                self.rom_bank_high(),
+                ROM_BANK_SIZE,
+                (addr - 0x4000) as usize,
+            ),
+        }
+    }
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            // The zero check sees all 5 bits, even on multicarts
+            0x2000..=0x3FFF => {
+                self.bank1 = match value & 0x1F {
+                    0x00 => 0x01,
+                    bank => bank,
+                }
+            }
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            _ => self.mode = value & 0x01 == 0x01,
+        }
+    }
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        match self.ram_enabled {
+            true => read_banked(ram, self.ram_bank(), RAM_BANK_SIZE, offset as usize),
+            false => 0xFF,
+        }
+    }
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, value: u8) -> bool {
+        self.ram_enabled
+            && write_banked(ram, self.ram_bank(), RAM_BANK_SIZE, offset as usize, value)
+    }
+}
+// !SECTION
+
+/* SECTION MBC3 : up to 2 MiB ROM, 32 KiB RAM and an optional real time clock
+0000-1FFF : RAM and RTC enable, 0x0A
+2000-3FFF : ROM bank, 7 bits, 0 reads as 1
+4000-5FFF : 0x00-0x03 selects a RAM bank, 0x08-0x0C an RTC register
+6000-7FFF : Writing 0x00 then 0x01 latches the RTC
+*/
+pub struct MBC3 {
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_select: u8,
+    rtc: Option<RTC>,
+}
+
+impl MBC3 {
+    pub fn new(rtc: Option<RTC>) -> MBC3 {
+        MBC3 {
+            ram_enabled: false,
+            rom_bank: 0x01,
+            ram_select: 0x00,
+            rtc,
+        }
+    }
+}
+
+impl MBC for MBC3 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => read_banked(rom, 0, ROM_BANK_SIZE, addr as usize),
+            _ => read_banked(
+                rom,
+                self.rom_bank as usize,
+                ROM_BANK_SIZE,
+                (addr - 0x4000) as usize,
+            ),
+        }
+    }
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                self.rom_bank = match value & 0x7F {
+                    0x00 => 0x01,
+                    bank => bank,
+                }
+            }
+            0x4000..=0x5FFF => self.ram_select = value & 0x0F,
+            _ => {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write_latch(value);
+                }
+            }
+        }
+    }
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        match (self.ram_enabled, self.ram_select, self.rtc.as_ref()) {
+            (false, _, _) => 0xFF,
+            (true, 0x00..=0x03, _) => read_banked(
+                ram,
+                self.ram_select as usize,
+                RAM_BANK_SIZE,
+                offset as usize,
+            ),
+            (true, 0x08..=0x0C, Some(rtc)) => rtc.read(self.ram_select),
+            _ => 0xFF,
+        }
+    }
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, value: u8) -> bool {
+        match (self.ram_enabled, self.ram_select, self.rtc.as_mut()) {
+            (true, 0x00..=0x03, _) => write_banked(
+                ram,
+                self.ram_select as usize,
+                RAM_BANK_SIZE,
+                offset as usize,
+                value,
+            ),
+            (true, 0x08..=0x0C, Some(rtc)) => {
+                rtc.write(self.ram_select, value);
+                true
+            }
+            _ => false,
+        }
+    }
+    fn rtc(&self) -> Option<&RTC> {
+        self.rtc.as_ref()
+    }
+    fn rtc_mut(&mut self) -> Option<&mut RTC> {
+        self.rtc.as_mut()
+    }
+}
+// !SECTION
+
+/* SECTION MBC2 : up to 256 KiB ROM and a built in 512 x 4 bit RAM
+0000-3FFF : Address bit 8 clear : RAM enable, 0x_A
+            Address bit 8 set   : ROM bank, 4 bits, 0 reads as 1
+A000-A1FF : RAM, upper nibble reads as 1s, echoed up to BFFF
+*/
+pub struct MBC2 {
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl MBC2 {
+    pub fn new() -> MBC2 {
+        MBC2 {
+            ram_enabled: false,
+            rom_bank: 0x01,
+        }
+    }
+}
+
+impl MBC for MBC2 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => read_banked(rom, 0, ROM_BANK_SIZE, addr as usize),
+            _ => read_banked(
+                rom,
+                self.rom_bank as usize,
+                ROM_BANK_SIZE,
+                (addr - 0x4000) as usize,
+            ),
+        }
+    }
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        match (addr, addr & 0x0100 == 0x0100) {
+            (0x0000..=0x3FFF, false) => self.ram_enabled = value & 0x0F == 0x0A,
+            (0x0000..=0x3FFF, true) => {
+                self.rom_bank = match value & 0x0F {
+                    0x00 => 0x01,
+                    bank => bank,
+                }
+            }
+            _ => {}
+        }
+    }
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        match self.ram_enabled {
+            true => 0xF0 | read_banked(ram, 0, MBC2_RAM_SIZE, offset as usize),
+            false => 0xFF,
+        }
+    }
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, value: u8) -> bool {
+        self.ram_enabled && write_banked(ram, 0, MBC2_RAM_SIZE, offset as usize, value & 0x0F)
+    }
+}
+// !SECTION
+
+/* SECTION MBC5 : up to 8 MiB ROM and 128 KiB RAM
+0000-1FFF : RAM enable, 0x0A
+2000-2FFF : ROM bank, low 8 bits, bank 0 is selectable
+3000-3FFF : ROM bank, bit 8
+4000-5FFF : RAM bank, 4 bits. Rumble carts drive the motor with bit 3 instead
+*/
+pub struct MBC5 {
+    ram_enabled: bool,
+    rom_bank: u16,
+    ram_bank: u8,
+    rumble: Option<bool>, // Motor state, on carts fitted with one
+    rumble_events: VecDeque<RumbleEvent>,
+}
+
+impl MBC5 {
+    pub fn new(rumble: bool) -> MBC5 {
+        MBC5 {
+            ram_enabled: false,
+            rom_bank: 0x01,
+            ram_bank: 0x00,
+            rumble: if rumble { Some(false) } else { None },
+            rumble_events: VecDeque::new(),
+        }
+    }
+}
+
+impl MBC for MBC5 {
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => read_banked(rom, 0, ROM_BANK_SIZE, addr as usize),
+            _ => read_banked(
+                rom,
+                self.rom_bank as usize,
+                ROM_BANK_SIZE,
+                (addr - 0x4000) as usize,
+            ),
+        }
+    }
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | ((value & 0x01) as u16) << 8
+            }
+            0x4000..=0x5FFF => match self.rumble {
+                Some(rumbling) => {
+                    self.ram_bank = value & 0x07;
+                    let motor = value & 0x08 == 0x08;
+                    if motor != rumbling {
+                        self.rumble = Some(motor);
+                        if self.rumble_events.len() == MAX_RUMBLE_EVENTS {
+                            self.rumble_events.pop_front();
+                        }
+                        self.rumble_events.push_back(match motor {
+                            true => RumbleEvent::Started,
+                            false => RumbleEvent::Stopped,
+                        });
+                    }
+                }
+                None => self.ram_bank = value & 0x0F,
+            },
+            _ => {}
+        }
+    }
+    fn read_ram(&self, ram: &[u8], offset: u16) -> u8 {
+        match self.ram_enabled {
+            true => read_banked(ram, self.ram_bank as usize, RAM_BANK_SIZE, offset as usize),
+            false => 0xFF,
+        }
+    }
+    fn write_ram(&mut self, ram: &mut [u8], offset: u16, value: u8) -> bool {
+        self.ram_enabled
+            && write_banked(
+                ram,
+                self.ram_bank as usize,
+                RAM_BANK_SIZE,
+                offset as usize,
+                value,
+            )
+    }
+    fn drain_rumble_events(&mut self) -> Vec<RumbleEvent> {
+        self.rumble_events.drain(..).collect()
+    }
+}
+// !SECTION
+
+// Scenarios from mooneye's emulator-only/mbc1 suite
+#[cfg(test)]
+mod mbc1_tests {
+    use super::{MBC, MBC1, RAM_BANK_SIZE, ROM_BANK_SIZE};
+
+    // Every ROM bank starts with its own number
+    fn rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0u8; banks * ROM_BANK_SIZE];
+        for bank in 0..banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn bits_bank1() {
+        let rom = rom(128);
+        let mut mbc = MBC1::new(false);
+        for (value, bank) in [
+            (0x00, 1),
+            (0x01, 1),
+            (0x02, 2),
+            (0x1F, 31),
+            (0x20, 1),
+            (0xE5, 5),
+        ]
+        .iter()
+        {
+            mbc.write_rom(0x2000, *value);
+            assert_eq!(mbc.read_rom(&rom, 0x4000), *bank);
+        }
+    }
+    #[test]
+    fn bits_bank2() {
+        let rom = rom(128);
+        let mut mbc = MBC1::new(false);
+        mbc.write_rom(0x2000, 0x03);
+        for (value, bank) in [(0x00, 3), (0x01, 35), (0x02, 67), (0x03, 99), (0xFE, 67)].iter() {
+            mbc.write_rom(0x4000, *value);
+            assert_eq!(mbc.read_rom(&rom, 0x4000), *bank);
+        }
+        // Bank 0x20 is unreachable from BANK1 alone
+        mbc.write_rom(0x2000, 0x00);
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 33);
+    }
+    #[test]
+    fn bits_mode() {
+        let rom = rom(128);
+        let mut mbc = MBC1::new(false);
+        mbc.write_rom(0x4000, 0x02);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 64);
+        mbc.write_rom(0x6000, 0xFE);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+    }
+    #[test]
+    fn rom_banks_wrap_to_rom_size() {
+        let rom = rom(32); // 512 KiB
+        let mut mbc = MBC1::new(false);
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_rom(0x2000, 0x05);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 5);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 0);
+    }
+    #[test]
+    fn bits_ramg() {
+        let mut ram = vec![0u8; RAM_BANK_SIZE];
+        let mut mbc = MBC1::new(false);
+        mbc.write_ram(&mut ram, 0x0, 0x12);
+        assert_eq!(mbc.read_ram(&ram, 0x0), 0xFF);
+        for (value, enabled) in [(0x0A, true), (0x1A, true), (0x0B, false), (0xA0, false)].iter() {
+            mbc.write_rom(0x0000, *value);
+            assert_eq!(mbc.read_ram(&ram, 0x0) != 0xFF, *enabled);
+        }
+        assert_eq!(ram[0x0], 0x0);
+    }
+    #[test]
+    fn ram_banking_needs_mode_1() {
+        let mut ram = vec![0u8; 4 * RAM_BANK_SIZE];
+        let mut mbc = MBC1::new(false);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x02);
+        mbc.write_ram(&mut ram, 0x10, 0x11);
+        mbc.write_rom(0x6000, 0x01);
+        mbc.write_ram(&mut ram, 0x10, 0x22);
+        assert_eq!((ram[0x10], ram[2 * RAM_BANK_SIZE + 0x10]), (0x11, 0x22));
+        assert_eq!(mbc.read_ram(&ram, 0x10), 0x22);
+    }
+    #[test]
+    fn multicart_wires_bank2_to_bit_4() {
+        let rom = rom(64); // 1 MiB, 4 games of 16 banks
+        let mut mbc = MBC1::new(true);
+        mbc.write_rom(0x2000, 0x13);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 3);
+        mbc.write_rom(0x4000, 0x02);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 35);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x0000), 32);
+        // BANK1 = 0x10 is not remapped, so it selects the first bank of the game
+        mbc.write_rom(0x2000, 0x10);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 32);
+    }
+}
+
+#[cfg(test)]
+mod mbc3_tests {
+    use super::{MBC, MBC3, RAM_BANK_SIZE, ROM_BANK_SIZE};
+    use crate::rtc::rtc_tests::FakeClock;
+    use crate::rtc::RTC;
+
+    #[test]
+    fn rom_bank_uses_7_bits() {
+        let mut rom = vec![0u8; 128 * ROM_BANK_SIZE];
+        rom[0x7F * ROM_BANK_SIZE] = 0x7F;
+        rom[ROM_BANK_SIZE] = 0x01;
+        let mut mbc = MBC3::new(None);
+        mbc.write_rom(0x2000, 0xFF);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x7F);
+        mbc.write_rom(0x2000, 0x80);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x01);
+    }
+    #[test]
+    fn ram_banks_and_rtc_share_the_window() {
+        let clock = FakeClock::default();
+        let mut ram = vec![0u8; 4 * RAM_BANK_SIZE];
+        let mut mbc = MBC3::new(Some(RTC::new(Box::new(clock.clone()))));
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x03);
+        mbc.write_ram(&mut ram, 0x0, 0x33);
+        mbc.write_rom(0x4000, 0x08);
+        mbc.write_ram(&mut ram, 0x0, 0x2A);
+        clock.advance(1);
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(
+            (ram[3 * RAM_BANK_SIZE], mbc.read_ram(&ram, 0x0)),
+            (0x33, 0x2B)
+        );
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0x0), 0xFF);
+    }
+    #[test]
+    fn rtc_registers_are_open_bus_without_a_timer() {
+        let mut ram = vec![0u8; RAM_BANK_SIZE];
+        let mut mbc = MBC3::new(None);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x08);
+        mbc.write_ram(&mut ram, 0x0, 0x12);
+        assert_eq!((mbc.read_ram(&ram, 0x0), ram[0x0]), (0xFF, 0x00));
+    }
+}
+
+#[cfg(test)]
+mod mbc2_tests {
+    use super::{MBC, MBC2, MBC2_RAM_SIZE, ROM_BANK_SIZE};
+
+    #[test]
+    fn address_bit_8_selects_the_register() {
+        let mut rom = vec![0u8; 16 * ROM_BANK_SIZE];
+        rom[0x0F * ROM_BANK_SIZE] = 0x0F;
+        let mut ram = vec![0u8; MBC2_RAM_SIZE];
+        let mut mbc = MBC2::new();
+        mbc.write_rom(0x2100, 0x0F);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x0F);
+        // Bit 8 clear enables RAM and leaves the ROM bank alone
+        mbc.write_rom(0x2000, 0x0A);
+        mbc.write_ram(&mut ram, 0x0, 0x05);
+        assert_eq!((mbc.read_rom(&rom, 0x4000), ram[0x0]), (0x0F, 0x05));
+    }
+    #[test]
+    fn ram_is_4_bit_and_echoed() {
+        let mut ram = vec![0u8; MBC2_RAM_SIZE];
+        let mut mbc = MBC2::new();
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_ram(&mut ram, 0x1FF, 0xAB);
+        assert_eq!(
+            (
+                ram[0x1FF],
+                mbc.read_ram(&ram, 0x1FF),
+                mbc.read_ram(&ram, 0x1FFF)
+            ),
+            (0x0B, 0xFB, 0xFB)
+        );
+    }
+}
+
+#[cfg(test)]
+mod mbc5_tests {
+    use super::{RumbleEvent, MAX_RUMBLE_EVENTS, MBC, MBC5, RAM_BANK_SIZE, ROM_BANK_SIZE};
+
+    #[test]
+    fn rom_bank_uses_9_bits() {
+        let mut rom = vec![0u8; 512 * ROM_BANK_SIZE];
+        rom[0x1FF * ROM_BANK_SIZE] = 0xAA;
+        rom[0x0] = 0x55;
+        let mut mbc = MBC5::new(false);
+        mbc.write_rom(0x2000, 0xFF);
+        mbc.write_rom(0x3000, 0x01);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0xAA);
+        // Unlike MBC1 and MBC3, bank 0 can be mapped at 4000
+        mbc.write_rom(0x2000, 0x00);
+        mbc.write_rom(0x3000, 0x00);
+        assert_eq!(mbc.read_rom(&rom, 0x4000), 0x55);
+    }
+    #[test]
+    fn ram_bank_uses_4_bits() {
+        let mut ram = vec![0u8; 16 * RAM_BANK_SIZE];
+        let mut mbc = MBC5::new(false);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0F);
+        mbc.write_ram(&mut ram, 0x0, 0x42);
+        assert_eq!(ram[15 * RAM_BANK_SIZE], 0x42);
+    }
+    #[test]
+    fn rumble_bit_drives_the_motor() {
+        let mut ram = vec![0u8; 8 * RAM_BANK_SIZE];
+        let mut mbc = MBC5::new(true);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0F);
+        mbc.write_rom(0x4000, 0x0B);
+        mbc.write_ram(&mut ram, 0x0, 0x42);
+        mbc.write_rom(0x4000, 0x03);
+        assert_eq!(
+            (mbc.drain_rumble_events(), ram[3 * RAM_BANK_SIZE]),
+            (vec![RumbleEvent::Started, RumbleEvent::Stopped], 0x42)
+        );
+        assert_eq!(mbc.drain_rumble_events(), vec![]);
+    }
+    #[test]
+    fn undrained_rumble_events_are_capped() {
+        let mut mbc = MBC5::new(true);
+        (0..MAX_RUMBLE_EVENTS + 1)
+            .for_each(|step| mbc.write_rom(0x4000, ((step as u8 + 1) & 0x1) << 3));
+        let events = mbc.drain_rumble_events();
+        // The first Started is gone, the latest change is still last
+        assert_eq!(
+            (events.len(), events.first(), events.last()),
+            (
+                MAX_RUMBLE_EVENTS,
+                Some(&RumbleEvent::Stopped),
+                Some(&RumbleEvent::Started)
+            )
+        );
+    }
+}
@@ -4,12 +4,13 @@ Arrays cannot be indexed with u16/u8 etc types which are dependednt on underlyin
 */
 
 const BOOT_ROM_SIZE: usize = 0x100;
-const CATRIDGE_ROM_SIZE: usize = 0x8000;
-const VRAM_SIZE: usize = 0x2000;
-const EXTERNAL_RAM_SIZE: usize = 0x2000;
+pub const VRAM_SIZE: usize = 0x2000;
 const RAM_SIZE: usize = 0x2000;
 const HRAM: usize = 0x7F;
-const OAM_RAM: usize = 0xA0;
+pub const OAM_RAM: usize = 0xA0;
+const IO_SIZE: usize = 0x80;
+
+const BOOT_ROM_DISABLE: u16 = 0xFF50;
 
 const BOOT_ROM: [u8; 256] = [
     0x31, 0xFE, 0xFF, 0xAF, 0x21, 0xFF, 0x9F, 0x32, 0xCB, 0x7C, 0x20, 0xFB, 0x21, 0x26, 0xFF, 0x0E,
@@ -32,49 +33,182 @@ const BOOT_ROM: [u8; 256] = [
 
 use std::fmt;
 
-#[derive(PartialEq, Copy, Clone)]
+use super::bus::Bus;
+use super::cartridge::Cartridge;
+use super::dma::{OamDma, DMA_ADDR};
+use super::interrupt::{InterruptController, IE_ADDR, IF_ADDR};
+use super::joypad::{Button, JoyPad, P1_ADDR};
+use super::ppu::{Renderer, PPU};
+use super::sound::APU;
+use super::timer::{Timer, DIV_ADDR, TAC_ADDR};
+
+/* Memory Map
+0000-00FF : Boot ROM, until a write to 0xFF50 unmaps it
+0000-7FFF : Cartridge ROM
+8000-9FFF : VRAM (PPU)
+A000-BFFF : External RAM
+C000-DFFF : WRAM
+E000-FDFF : Echo of C000-DDFF
+FE00-FE9F : OAM (PPU)
+FEA0-FEFF : Unusable
+FF00-FF7F : I/O registers
+FF00      : Joypad
+FF04-FF07 : Timer
+FF10-FF3F : APU registers and wave RAM
+FF46      : OAM DMA
+FF80-FFFE : HRAM
+FFFF      : IE (Interrupt Controller)
+*/
+
+// The MMU : owns the address space and routes every access to the component behind it
 pub struct Memory {
-    bank: [u8; 65536],
+    cartridge: Option<Cartridge>, // Open bus until one is inserted
     boot_rom: [u8; BOOT_ROM_SIZE],
+    boot_rom_enabled: bool,
+    wram: [u8; RAM_SIZE],
+    io: [u8; IO_SIZE], // I/O registers not yet owned by a component
+    hram: [u8; HRAM],
+    ppu: PPU,
+    dma: OamDma,
+    joypad: JoyPad,
+    timer: Timer,
+    apu: APU,
+    interrupt_controller: InterruptController,
 }
 
 impl Memory {
+    #[cfg(test)]
     pub fn new() -> Memory {
+        Memory::with_renderer(Renderer::Scanline)
+    }
+    pub fn with_renderer(renderer: Renderer) -> Memory {
         Memory {
-            bank: [0u8; 65536],
+            cartridge: None,
             boot_rom: BOOT_ROM,
+            boot_rom_enabled: true,
+            wram: [0u8; RAM_SIZE],
+            io: [0u8; IO_SIZE],
+            hram: [0u8; HRAM],
+            ppu: PPU::with_renderer(renderer),
+            dma: OamDma::new(),
+            joypad: JoyPad::new(),
+            timer: Timer::new(),
+            apu: APU::new(),
+            interrupt_controller: InterruptController::new(),
         }
     }
     pub fn get_addr(&self, addr: u16) -> u8 {
-        if addr > 0x00ff {
-            self.bank[addr as usize]
-        } else {
-            match self.bank[0xff50] {
-                0x0 => self.boot_rom[addr as usize],
-                _ => self.bank[addr as usize],
-            }
+        match addr {
+            0x0000..=0x00FF if self.boot_rom_enabled => self.boot_rom[addr as usize],
+            0x0000..=0x7FFF => self.cartridge.as_ref().map_or(0xFF, |c| c.read_rom(addr)),
+            0x8000..=0x9FFF => self.ppu.read_vram(addr - 0x8000),
+            0xA000..=0xBFFF => self
+                .cartridge
+                .as_ref()
+                .map_or(0xFF, |c| c.read_ram(addr - 0xA000)),
+            0xC000..=0xDFFF => self.wram[(addr - 0xC000) as usize],
+            0xE000..=0xFDFF => self.wram[(addr - 0xE000) as usize],
+            0xFE00..=0xFE9F if self.dma.is_active() => 0xFF,
+            0xFE00..=0xFE9F => self.ppu.read_oam(addr - 0xFE00),
+            0xFEA0..=0xFEFF => 0x00,
+            IF_ADDR | IE_ADDR => self.interrupt_controller.read_register(addr),
+            DMA_ADDR => self.dma.read_register(),
+            P1_ADDR => self.joypad.read_register(),
+            DIV_ADDR..=TAC_ADDR => self.timer.read_register(addr),
+            0xFF10..=0xFF3F => self.apu.read_register(addr),
+            0xFF40..=0xFF4B => self.ppu.read_register(addr),
+            BOOT_ROM_DISABLE => 0xFF,
+            0xFF01..=0xFF7F => self.io[(addr - 0xFF00) as usize],
+            _ => self.hram[(addr - 0xFF80) as usize],
         }
     }
     pub fn set_addr(&mut self, addr: u16, val: u8) {
-        if addr > 0x00ff {
-            self.bank[addr as usize] = val;
-        } else {
-            match self.bank[0xff50] {
-                0x0 => self.boot_rom[addr as usize] = val,
-                _ => self.bank[addr as usize] = val,
+        match addr {
+            0x0000..=0x7FFF => {
+                if let Some(cartridge) = self.cartridge.as_mut() {
+                    cartridge.write_rom(addr, val);
+                }
             }
-        }
-    }
-    //TODO : Better API
-    pub fn load_cartridge(&mut self, cartridge_rom: Vec<u8>) {
-        match cartridge_rom {
-            cartridge_rom if cartridge_rom.len() < 0x8000 => {
-                for (index, val) in cartridge_rom.iter().enumerate() {
-                    self.bank[index] = *val;
+            0x8000..=0x9FFF => self.ppu.write_vram(addr - 0x8000, val),
+            0xA000..=0xBFFF => {
+                if let Some(cartridge) = self.cartridge.as_mut() {
+                    cartridge.write_ram(addr - 0xA000, val);
+                }
+            }
+            0xC000..=0xDFFF => self.wram[(addr - 0xC000) as usize] = val,
+            0xE000..=0xFDFF => self.wram[(addr - 0xE000) as usize] = val,
+            0xFE00..=0xFE9F => self.ppu.write_oam(addr - 0xFE00, val),
+            0xFEA0..=0xFEFF => {}
+            IF_ADDR | IE_ADDR => self.interrupt_controller.write_register(addr, val),
+            DMA_ADDR => self.dma.write_register(val),
+            P1_ADDR => self
+                .joypad
+                .write_register(val, &mut self.interrupt_controller),
+            DIV_ADDR..=TAC_ADDR => self.timer.write_register(addr, val),
+            0xFF10..=0xFF3F => self.apu.write_register(addr, val),
+            0xFF40..=0xFF4B => self.ppu.write_register(addr, val),
+            // Unmapping the boot ROM is one way, only a reset maps it back
+            BOOT_ROM_DISABLE => {
+                if val != 0x0 {
+                    self.boot_rom_enabled = false;
                 }
             }
-            _ => panic!("Inserted Cartridge Exceeds 32Kb!"),
-        };
+            0xFF01..=0xFF7F => self.io[(addr - 0xFF00) as usize] = val,
+            _ => self.hram[(addr - 0xFF80) as usize] = val,
+        }
+    }
+    pub fn load_cartridge(&mut self, cartridge: Cartridge) {
+        self.cartridge = Some(cartridge);
+    }
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.joypad
+            .set_button(button, pressed, &mut self.interrupt_controller);
+    }
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.joypad
+            .set_buttons(buttons, &mut self.interrupt_controller);
+    }
+    pub fn get_apu_mut(&mut self) -> &mut APU {
+        &mut self.apu
+    }
+    pub fn get_ppu(&self) -> &PPU {
+        &self.ppu
+    }
+    pub fn get_cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        self.cartridge.as_mut()
+    }
+}
+
+// The CPU's view of the address space, minus OAM and the bus OAM DMA is reading from
+impl Bus for Memory {
+    fn read(&self, addr: u16) -> u8 {
+        match self.dma.blocks(addr) {
+            true => 0xFF,
+            false => self.get_addr(addr),
+        }
+    }
+    fn write(&mut self, addr: u16, value: u8) {
+        if !self.dma.blocks(addr) {
+            self.set_addr(addr, value);
+        }
+    }
+    fn tick(&mut self) {
+        self.ppu.tick(&mut self.interrupt_controller);
+        self.timer.tick(&mut self.interrupt_controller);
+        self.apu.tick();
+        if let Some((source, offset)) = self.dma.step() {
+            let value = self.get_addr(source);
+            self.ppu.write_oam(offset, value);
+        }
+    }
+    fn interrupts(&mut self) -> &mut InterruptController {
+        &mut self.interrupt_controller
+    }
+    fn reset_divider(&mut self) {
+        self.timer.reset_divider();
+    }
+    fn get_joypad_lines(&self) -> u8 {
+        self.joypad.get_lines()
     }
 }
 
@@ -83,3 +217,168 @@ impl fmt::Debug for Memory {
         write!(f, "")
     }
 }
+
+#[cfg(test)]
+mod memory_tests {
+    use super::{Button, Memory};
+    use crate::bus::Bus;
+    use crate::cartridge::{fix_checksums, Cartridge};
+
+    // 32 KiB ROM + 8 KiB RAM
+    fn cartridge(fill: u8) -> Cartridge {
+        let mut rom = vec![fill; 0x8000];
+        rom[0x134..0x150].iter_mut().for_each(|byte| *byte = 0x0);
+        rom[0x147] = 0x08;
+        rom[0x149] = 0x02;
+        fix_checksums(&mut rom);
+        Cartridge::new(rom).unwrap()
+    }
+
+    #[test]
+    fn boot_rom_overlays_cartridge_until_disabled() {
+        let mut memory = Memory::new();
+        memory.load_cartridge(cartridge(0xAA));
+        assert_eq!((memory.get_addr(0x0), memory.get_addr(0x100)), (0x31, 0xAA));
+        memory.set_addr(0xFF50, 0x01);
+        memory.set_addr(0xFF50, 0x00);
+        assert_eq!(memory.get_addr(0x0), 0xAA);
+    }
+    #[test]
+    fn rom_writes_do_not_modify_rom() {
+        let mut memory = Memory::new();
+        memory.load_cartridge(cartridge(0xAA));
+        memory.set_addr(0x4000, 0x55);
+        assert_eq!(memory.get_addr(0x4000), 0xAA);
+    }
+    #[test]
+    fn missing_cartridge_reads_open_bus() {
+        let memory = Memory::new();
+        assert_eq!(
+            (memory.get_addr(0x4000), memory.get_addr(0xA000)),
+            (0xFF, 0xFF)
+        );
+    }
+    #[test]
+    fn echo_ram_mirrors_wram() {
+        let mut memory = Memory::new();
+        memory.set_addr(0xC123, 0x12);
+        memory.set_addr(0xFDFF, 0x34);
+        assert_eq!(
+            (memory.get_addr(0xE123), memory.get_addr(0xDDFF)),
+            (0x12, 0x34)
+        );
+    }
+    #[test]
+    fn unusable_region_reads_zero() {
+        let mut memory = Memory::new();
+        memory.set_addr(0xFEA0, 0x12);
+        assert_eq!(memory.get_addr(0xFEA0), 0x00);
+    }
+    #[test]
+    fn regions_are_independent() {
+        let mut memory = Memory::new();
+        memory.load_cartridge(cartridge(0x0));
+        for (value, addr) in [0x8000, 0xA000, 0xC000, 0xFE00, 0xFF80, 0xFFFF]
+            .iter()
+            .enumerate()
+        {
+            memory.set_addr(*addr, value as u8 + 1);
+        }
+        assert_eq!(
+            [0x8000, 0xA000, 0xC000, 0xFE00, 0xFF80, 0xFFFF]
+                .iter()
+                .map(|addr| memory.get_addr(*addr))
+                .collect::<Vec<u8>>(),
+            vec![1, 2, 3, 4, 5, 6]
+        );
+    }
+    #[test]
+    fn interrupt_flags_route_to_controller() {
+        let mut memory = Memory::new();
+        memory.set_addr(0xFF0F, 0xFF);
+        assert_eq!(memory.get_addr(0xFF0F), 0xFF);
+        memory.set_addr(0xFF0F, 0x01);
+        assert_eq!(memory.get_addr(0xFF0F), 0xE1);
+    }
+    #[test]
+    fn joypad_press_requests_interrupt() {
+        let mut memory = Memory::new();
+        memory.set_addr(0xFFFF, 0x10);
+        memory.set_addr(0xFF00, 0x10);
+        memory.set_button(Button::Start, true);
+        assert_eq!(
+            (memory.get_addr(0xFF00), memory.get_addr(0xFF0F)),
+            (0xD7, 0xF0)
+        );
+    }
+    #[test]
+    fn timer_overflow_requests_interrupt() {
+        let mut memory = Memory::new();
+        memory.set_addr(0xFFFF, 0x04);
+        memory.set_addr(0xFF06, 0x80);
+        memory.set_addr(0xFF05, 0xFF);
+        memory.set_addr(0xFF07, 0x05);
+        (0..5).for_each(|_| memory.tick());
+        assert_eq!(
+            (memory.get_addr(0xFF05), memory.get_addr(0xFF0F)),
+            (0x80, 0xE4)
+        );
+    }
+    #[test]
+    fn oam_dma_copies_160_bytes() {
+        let mut memory = Memory::new();
+        for offset in 0..0xA0 {
+            memory.set_addr(0xC100 + offset, offset as u8);
+        }
+        memory.write(0xFF46, 0xC1);
+        for _ in 0..160 {
+            assert_eq!(memory.get_addr(0xFE00), 0xFF);
+            memory.tick();
+        }
+        assert_eq!(
+            (
+                memory.get_addr(0xFE00),
+                memory.get_addr(0xFE9F),
+                memory.get_addr(0xFF46)
+            ),
+            (0x00, 0x9F, 0xC1)
+        );
+    }
+    #[test]
+    fn oam_dma_blocks_the_source_bus() {
+        let mut memory = Memory::new();
+        memory.set_addr(0xC000, 0x12);
+        memory.write(0xFF46, 0xC0);
+        memory.write(0xC001, 0x34);
+        memory.write(0x8000, 0x56);
+        memory.write(0xFF80, 0x78);
+        assert_eq!(
+            (
+                memory.read(0xC000),
+                memory.get_addr(0xC001),
+                memory.read(0x8000),
+                memory.read(0xFF80)
+            ),
+            (0xFF, 0x00, 0x56, 0x78)
+        );
+        (0..160).for_each(|_| memory.tick());
+        assert_eq!(memory.read(0xC000), 0x12);
+    }
+    #[test]
+    fn io_and_ie_stay_reachable_during_oam_dma() {
+        let mut memory = Memory::new();
+        memory.write(0xFF46, 0xC0);
+        memory.write(0xFFFF, 0x05);
+        memory.write(0xFF07, 0x05);
+        memory.write(0xFF0F, 0x01);
+        assert_eq!(
+            (
+                memory.read(0xFFFF),
+                memory.read(0xFF07),
+                memory.read(0xFF0F),
+                memory.read(0xFF46)
+            ),
+            (0x05, 0xFD, 0xE1, 0xC0)
+        );
+    }
+}
@@ -17,13 +17,71 @@ CALL B6 FF at 0x6D FOR Super Mario
 
 // This is a test PR whaaaaaaat!?
 
-use super::GumBoi;
-use super::Memory;
-
-use std::sync::{Arc, Mutex};
+use super::bus::M_CYCLE;
+use super::interrupt::{InterruptController, InterruptType};
+use super::memory::{OAM_RAM, VRAM_SIZE};
 
 const LCDC: u16 = 0xFF40;
+const STAT: u16 = 0xFF41;
+const LY: u16 = 0xFF44;
+const LYC: u16 = 0xFF45;
+const SCY: u16 = 0xFF42;
+const SCX: u16 = 0xFF43;
+const BGP: u16 = 0xFF47;
+const OBP0: u16 = 0xFF48;
+const OBP1: u16 = 0xFF49;
+const WY: u16 = 0xFF4A;
+const WX: u16 = 0xFF4B;
+const LCD_REGISTERS: usize = 0x0C; // LCDC SCY SCX STAT LY LYC DMA BGP OBP0 OBP1 WY WX
+
+/* Timing : 154 lines of 456 dots, 70224 dots per frame
+Lines 0-143   : OAM scan (80 dots) -> Drawing (172 dots) -> HBlank (rest of the line)
+Lines 144-153 : VBlank
+The first line after the LCD is turned on spends its OAM scan dots in HBlank instead.
+*/
+const DOTS_PER_LINE: usize = 456;
+const OAM_SCAN_DOTS: usize = 80;
+const DRAWING_DOTS: usize = 172;
+const VBLANK_LINE: u8 = 144;
+const LINES_PER_FRAME: u8 = 154;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+pub const FRAME_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
+
+// LCDC
+const BG_ENABLE: u8 = 0b0000_0001; // Also gates the window on DMG
+const OBJ_ENABLE: u8 = 0b0000_0010;
+const OBJ_SIZE: u8 = 0b0000_0100; // 0 : 8x8, 1 : 8x16
+const BG_TILE_MAP: u8 = 0b0000_1000; // 0 : 9800, 1 : 9C00
+const TILE_DATA: u8 = 0b0001_0000; // 0 : 8800 signed, 1 : 8000 unsigned
+const WINDOW_ENABLE: u8 = 0b0010_0000;
+const WINDOW_TILE_MAP: u8 = 0b0100_0000;
+const LCD_ENABLE: u8 = 0b1000_0000;
+const LYC_EQUAL: u8 = 0b0000_0100; // STAT
+const HBLANK_SOURCE: u8 = 0b0000_1000;
+const VBLANK_SOURCE: u8 = 0b0001_0000;
+const OAM_SCAN_SOURCE: u8 = 0b0010_0000;
+const LYC_SOURCE: u8 = 0b0100_0000;
+
+const SPRITES_PER_LINE: usize = 10;
+// OAM entry flags
+const PALETTE: u8 = 0b0001_0000;
+const X_FLIP: u8 = 0b0010_0000;
+const Y_FLIP: u8 = 0b0100_0000;
+const BG_PRIORITY: u8 = 0b1000_0000; // BG colors 1-3 are drawn over the sprite
 
+// Scanline draws each line in one go at the start of HBlank, with a fixed 172 dot mode 3.
+// Fifo runs the pixel fetchers dot by dot : slower, but mid-line register writes take effect
+// and mode 3 stretches with SCX, the window and sprites like on hardware
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Renderer {
+    Scanline,
+    Fifo,
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 enum PPUModes {
     OAMSCAN, //OAM RAM --> Buffer
     DRAWING, //Buffer --> LCD
@@ -31,18 +89,1013 @@ enum PPUModes {
     VBLANK,  //PPU Does nothing | Takes place at the end of every frame
 }
 
+impl PPUModes {
+    // STAT bits 0-1
+    fn get_stat_bits(&self) -> u8 {
+        match self {
+            PPUModes::HBLANK => 0,
+            PPUModes::VBLANK => 1,
+            PPUModes::OAMSCAN => 2,
+            PPUModes::DRAWING => 3,
+        }
+    }
+}
+
+const FIFO_STARTUP_DOTS: u8 = 6; // The discarded first tile fetch
+const SPRITE_FETCH_DOTS: u8 = 6;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum FetcherStep {
+    Tile,
+    DataLow,
+    DataHigh,
+    Push,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+struct SpritePixel {
+    color: u8, // 0 is transparent
+    flags: u8, // OAM flags of the sprite it came from
+}
+
+// Mode 3 state of the FIFO renderer, reset at the start of every line
+struct PixelFifo {
+    x: u8,       // Pixels pushed to the LCD on this line
+    discard: u8, // SCX % 8 pixels dropped at the start of the line
+    startup: u8,
+    fetcher: FetcherStep,
+    fetcher_dots: u8,
+    tile_x: u8,
+    tile: u8,
+    data_low: u8,
+    data_high: u8,
+    window: bool,
+    // Background FIFO as a pair of shift registers, like the hardware
+    bg_low: u8,
+    bg_high: u8,
+    bg_len: u8,
+    sprites: [SpritePixel; 8],
+    sprite_fetch: Option<(usize, u8)>, // Index into the line's sprites and dots left
+    fetched_sprites: u16,              // Bit per line sprite
+    done: bool,
+}
+
+impl PixelFifo {
+    fn new() -> PixelFifo {
+        PixelFifo {
+            x: 0,
+            discard: 0,
+            startup: FIFO_STARTUP_DOTS,
+            fetcher: FetcherStep::Tile,
+            fetcher_dots: 0,
+            tile_x: 0,
+            tile: 0,
+            data_low: 0,
+            data_high: 0,
+            window: false,
+            bg_low: 0,
+            bg_high: 0,
+            bg_len: 0,
+            sprites: [SpritePixel::default(); 8],
+            sprite_fetch: None,
+            fetched_sprites: 0,
+            done: false,
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct PPU {
-    buffer: [u8; 16],
     mode: PPUModes,
-    memory: Arc<Mutex<Memory>>,
+    dots: usize,      // Position within the current line
+    stat_line: bool,  // STAT interrupts fire on the rising edge of the OR of the enabled sources
+    first_line: bool, // First line after the LCD is enabled, which has no OAM scan
+    vram: [u8; VRAM_SIZE],
+    oam: [u8; OAM_RAM],
+    registers: [u8; LCD_REGISTERS], // 0xFF40 - 0xFF4B
+    back_buffer: [u8; FRAME_SIZE],  // Shades 0-3, written a line at a time
+    frame: [u8; FRAME_SIZE],        // Last completed frame
+    frame_count: usize,
+    window_line: u8,          // The window only advances on lines where it was drawn
+    line_sprites: Vec<usize>, // OAM entries picked by the OAM scan, in drawing priority order
+    renderer: Renderer,
+    fifo: PixelFifo,
 }
 
 impl PPU {
-    pub fn new(memory: Arc<Mutex<Memory>>) -> PPU {
+    pub fn new() -> PPU {
+        PPU::with_renderer(Renderer::Scanline)
+    }
+    pub fn with_renderer(renderer: Renderer) -> PPU {
         PPU {
-            buffer: [0u8; 16],
-            mode: PPUModes::OAMSCAN,
-            memory: memory,
+            mode: PPUModes::HBLANK,
+            dots: 0,
+            stat_line: false,
+            first_line: false,
+            vram: [0u8; VRAM_SIZE],
+            oam: [0u8; OAM_RAM],
+            registers: [0u8; LCD_REGISTERS],
+            back_buffer: [0u8; FRAME_SIZE],
+            frame: [0u8; FRAME_SIZE],
+            frame_count: 0,
+            window_line: 0,
+            line_sprites: Vec::with_capacity(SPRITES_PER_LINE),
+            renderer,
+            fifo: PixelFifo::new(),
+        }
+    }
+    pub fn get_frame(&self) -> &[u8; FRAME_SIZE] {
+        &self.frame
+    }
+    pub fn get_frame_count(&self) -> usize {
+        self.frame_count
+    }
+    pub fn read_vram(&self, offset: u16) -> u8 {
+        self.vram[offset as usize]
+    }
+    pub fn write_vram(&mut self, offset: u16, value: u8) {
+        self.vram[offset as usize] = value;
+    }
+    pub fn read_oam(&self, offset: u16) -> u8 {
+        self.oam[offset as usize]
+    }
+    pub fn write_oam(&mut self, offset: u16, value: u8) {
+        self.oam[offset as usize] = value;
+    }
+    fn get_register(&self, addr: u16) -> u8 {
+        self.registers[(addr - LCDC) as usize]
+    }
+    fn set_register(&mut self, addr: u16, value: u8) {
+        self.registers[(addr - LCDC) as usize] = value;
+    }
+    fn is_lcd_enabled(&self) -> bool {
+        self.get_register(LCDC) & LCD_ENABLE == LCD_ENABLE
+    }
+    // STAT bit 7 is unused and reads back as 1, bits 0-2 are live PPU state
+    pub fn read_register(&self, addr: u16) -> u8 {
+        match addr {
+            STAT => {
+                let coincidence = match self.get_register(LY) == self.get_register(LYC) {
+                    true => LYC_EQUAL,
+                    false => 0x0,
+                };
+                0x80 | (self.get_register(STAT) & 0x78) | coincidence | self.mode.get_stat_bits()
+            }
+            _ => self.get_register(addr),
+        }
+    }
+    // LY and the STAT mode/coincidence bits are driven by the PPU, the CPU cannot write them
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            LY => {}
+            STAT => self.set_register(STAT, value & 0x78),
+            // Turning the LCD off parks the PPU at the start of line 0 in HBlank
+            LCDC if value & LCD_ENABLE == 0x0 => {
+                self.set_register(LCDC, value);
+                self.set_register(LY, 0x0);
+                self.dots = 0;
+                self.mode = PPUModes::HBLANK;
+            }
+            // The first line after turning it back on stays in HBlank for the 80 dots of the
+            // missing OAM scan, so it never raises the mode 2 STAT interrupt
+            LCDC if !self.is_lcd_enabled() => {
+                self.set_register(LCDC, value);
+                self.first_line = true;
+            }
+            _ => self.set_register(addr, value),
+        }
+    }
+    // Advances the PPU by one M-cycle. The scanline renderer steps 4 dots at once, as all of its
+    // mode boundaries fall on a multiple of 4 dots
+    pub fn tick(&mut self, interrupts: &mut InterruptController) {
+        if !self.is_lcd_enabled() {
+            return;
+        }
+        match self.renderer {
+            Renderer::Scanline => self.step(M_CYCLE, interrupts),
+            Renderer::Fifo => (0..M_CYCLE).for_each(|_| self.step(1, interrupts)),
+        }
+    }
+    fn step(&mut self, dots: usize, interrupts: &mut InterruptController) {
+        self.dots += dots;
+        if self.dots == DOTS_PER_LINE {
+            self.dots = 0;
+            self.first_line = false;
+            self.set_register(LY, (self.get_register(LY) + 1) % LINES_PER_FRAME);
+        }
+        let drawing_done = match self.renderer {
+            Renderer::Scanline => self.dots >= OAM_SCAN_DOTS + DRAWING_DOTS,
+            // The first line is in HBlank before drawing as well as after
+            Renderer::Fifo => {
+                (self.mode == PPUModes::HBLANK && self.dots > OAM_SCAN_DOTS)
+                    || (self.mode == PPUModes::DRAWING && self.fifo.done)
+            }
+        };
+        let mode = match (self.get_register(LY), self.dots) {
+            (line, _) if line >= VBLANK_LINE => PPUModes::VBLANK,
+            (_, dots) if dots < OAM_SCAN_DOTS && self.first_line => PPUModes::HBLANK,
+            (_, dots) if dots < OAM_SCAN_DOTS => PPUModes::OAMSCAN,
+            _ if drawing_done => PPUModes::HBLANK,
+            _ => PPUModes::DRAWING,
+        };
+        match (self.mode, mode, self.renderer) {
+            (PPUModes::OAMSCAN | PPUModes::HBLANK, PPUModes::DRAWING, Renderer::Scanline) => {
+                self.scan_oam()
+            }
+            (PPUModes::OAMSCAN | PPUModes::HBLANK, PPUModes::DRAWING, Renderer::Fifo) => {
+                self.scan_oam();
+                self.fifo = PixelFifo::new();
+                self.fifo.discard = self.get_register(SCX) % 8;
+            }
+            (PPUModes::DRAWING, PPUModes::HBLANK, Renderer::Scanline) => self.render_line(),
+            (PPUModes::VBLANK, PPUModes::VBLANK, _) => {}
+            (_, PPUModes::VBLANK, _) => {
+                self.frame = self.back_buffer;
+                self.frame_count += 1;
+                self.window_line = 0;
+                interrupts.request(InterruptType::VBLANK);
+            }
+            _ => {}
+        }
+        self.mode = mode;
+        if self.mode == PPUModes::DRAWING && self.renderer == Renderer::Fifo {
+            self.step_fifo();
+        }
+        self.update_stat_line(interrupts);
+    }
+    // VRAM offset of row y (0-255) of a BG/window tile, following the LCDC addressing mode
+    fn get_tile_row(&self, tile: u8, y: u8) -> usize {
+        let tile_addr = match self.get_register(LCDC) & TILE_DATA == TILE_DATA {
+            true => 0x8000 + tile as u16 * 16,
+            false => 0x9000u16.wrapping_add((tile as i8 as i16 * 16) as u16),
+        };
+        (tile_addr - 0x8000) as usize + (y as usize % 8) * 2
+    }
+    fn get_map_tile(&self, map: u16, tile_x: u8, y: u8) -> u8 {
+        let map_index = (y as u16 / 8) * 32 + (tile_x as u16 & 0x1F);
+        self.vram[(map + map_index - 0x8000) as usize]
+    }
+    // Color index 0-3 of a pixel in the 256x256 map at 9800 or 9C00
+    fn get_map_pixel(&self, map: u16, x: u8, y: u8) -> u8 {
+        let row = self.get_tile_row(self.get_map_tile(map, x / 8, y), y);
+        let bit = 7 - x % 8;
+        let low = (self.vram[row] >> bit) & 0x1;
+        let high = (self.vram[row + 1] >> bit) & 0x1;
+        high << 1 | low
+    }
+    fn get_tile_map(&self, bit: u8) -> u16 {
+        match self.get_register(LCDC) & bit == bit {
+            true => 0x9C00,
+            false => 0x9800,
+        }
+    }
+    fn get_sprite_height(&self) -> u16 {
+        match self.get_register(LCDC) & OBJ_SIZE == OBJ_SIZE {
+            true => 16,
+            false => 8,
+        }
+    }
+    // Picks the first 10 sprites in OAM overlapping LY. On DMG the smaller X wins, then OAM order
+    fn scan_oam(&mut self) {
+        let line = self.get_register(LY) as u16 + 16;
+        let height = self.get_sprite_height();
+        let mut sprites = (0..OAM_RAM / 4)
+            .filter(|sprite| {
+                let y = self.oam[sprite * 4] as u16;
+                line >= y && line < y + height
+            })
+            .take(SPRITES_PER_LINE)
+            .collect::<Vec<usize>>();
+        sprites.sort_by_key(|sprite| self.oam[sprite * 4 + 1]);
+        self.line_sprites = sprites;
+    }
+    // Color index 0-3 of the sprite at screen x, 0 being transparent
+    fn get_sprite_pixel(&self, sprite: usize, x: u8) -> u8 {
+        let entry = &self.oam[sprite * 4..sprite * 4 + 4];
+        let (y, sprite_x, flags) = (entry[0] as u16, entry[1] as i16, entry[3]);
+        let column = x as i16 + 8 - sprite_x;
+        if !(0..8).contains(&column) {
+            return 0;
+        }
+        let height = self.get_sprite_height();
+        let row = self.get_register(LY) as u16 + 16 - y;
+        let row = match flags & Y_FLIP == Y_FLIP {
+            true => height - 1 - row,
+            false => row,
+        };
+        let bit = match flags & X_FLIP == X_FLIP {
+            true => column,
+            false => 7 - column,
+        };
+        // 8x16 sprites ignore bit 0 of the tile number, the bottom half is the next tile
+        let tile = match height {
+            16 => entry[2] & 0xFE,
+            _ => entry[2],
+        };
+        let addr = tile as usize * 16 + row as usize * 2;
+        let low = (self.vram[addr] >> bit) & 0x1;
+        let high = (self.vram[addr + 1] >> bit) & 0x1;
+        high << 1 | low
+    }
+    // Draws the background, window and sprites for LY once the line has been transferred
+    fn render_line(&mut self) {
+        let line = self.get_register(LY);
+        let lcdc = self.get_register(LCDC);
+        let (scx, scy) = (self.get_register(SCX), self.get_register(SCY));
+        let (wx, wy) = (self.get_register(WX), self.get_register(WY));
+        let bgp = self.get_register(BGP);
+        let bg_enabled = lcdc & BG_ENABLE == BG_ENABLE;
+        let window_visible =
+            bg_enabled && lcdc & WINDOW_ENABLE == WINDOW_ENABLE && line >= wy && wx <= 166;
+        for x in 0..SCREEN_WIDTH as u8 {
+            // WX is offset by 7, the window starts at screen x = WX - 7
+            let color = match (bg_enabled, window_visible && x as u16 + 7 >= wx as u16) {
+                (false, _) => 0,
+                (true, true) => self.get_map_pixel(
+                    self.get_tile_map(WINDOW_TILE_MAP),
+                    (x as u16 + 7 - wx as u16) as u8,
+                    self.window_line,
+                ),
+                (true, false) => self.get_map_pixel(
+                    self.get_tile_map(BG_TILE_MAP),
+                    x.wrapping_add(scx),
+                    line.wrapping_add(scy),
+                ),
+            };
+            let shade = match bg_enabled {
+                true => (bgp >> (color * 2)) & 0x3,
+                false => 0,
+            };
+            let shade = match lcdc & OBJ_ENABLE == OBJ_ENABLE {
+                true => self.get_sprite_shade(x, color).unwrap_or(shade),
+                false => shade,
+            };
+            self.back_buffer[line as usize * SCREEN_WIDTH + x as usize] = shade;
+        }
+        if window_visible {
+            self.window_line += 1;
+        }
+    }
+    // The highest priority opaque sprite pixel settles the pixel, even when it hides behind the BG
+    fn get_sprite_shade(&self, x: u8, bg_color: u8) -> Option<u8> {
+        let (sprite, color) = self
+            .line_sprites
+            .iter()
+            .map(|sprite| (*sprite, self.get_sprite_pixel(*sprite, x)))
+            .find(|(_, color)| *color != 0)?;
+        let flags = self.oam[sprite * 4 + 3];
+        if flags & BG_PRIORITY == BG_PRIORITY && bg_color != 0 {
+            return None;
+        }
+        let palette = match flags & PALETTE == PALETTE {
+            true => self.get_register(OBP1),
+            false => self.get_register(OBP0),
+        };
+        Some((palette >> (color * 2)) & 0x3)
+    }
+    // SECTION Pixel FIFO
+    fn is_window_triggered(&self) -> bool {
+        let lcdc = self.get_register(LCDC);
+        let (wx, wy) = (self.get_register(WX), self.get_register(WY));
+        lcdc & BG_ENABLE == BG_ENABLE
+            && lcdc & WINDOW_ENABLE == WINDOW_ENABLE
+            && self.get_register(LY) >= wy
+            && wx <= 166
+            && self.fifo.x as u16 + 7 >= wx as u16
+    }
+    // The next sprite the fetcher has to stop for, in X then OAM order
+    fn get_sprite_hit(&self) -> Option<usize> {
+        match self.get_register(LCDC) & OBJ_ENABLE == OBJ_ENABLE {
+            true => (0..self.line_sprites.len()).find(|index| {
+                self.fifo.fetched_sprites & (1 << index) == 0
+                    && self.oam[self.line_sprites[*index] * 4 + 1] as u16 <= self.fifo.x as u16 + 8
+            }),
+            false => None,
+        }
+    }
+    // Runs one dot of mode 3
+    fn step_fifo(&mut self) {
+        if self.fifo.done {
+            return;
+        }
+        // The first tile is fetched twice, the first fetch is thrown away
+        if self.fifo.startup > 0 {
+            self.fifo.startup -= 1;
+            return;
+        }
+        if !self.fifo.window && self.is_window_triggered() {
+            self.fifo.window = true;
+            self.fifo.fetcher = FetcherStep::Tile;
+            self.fifo.fetcher_dots = 0;
+            self.fifo.tile_x = 0;
+            self.fifo.bg_len = 0;
+            // Fine scroll does not apply to the window, but a WX below 7 hides its first pixels
+            self.fifo.discard = 7u8.saturating_sub(self.get_register(WX));
+        }
+        if self.fifo.sprite_fetch.is_none() {
+            self.fifo.sprite_fetch = self
+                .get_sprite_hit()
+                .map(|index| (index, SPRITE_FETCH_DOTS));
+        }
+        // Sprite fetches stall the pixel output, once the background fetch in flight completes
+        if let Some((index, dots)) = self.fifo.sprite_fetch {
+            match (self.fifo.fetcher, dots) {
+                (FetcherStep::Push, 1) => {
+                    self.merge_sprite(self.line_sprites[index]);
+                    self.fifo.fetched_sprites |= 1 << index;
+                    self.fifo.sprite_fetch = None;
+                }
+                (FetcherStep::Push, _) => self.fifo.sprite_fetch = Some((index, dots - 1)),
+                _ => self.step_fetcher(),
+            }
+            return;
+        }
+        self.step_fetcher();
+        if self.fifo.bg_len > 0 {
+            self.shift_pixel();
+        }
+    }
+    // Tile number, data low and data high take 2 dots each, then the row waits for an empty FIFO
+    fn step_fetcher(&mut self) {
+        let line = self.get_register(LY);
+        let (map, tile_x, y) = match self.fifo.window {
+            true => (
+                self.get_tile_map(WINDOW_TILE_MAP),
+                self.fifo.tile_x,
+                self.window_line,
+            ),
+            false => (
+                self.get_tile_map(BG_TILE_MAP),
+                (self.get_register(SCX) / 8).wrapping_add(self.fifo.tile_x),
+                line.wrapping_add(self.get_register(SCY)),
+            ),
+        };
+        self.fifo.fetcher_dots += 1;
+        match (self.fifo.fetcher, self.fifo.fetcher_dots) {
+            (FetcherStep::Tile, 2) => {
+                self.fifo.tile = self.get_map_tile(map, tile_x, y);
+                self.fifo.fetcher = FetcherStep::DataLow;
+            }
+            (FetcherStep::DataLow, 2) => {
+                self.fifo.data_low = self.vram[self.get_tile_row(self.fifo.tile, y)];
+                self.fifo.fetcher = FetcherStep::DataHigh;
+            }
+            (FetcherStep::DataHigh, 2) => {
+                self.fifo.data_high = self.vram[self.get_tile_row(self.fifo.tile, y) + 1];
+                self.fifo.fetcher = FetcherStep::Push;
+            }
+            (FetcherStep::Push, _) if self.fifo.bg_len == 0 => {
+                self.fifo.bg_low = self.fifo.data_low;
+                self.fifo.bg_high = self.fifo.data_high;
+                self.fifo.bg_len = 8;
+                self.fifo.tile_x = self.fifo.tile_x.wrapping_add(1);
+                self.fifo.fetcher = FetcherStep::Tile;
+            }
+            _ => return,
+        }
+        self.fifo.fetcher_dots = 0;
+    }
+    // Sprite pixels only land on transparent slots, so earlier fetches keep priority
+    fn merge_sprite(&mut self, sprite: usize) {
+        let flags = self.oam[sprite * 4 + 3];
+        for slot in 0..8 {
+            let color = self.get_sprite_pixel(sprite, self.fifo.x.wrapping_add(slot as u8));
+            if self.fifo.sprites[slot].color == 0 && color != 0 {
+                self.fifo.sprites[slot] = SpritePixel { color, flags };
+            }
+        }
+    }
+    // Pops one pixel and mixes it with the sprite FIFO, reading the palettes as they are right now
+    fn shift_pixel(&mut self) {
+        let color = (self.fifo.bg_high >> 7) << 1 | self.fifo.bg_low >> 7;
+        self.fifo.bg_low <<= 1;
+        self.fifo.bg_high <<= 1;
+        self.fifo.bg_len -= 1;
+        if self.fifo.discard > 0 {
+            self.fifo.discard -= 1;
+            return;
+        }
+        let sprite = self.fifo.sprites[0];
+        self.fifo.sprites.rotate_left(1);
+        self.fifo.sprites[7] = SpritePixel::default();
+
+        let lcdc = self.get_register(LCDC);
+        let color = match lcdc & BG_ENABLE == BG_ENABLE {
+            true => color,
+            false => 0,
+        };
+        let shade = match (lcdc & BG_ENABLE == BG_ENABLE, self.get_register(BGP)) {
+            (true, bgp) => (bgp >> (color * 2)) & 0x3,
+            (false, _) => 0,
+        };
+        let sprite_visible = lcdc & OBJ_ENABLE == OBJ_ENABLE
+            && sprite.color != 0
+            && !(sprite.flags & BG_PRIORITY == BG_PRIORITY && color != 0);
+        let shade = match (sprite_visible, sprite.flags & PALETTE == PALETTE) {
+            (true, false) => (self.get_register(OBP0) >> (sprite.color * 2)) & 0x3,
+            (true, true) => (self.get_register(OBP1) >> (sprite.color * 2)) & 0x3,
+            (false, _) => shade,
+        };
+        let line = self.get_register(LY) as usize;
+        self.back_buffer[line * SCREEN_WIDTH + self.fifo.x as usize] = shade;
+        self.fifo.x += 1;
+        if self.fifo.x as usize == SCREEN_WIDTH {
+            self.fifo.done = true;
+            if self.fifo.window {
+                self.window_line += 1;
+            }
+        }
+    }
+    // !SECTION
+    fn update_stat_line(&mut self, interrupts: &mut InterruptController) {
+        let stat = self.get_register(STAT);
+        let source = |bit: u8, active: bool| stat & bit == bit && active;
+        let stat_line = source(HBLANK_SOURCE, self.mode == PPUModes::HBLANK)
+            || source(VBLANK_SOURCE, self.mode == PPUModes::VBLANK)
+            || source(OAM_SCAN_SOURCE, self.mode == PPUModes::OAMSCAN)
+            || source(LYC_SOURCE, self.get_register(LY) == self.get_register(LYC));
+        if stat_line && !self.stat_line {
+            interrupts.request(InterruptType::LCD_STAT);
+        }
+        self.stat_line = stat_line;
+    }
+}
+
+impl Default for PPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod ppu_tests {
+    use super::{LCDC, LY, LYC, PPU, STAT};
+    use crate::interrupt::{InterruptController, InterruptType};
+
+    fn enabled_ppu() -> (PPU, InterruptController) {
+        let mut ppu = PPU::new();
+        ppu.write_register(LCDC, 0x91);
+        (ppu, InterruptController::new())
+    }
+
+    // Runs the PPU for the given number of dots, returning the STAT mode bits seen on the way
+    fn run(ppu: &mut PPU, interrupts: &mut InterruptController, dots: usize) -> Vec<u8> {
+        let mut modes = Vec::new();
+        for _ in 0..dots / 4 {
+            ppu.tick(interrupts);
+            let mode = ppu.read_register(STAT) & 0x03;
+            if modes.last() != Some(&mode) {
+                modes.push(mode);
+            }
+        }
+        modes
+    }
+
+    #[test]
+    fn line_cycles_through_modes() {
+        let (mut ppu, mut interrupts) = enabled_ppu();
+        assert_eq!(run(&mut ppu, &mut interrupts, 456), vec![0, 3, 0, 2]);
+        assert_eq!(run(&mut ppu, &mut interrupts, 456), vec![2, 3, 0, 2]);
+        assert_eq!(ppu.read_register(LY), 2);
+    }
+    #[test]
+    fn mode_boundaries() {
+        let (mut ppu, mut interrupts) = enabled_ppu();
+        run(&mut ppu, &mut interrupts, 456);
+        run(&mut ppu, &mut interrupts, 76);
+        assert_eq!(ppu.read_register(STAT) & 0x03, 2);
+        run(&mut ppu, &mut interrupts, 4);
+        assert_eq!(ppu.read_register(STAT) & 0x03, 3);
+        run(&mut ppu, &mut interrupts, 172);
+        assert_eq!(ppu.read_register(STAT) & 0x03, 0);
+    }
+    #[test]
+    fn first_line_after_enable_skips_oam_scan() {
+        let (mut ppu, mut interrupts) = enabled_ppu();
+        ppu.write_register(STAT, 0x20);
+        interrupts.write_register(0xFFFF, 0xFF);
+        run(&mut ppu, &mut interrupts, 76);
+        assert_eq!(
+            (ppu.read_register(STAT) & 0x03, interrupts.pending()),
+            (0, None)
+        );
+        run(&mut ppu, &mut interrupts, 4);
+        assert_eq!(ppu.read_register(STAT) & 0x03, 3);
+        run(&mut ppu, &mut interrupts, 376);
+        assert_eq!(
+            (ppu.read_register(LY), interrupts.pending()),
+            (1, Some(InterruptType::LCD_STAT))
+        );
+    }
+    #[test]
+    fn vblank_starts_at_line_144() {
+        let (mut ppu, mut interrupts) = enabled_ppu();
+        interrupts.write_register(0xFFFF, 0xFF);
+        run(&mut ppu, &mut interrupts, 144 * 456 - 4);
+        assert_eq!(interrupts.pending(), None);
+        run(&mut ppu, &mut interrupts, 4);
+        assert_eq!(
+            (
+                ppu.read_register(LY),
+                ppu.read_register(STAT) & 0x03,
+                interrupts.pending()
+            ),
+            (0x90, 1, Some(InterruptType::VBLANK))
+        );
+    }
+    #[test]
+    fn frame_wraps_after_154_lines() {
+        let (mut ppu, mut interrupts) = enabled_ppu();
+        run(&mut ppu, &mut interrupts, 70224);
+        assert_eq!(
+            (ppu.read_register(LY), ppu.read_register(STAT) & 0x03),
+            (0, 2)
+        );
+    }
+    #[test]
+    fn lyc_match_sets_coincidence_and_interrupt() {
+        let (mut ppu, mut interrupts) = enabled_ppu();
+        ppu.write_register(LYC, 2);
+        ppu.write_register(STAT, 0x40);
+        interrupts.write_register(0xFFFF, 0xFF);
+        run(&mut ppu, &mut interrupts, 2 * 456 - 4);
+        assert_eq!(
+            (ppu.read_register(STAT) & 0x04, interrupts.pending()),
+            (0x00, None)
+        );
+        run(&mut ppu, &mut interrupts, 4);
+        assert_eq!(
+            (ppu.read_register(STAT) & 0x04, interrupts.pending()),
+            (0x04, Some(InterruptType::LCD_STAT))
+        );
+    }
+    #[test]
+    fn stat_interrupt_fires_on_rising_edge_only() {
+        let (mut ppu, mut interrupts) = enabled_ppu();
+        // HBlank and OAM scan back to back keep the line high across the line boundary
+        ppu.write_register(STAT, 0x28);
+        interrupts.write_register(0xFFFF, 0xFF);
+        run(&mut ppu, &mut interrupts, 252);
+        interrupts.acknowledge(InterruptType::LCD_STAT);
+        run(&mut ppu, &mut interrupts, 204 + 76);
+        assert_eq!(interrupts.pending(), None);
+    }
+    #[test]
+    fn lcd_off_resets_ly_and_mode() {
+        let (mut ppu, mut interrupts) = enabled_ppu();
+        run(&mut ppu, &mut interrupts, 10 * 456 + 100);
+        ppu.write_register(LCDC, 0x11);
+        run(&mut ppu, &mut interrupts, 456);
+        assert_eq!(
+            (ppu.read_register(LY), ppu.read_register(STAT) & 0x03),
+            (0, 0)
+        );
+        ppu.write_register(LCDC, 0x91);
+        run(&mut ppu, &mut interrupts, 76);
+        assert_eq!(ppu.read_register(STAT) & 0x03, 0);
+        run(&mut ppu, &mut interrupts, 4);
+        assert_eq!(ppu.read_register(STAT) & 0x03, 3);
+    }
+}
+
+#[cfg(test)]
+mod render_tests {
+    use super::{FRAME_SIZE, LCDC, PPU, SCREEN_WIDTH, SCX, SCY, WX, WY};
+    use crate::interrupt::InterruptController;
+
+    // Tile 1 at 8010 : a vertical bar in color 3 down column 0, color 1 everywhere else
+    fn ppu(lcdc: u8) -> PPU {
+        let mut ppu = PPU::new();
+        for row in 0..8 {
+            ppu.write_vram(0x10 + row * 2, 0xFF);
+            ppu.write_vram(0x10 + row * 2 + 1, 0x80);
+        }
+        ppu.write_register(0xFF47, 0b11_10_01_00);
+        ppu.write_register(LCDC, lcdc);
+        ppu
+    }
+
+    fn run_frame(ppu: &mut PPU) -> [u8; FRAME_SIZE] {
+        let mut interrupts = InterruptController::new();
+        let frame_count = ppu.get_frame_count();
+        while ppu.get_frame_count() == frame_count {
+            ppu.tick(&mut interrupts);
+        }
+        *ppu.get_frame()
+    }
+
+    fn row(frame: &[u8; FRAME_SIZE], y: usize) -> &[u8] {
+        &frame[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH]
+    }
+
+    #[test]
+    fn background_uses_unsigned_tile_data_and_bgp() {
+        let mut ppu = ppu(0x91);
+        ppu.write_vram(0x1800, 0x01);
+        let frame = run_frame(&mut ppu);
+        assert_eq!(&row(&frame, 0)[..10], &[3, 1, 1, 1, 1, 1, 1, 1, 0, 0]);
+        assert_eq!(&row(&frame, 7)[..2], &[3, 1]);
+        assert_eq!(row(&frame, 8)[0], 0);
+    }
+    #[test]
+    fn background_uses_signed_tile_data() {
+        // Tile 0x81 with 8800 addressing sits at 0x8810, tile 1 at 0x9010
+        let mut ppu = ppu(0x81);
+        for row in 0..16 {
+            ppu.write_vram(0x1010 + row, ppu.read_vram(0x10 + row));
+            ppu.write_vram(0x0810 + row, 0xFF);
+        }
+        ppu.write_vram(0x1800, 0x01);
+        ppu.write_vram(0x1801, 0x81);
+        let frame = run_frame(&mut ppu);
+        assert_eq!(&row(&frame, 0)[..10], &[3, 1, 1, 1, 1, 1, 1, 1, 3, 3]);
+    }
+    #[test]
+    fn scroll_wraps_around_the_map() {
+        let mut ppu = ppu(0x91);
+        ppu.write_vram(0x1800 + 31 * 32 + 31, 0x01);
+        ppu.write_register(SCX, 0xFC);
+        ppu.write_register(SCY, 0xF8);
+        let frame = run_frame(&mut ppu);
+        assert_eq!(&row(&frame, 0)[..5], &[1, 1, 1, 1, 0]);
+        assert_eq!(row(&frame, 8)[0], 0);
+    }
+    #[test]
+    fn window_overlays_from_wx_wy() {
+        let mut ppu = ppu(0xF1);
+        (0..0x400).for_each(|offset| ppu.write_vram(0x1C00 + offset, 0x01));
+        ppu.write_register(WX, 7 + 80);
+        ppu.write_register(WY, 100);
+        let frame = run_frame(&mut ppu);
+        assert_eq!(&row(&frame, 99)[80..82], &[0, 0]);
+        assert_eq!(&row(&frame, 100)[78..82], &[0, 0, 3, 1]);
+        assert_eq!(row(&frame, 143)[160 - 1], 1);
+    }
+    #[test]
+    fn disabled_background_is_blank() {
+        let mut ppu = ppu(0x90);
+        ppu.write_vram(0x1800, 0x01);
+        let frame = run_frame(&mut ppu);
+        assert_eq!(&row(&frame, 0)[..2], &[0, 0]);
+    }
+}
+
+#[cfg(test)]
+mod sprite_tests {
+    use super::{FRAME_SIZE, LCDC, OBP0, OBP1, PPU, SCREEN_WIDTH};
+    use crate::interrupt::InterruptController;
+
+    /* Tile 1 : color 1 with a color 3 top left pixel
+    Tile 2 : solid color 2
+    Tile 3 : color 0 on the left half, color 1 on the right half */
+    fn ppu(lcdc: u8) -> PPU {
+        let mut ppu = PPU::new();
+        for row in 0..8 {
+            ppu.write_vram(0x10 + row * 2, 0xFF);
+            ppu.write_vram(0x20 + row * 2 + 1, 0xFF);
+            ppu.write_vram(0x30 + row * 2, 0x0F);
+        }
+        ppu.write_vram(0x11, 0x80);
+        ppu.write_register(0xFF47, 0b11_10_01_00);
+        ppu.write_register(OBP0, 0b11_10_01_00);
+        ppu.write_register(OBP1, 0b00_01_10_11);
+        ppu.write_register(LCDC, lcdc);
+        ppu
+    }
+
+    fn sprite(ppu: &mut PPU, index: u16, y: u8, x: u8, tile: u8, flags: u8) {
+        for (offset, value) in [y, x, tile, flags].iter().enumerate() {
+            ppu.write_oam(index * 4 + offset as u16, *value);
+        }
+    }
+
+    fn run_frame(ppu: &mut PPU) -> [u8; FRAME_SIZE] {
+        let mut interrupts = InterruptController::new();
+        let frame_count = ppu.get_frame_count();
+        while ppu.get_frame_count() == frame_count {
+            ppu.tick(&mut interrupts);
+        }
+        *ppu.get_frame()
+    }
+
+    fn pixel(frame: &[u8; FRAME_SIZE], x: usize, y: usize) -> u8 {
+        frame[y * SCREEN_WIDTH + x]
+    }
+
+    #[test]
+    fn sprite_is_placed_at_x_minus_8_y_minus_16() {
+        let mut ppu = ppu(0x93);
+        sprite(&mut ppu, 0, 16 + 10, 8 + 20, 0x01, 0x00);
+        let frame = run_frame(&mut ppu);
+        assert_eq!(
+            (
+                pixel(&frame, 19, 10),
+                pixel(&frame, 20, 10),
+                pixel(&frame, 21, 10),
+                pixel(&frame, 20, 17),
+                pixel(&frame, 20, 18)
+            ),
+            (0, 3, 1, 1, 0)
+        );
+    }
+    #[test]
+    fn sprites_flip_and_use_obp1() {
+        let mut ppu = ppu(0x93);
+        sprite(&mut ppu, 0, 16, 8, 0x01, 0x60);
+        sprite(&mut ppu, 1, 16, 8 + 10, 0x01, 0x10);
+        let frame = run_frame(&mut ppu);
+        assert_eq!(
+            (
+                pixel(&frame, 7, 7),
+                pixel(&frame, 0, 0),
+                pixel(&frame, 10, 0)
+            ),
+            (3, 1, 0)
+        );
+    }
+    #[test]
+    fn tall_sprites_span_two_tiles() {
+        let mut ppu = ppu(0x97);
+        sprite(&mut ppu, 0, 16, 8, 0x03, 0x00);
+        let frame = run_frame(&mut ppu);
+        // Tile 3 & 0xFE = tile 2 on top, tile 3 below
+        assert_eq!(
+            (
+                pixel(&frame, 0, 0),
+                pixel(&frame, 0, 8),
+                pixel(&frame, 4, 15)
+            ),
+            (2, 0, 1)
+        );
+    }
+    #[test]
+    fn ten_sprites_per_line() {
+        let mut ppu = ppu(0x93);
+        for index in 0..11 {
+            sprite(&mut ppu, index, 16, 8 + 10 * index as u8, 0x02, 0x00);
+        }
+        let frame = run_frame(&mut ppu);
+        assert_eq!((pixel(&frame, 90, 0), pixel(&frame, 100, 0)), (2, 0));
+    }
+    #[test]
+    fn smaller_x_wins_then_oam_order() {
+        let mut ppu = ppu(0x93);
+        sprite(&mut ppu, 0, 16, 8 + 4, 0x02, 0x00);
+        sprite(&mut ppu, 1, 16, 8 + 2, 0x01, 0x00);
+        sprite(&mut ppu, 2, 16 + 8, 8, 0x02, 0x00);
+        sprite(&mut ppu, 3, 16 + 8, 8, 0x01, 0x00);
+        let frame = run_frame(&mut ppu);
+        assert_eq!(
+            (
+                pixel(&frame, 4, 1),
+                pixel(&frame, 10, 1),
+                pixel(&frame, 0, 9)
+            ),
+            (1, 2, 2)
+        );
+    }
+    #[test]
+    fn transparent_pixels_show_lower_priority_sprites() {
+        let mut ppu = ppu(0x93);
+        sprite(&mut ppu, 0, 16, 8, 0x03, 0x00);
+        sprite(&mut ppu, 1, 16, 8 + 1, 0x02, 0x00);
+        let frame = run_frame(&mut ppu);
+        assert_eq!((pixel(&frame, 1, 0), pixel(&frame, 4, 0)), (2, 1));
+    }
+    #[test]
+    fn bg_priority_hides_behind_bg_colors_1_to_3() {
+        let mut ppu = ppu(0x93);
+        ppu.write_vram(0x1800, 0x03);
+        sprite(&mut ppu, 0, 16, 8, 0x02, 0x80);
+        // A lower priority sprite does not show through a hidden one
+        sprite(&mut ppu, 1, 16, 8, 0x01, 0x00);
+        let frame = run_frame(&mut ppu);
+        assert_eq!((pixel(&frame, 1, 0), pixel(&frame, 4, 0)), (2, 1));
+    }
+    #[test]
+    fn obj_enable_gates_sprites() {
+        let mut ppu = ppu(0x91);
+        sprite(&mut ppu, 0, 16, 8, 0x02, 0x00);
+        let frame = run_frame(&mut ppu);
+        assert_eq!(pixel(&frame, 0, 0), 0);
+    }
+}
+
+#[cfg(test)]
+mod fifo_tests {
+    use super::{Renderer, BGP, FRAME_SIZE, LCDC, LY, PPU, SCREEN_WIDTH, SCX, STAT, WX, WY};
+    use crate::interrupt::InterruptController;
+
+    // Same scene as the sprite tests : tile 1 is color 1 with a color 3 top left pixel
+    fn ppu(renderer: Renderer) -> PPU {
+        let mut ppu = PPU::with_renderer(renderer);
+        for row in 0..8 {
+            ppu.write_vram(0x10 + row * 2, 0xFF);
+            ppu.write_vram(0x20 + row * 2 + 1, 0xFF);
+        }
+        ppu.write_vram(0x11, 0x80);
+        for offset in (0..0x3FF).step_by(3) {
+            ppu.write_vram(0x1800 + offset, 0x01);
+            ppu.write_vram(0x1C00 + offset + 1, 0x02);
+        }
+        ppu.write_register(BGP, 0b11_10_01_00);
+        ppu.write_register(0xFF48, 0b00_01_10_11);
+        ppu.write_register(0xFF49, 0b11_10_01_00);
+        ppu
+    }
+
+    fn sprite(ppu: &mut PPU, index: u16, y: u8, x: u8, tile: u8, flags: u8) {
+        for (offset, value) in [y, x, tile, flags].iter().enumerate() {
+            ppu.write_oam(index * 4 + offset as u16, *value);
+        }
+    }
+
+    fn run_frame(ppu: &mut PPU, interrupts: &mut InterruptController) -> [u8; FRAME_SIZE] {
+        let frame_count = ppu.get_frame_count();
+        while ppu.get_frame_count() == frame_count {
+            ppu.tick(interrupts);
+        }
+        *ppu.get_frame()
+    }
+
+    // Mode 3 length of line 0 of the next frame, in dots
+    fn mode_3_dots(ppu: &mut PPU) -> usize {
+        let mut interrupts = InterruptController::new();
+        run_frame(ppu, &mut interrupts);
+        while ppu.read_register(LY) != 0 {
+            ppu.tick(&mut interrupts);
+        }
+        let mut dots = 0;
+        while ppu.read_register(LY) == 0 {
+            ppu.step(1, &mut interrupts);
+            if ppu.read_register(STAT) & 0x03 == 3 {
+                dots += 1;
+            }
+        }
+        dots
+    }
+
+    #[test]
+    fn mode_3_takes_172_dots_without_scroll_or_sprites() {
+        let mut ppu = ppu(Renderer::Fifo);
+        ppu.write_register(LCDC, 0x91);
+        assert_eq!(mode_3_dots(&mut ppu), 172);
+    }
+    #[test]
+    fn fine_scroll_stretches_mode_3() {
+        let mut ppu = ppu(Renderer::Fifo);
+        ppu.write_register(LCDC, 0x91);
+        ppu.write_register(SCX, 0x0B);
+        assert_eq!(mode_3_dots(&mut ppu), 172 + 3);
+    }
+    #[test]
+    fn sprites_and_window_stretch_mode_3() {
+        let mut ppu = ppu(Renderer::Fifo);
+        ppu.write_register(LCDC, 0x93);
+        sprite(&mut ppu, 0, 16, 8 + 8, 0x01, 0x00);
+        let with_sprite = mode_3_dots(&mut ppu);
+        ppu.write_register(LCDC, 0xB3);
+        ppu.write_register(WX, 7 + 80);
+        ppu.write_register(WY, 0);
+        let with_window = mode_3_dots(&mut ppu);
+        assert!(with_sprite >= 172 + 6 && with_window > with_sprite);
+    }
+    #[test]
+    fn static_scenes_match_the_scanline_renderer() {
+        // SCX and WX : window mid-screen, at the left edge with fine scroll, and cut off on the
+        // left
+        for (scx, wx) in [(0x0D, 7 + 100), (0x03, 7), (0x0D, 3)].iter() {
+            let frames = [Renderer::Scanline, Renderer::Fifo]
+                .iter()
+                .map(|renderer| {
+                    let mut ppu = ppu(*renderer);
+                    ppu.write_register(LCDC, 0xF3);
+                    ppu.write_register(SCX, *scx);
+                    ppu.write_register(0xFF42, 0x05);
+                    ppu.write_register(WX, *wx);
+                    ppu.write_register(WY, 60);
+                    sprite(&mut ppu, 0, 16 + 3, 4, 0x01, 0x20);
+                    sprite(&mut ppu, 1, 16 + 3, 8 + 50, 0x02, 0x80);
+                    sprite(&mut ppu, 2, 16 + 5, 8 + 52, 0x01, 0x10);
+                    sprite(&mut ppu, 3, 16 + 70, 8 + 98, 0x02, 0x00);
+                    run_frame(&mut ppu, &mut InterruptController::new())
+                })
+                .collect::<Vec<[u8; FRAME_SIZE]>>();
+            assert!(frames[0][..] == frames[1][..], "SCX {} WX {}", scx, wx);
+        }
+    }
+    #[test]
+    fn mid_line_palette_writes_take_effect() {
+        let mut ppu = ppu(Renderer::Fifo);
+        ppu.write_register(LCDC, 0x91);
+        let mut interrupts = InterruptController::new();
+        run_frame(&mut ppu, &mut interrupts);
+        while ppu.read_register(STAT) & 0x03 != 3 {
+            ppu.tick(&mut interrupts);
         }
+        (0..20).for_each(|_| ppu.tick(&mut interrupts));
+        ppu.write_register(BGP, 0x00);
+        let frame = run_frame(&mut ppu, &mut interrupts);
+        assert_eq!((frame[1], frame[SCREEN_WIDTH - 8]), (1, 0));
     }
 }
@@ -1,5 +1,3 @@
-#![allow(unused)]
-
 const SET_Z: u8 = 0b10000000;
 const SET_N: u8 = 0b01000000;
 const SET_H: u8 = 0b00100000;
@@ -7,9 +5,6 @@ const SET_C: u8 = 0b00010000;
 
 pub trait Flag {
     fn reset_flags(&mut self);
-    fn set_flags(&mut self, value: u8);
-    fn get_flags(&self) -> u8;
-
     fn set_z(&mut self);
     fn set_n(&mut self);
     fn set_h(&mut self);
@@ -20,11 +15,6 @@ pub trait Flag {
     fn reset_h(&mut self);
     fn reset_c(&mut self);
 
-    fn get_z(&self) -> u8;
-    fn get_n(&self) -> u8;
-    fn get_h(&self) -> u8;
-    fn get_c(&self) -> u8;
-
     fn is_set_z(&self) -> bool;
     fn is_set_n(&self) -> bool;
     fn is_set_h(&self) -> bool;
@@ -63,16 +53,16 @@ impl Registers {
 
     //16 bit register combination operations
     pub fn get_hl(&self) -> u16 {
-        ((self.h as u16) << 8 | self.l as u16).into()
+        (self.h as u16) << 8 | self.l as u16
     }
     pub fn get_bc(&self) -> u16 {
-        ((self.b as u16) << 8 | self.c as u16).into()
+        (self.b as u16) << 8 | self.c as u16
     }
     pub fn get_de(&self) -> u16 {
-        ((self.d as u16) << 8 | self.e as u16).into()
+        (self.d as u16) << 8 | self.e as u16
     }
     pub fn get_af(&self) -> u16 {
-        ((self.a as u16) << 8 | self.f as u16).into()
+        (self.a as u16) << 8 | self.f as u16
     }
 
     pub fn set_hl(&mut self, value: u16) {
@@ -97,13 +87,6 @@ impl Flag for Registers {
     fn reset_flags(&mut self) {
         self.f = 0x0;
     }
-    fn set_flags(&mut self, value: u8) {
-        self.f = value;
-    }
-    fn get_flags(&self) -> u8 {
-        self.f
-    }
-
     fn set_z(&mut self) {
         self.f |= SET_Z;
     }
@@ -130,19 +113,6 @@ impl Flag for Registers {
         self.f &= !SET_C;
     }
 
-    fn get_z(&self) -> u8 {
-        self.f & SET_Z
-    }
-    fn get_n(&self) -> u8 {
-        self.f & SET_N
-    }
-    fn get_h(&self) -> u8 {
-        self.f & SET_H
-    }
-    fn get_c(&self) -> u8 {
-        self.f & SET_C
-    }
-
     fn is_set_z(&self) -> bool {
         self.f & SET_Z == SET_Z
     }
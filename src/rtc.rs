@@ -0,0 +1,293 @@
+/* MBC3 Real Time Clock
+08 : Seconds 0-59
+09 : Minutes 0-59
+0A : Hours 0-23
+0B : Day counter, low 8 bits
+0C : Bit 0 day counter bit 8, Bit 6 halt, Bit 7 day counter carry
+Reads see the latched copy, writes go to the running clock.
+*/
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::error::GumBoiError;
+
+pub const RTC_FOOTER_SIZE: usize = 48;
+const RTC_FOOTER_SIZE_32BIT: usize = 44; // Older VBA saves store a 32 bit timestamp
+
+const DAY_HIGH: u8 = 0b0000_0001;
+const HALT: u8 = 0b0100_0000;
+const DAY_CARRY: u8 = 0b1000_0000;
+
+// Wall clock seconds since the unix epoch, swapped for a fake in tests
+pub trait TimeSource {
+    fn now(&self) -> u64;
+}
+
+pub struct SystemClock;
+
+impl TimeSource for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+struct Registers {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16, // 9 bits
+    halt: bool,
+    carry: bool,
+}
+
+impl Registers {
+    fn read(&self, register: u8) -> u8 {
+        match register {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.days as u8,
+            _ => {
+                (self.days >> 8) as u8 & DAY_HIGH
+                    | if self.halt { HALT } else { 0x0 }
+                    | if self.carry { DAY_CARRY } else { 0x0 }
+            }
+        }
+    }
+    fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => self.seconds = value & 0x3F,
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | value as u16,
+            _ => {
+                self.days = (self.days & 0xFF) | ((value & DAY_HIGH) as u16) << 8;
+                self.halt = value & HALT == HALT;
+                self.carry = value & DAY_CARRY == DAY_CARRY;
+            }
+        }
+    }
+    // The day counter overflows into the carry bit, which stays set until written back to 0
+    fn advance(&self, elapsed: u64) -> Registers {
+        let seconds = self.seconds as u64 + elapsed;
+        let minutes = self.minutes as u64 + seconds / 60;
+        let hours = self.hours as u64 + minutes / 60;
+        let days = self.days as u64 + hours / 24;
+        Registers {
+            seconds: (seconds % 60) as u8,
+            minutes: (minutes % 60) as u8,
+            hours: (hours % 24) as u8,
+            days: (days & 0x1FF) as u16,
+            halt: self.halt,
+            carry: self.carry || days > 0x1FF,
+        }
+    }
+    fn encode(&self) -> [u8; 20] {
+        let mut footer = [0u8; 20];
+        for (index, register) in (0x08..=0x0C).enumerate() {
+            footer[index * 4] = self.read(register);
+        }
+        footer
+    }
+    fn decode(footer: &[u8]) -> Registers {
+        let mut registers = Registers::default();
+        for (index, register) in (0x08..=0x0C).enumerate() {
+            registers.write(register, footer[index * 4]);
+        }
+        registers
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct RTC {
+    live: Registers,
+    latched: Registers,
+    latch_armed: bool, // A 0x00 write arms the latch, the following 0x01 copies the clock
+    last_update: u64,
+    time_source: Box<dyn TimeSource>,
+}
+
+impl RTC {
+    pub fn new(time_source: Box<dyn TimeSource>) -> RTC {
+        RTC {
+            live: Registers::default(),
+            latched: Registers::default(),
+            latch_armed: false,
+            last_update: time_source.now(),
+            time_source,
+        }
+    }
+    // The clock only advances lazily, when it is observed
+    fn current(&self) -> (Registers, u64) {
+        let now = self.time_source.now();
+        match self.live.halt {
+            true => (self.live, now),
+            false => (self.live.advance(now.saturating_sub(self.last_update)), now),
+        }
+    }
+    fn update(&mut self) {
+        let (live, now) = self.current();
+        self.live = live;
+        self.last_update = now;
+    }
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.update();
+            self.latched = self.live;
+        }
+        self.latch_armed = value == 0x00;
+    }
+    pub fn read(&self, register: u8) -> u8 {
+        self.latched.read(register)
+    }
+    pub fn write(&mut self, register: u8, value: u8) {
+        self.update();
+        self.live.write(register, value);
+    }
+    /* BGB / VBA footer, appended to the save RAM. All fields little endian
+    00-13 : Live S, M, H, DL, DH, each as a u32
+    14-27 : Latched S, M, H, DL, DH, each as a u32
+    28-2F : Unix timestamp of the save, a u32 in the older 44 byte variant
+    */
+    pub fn to_footer(&self) -> [u8; RTC_FOOTER_SIZE] {
+        let (live, now) = self.current();
+        let mut footer = [0u8; RTC_FOOTER_SIZE];
+        footer[0x00..0x14].copy_from_slice(&live.encode());
+        footer[0x14..0x28].copy_from_slice(&self.latched.encode());
+        footer[0x28..0x30].copy_from_slice(&now.to_le_bytes());
+        footer
+    }
+    // The cartridge kept counting while the save sat on disk, so the timestamp is caught up on
+    pub fn load_footer(&mut self, footer: &[u8]) -> Result<(), GumBoiError> {
+        let timestamp = match footer.len() {
+            RTC_FOOTER_SIZE => {
+                let mut timestamp = [0u8; 8];
+                timestamp.copy_from_slice(&footer[0x28..0x30]);
+                u64::from_le_bytes(timestamp)
+            }
+            RTC_FOOTER_SIZE_32BIT => {
+                let mut timestamp = [0u8; 4];
+                timestamp.copy_from_slice(&footer[0x28..0x2C]);
+                u32::from_le_bytes(timestamp) as u64
+            }
+            size => return Err(GumBoiError::InvalidRtcFooter { size }),
+        };
+        self.live = Registers::decode(&footer[0x00..0x14]);
+        self.latched = Registers::decode(&footer[0x14..0x28]);
+        self.last_update = timestamp;
+        Ok(())
+    }
+    pub fn is_valid_footer_size(size: usize) -> bool {
+        size == RTC_FOOTER_SIZE || size == RTC_FOOTER_SIZE_32BIT
+    }
+}
+
+#[cfg(test)]
+pub mod rtc_tests {
+    use super::{TimeSource, RTC, RTC_FOOTER_SIZE};
+    use crate::error::GumBoiError;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // A clock the test moves by hand
+    #[derive(Clone, Default)]
+    pub struct FakeClock(pub Rc<Cell<u64>>);
+
+    impl FakeClock {
+        pub fn advance(&self, seconds: u64) {
+            self.0.set(self.0.get() + seconds);
+        }
+    }
+
+    impl TimeSource for FakeClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    fn latch(rtc: &mut RTC) -> [u8; 5] {
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+        [
+            rtc.read(0x08),
+            rtc.read(0x09),
+            rtc.read(0x0A),
+            rtc.read(0x0B),
+            rtc.read(0x0C),
+        ]
+    }
+
+    #[test]
+    fn counts_wall_clock_time() {
+        let clock = FakeClock::default();
+        let mut rtc = RTC::new(Box::new(clock.clone()));
+        clock.advance(((24 + 1) * 60 + 2) * 60 + 3);
+        assert_eq!(latch(&mut rtc), [3, 2, 1, 1, 0x00]);
+    }
+    #[test]
+    fn reads_the_latched_copy() {
+        let clock = FakeClock::default();
+        let mut rtc = RTC::new(Box::new(clock.clone()));
+        clock.advance(5);
+        latch(&mut rtc);
+        clock.advance(5);
+        assert_eq!(rtc.read(0x08), 5);
+        // Writing 0x01 again without a 0x00 first does not relatch
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(0x08), 5);
+    }
+    #[test]
+    fn halt_stops_the_clock() {
+        let clock = FakeClock::default();
+        let mut rtc = RTC::new(Box::new(clock.clone()));
+        rtc.write(0x0C, 0x40);
+        clock.advance(100);
+        assert_eq!(latch(&mut rtc), [0, 0, 0, 0, 0x40]);
+        rtc.write(0x08, 30);
+        rtc.write(0x0C, 0x00);
+        clock.advance(40);
+        assert_eq!(latch(&mut rtc), [10, 1, 0, 0, 0x00]);
+    }
+    #[test]
+    fn day_counter_overflow_sets_carry() {
+        let clock = FakeClock::default();
+        let mut rtc = RTC::new(Box::new(clock.clone()));
+        rtc.write(0x0A, 23);
+        rtc.write(0x0B, 0xFF);
+        rtc.write(0x0C, 0x01);
+        clock.advance(3600);
+        assert_eq!(latch(&mut rtc), [0, 0, 0, 0, 0x80]);
+        clock.advance(24 * 3600);
+        assert_eq!(latch(&mut rtc), [0, 0, 0, 1, 0x80]);
+    }
+    #[test]
+    fn footer_round_trips_and_catches_up() {
+        let clock = FakeClock::default();
+        clock.advance(1_000_000);
+        let mut rtc = RTC::new(Box::new(clock.clone()));
+        rtc.write(0x09, 10);
+        latch(&mut rtc);
+        let footer = rtc.to_footer();
+        assert_eq!(
+            (footer[0x04], footer[0x18], &footer[0x28..0x30]),
+            (10, 10, &1_000_000u64.to_le_bytes()[..])
+        );
+
+        let mut restored = RTC::new(Box::new(clock.clone()));
+        clock.advance(60);
+        restored.load_footer(&footer).unwrap();
+        assert_eq!(restored.read(0x09), 10);
+        assert_eq!(latch(&mut restored), [0, 11, 0, 0, 0x00]);
+        // 44 byte footers carry a 32 bit timestamp
+        restored.load_footer(&footer[..44]).unwrap();
+        assert_eq!(latch(&mut restored), [0, 11, 0, 0, 0x00]);
+        assert_eq!(
+            restored.load_footer(&footer[..RTC_FOOTER_SIZE - 1]),
+            Err(GumBoiError::InvalidRtcFooter { size: 47 })
+        );
+    }
+}
@@ -1,9 +1,1027 @@
-use super::GumBoi;
+/* APU : 0xFF10 - 0xFF3F
+FF10-FF14 : NR10-NR14, square channel 1 : sweep, duty/length, envelope, frequency low, control
+FF16-FF19 : NR21-NR24, square channel 2 : as channel 1 without the sweep
+FF1A-FF1E : NR30-NR34, wave channel : DAC, length, volume, frequency low, control
+FF20-FF23 : NR41-NR44, noise channel : length, envelope, polynomial counter, control
+FF24      : NR50, master volume. Bits 4-6 left, bits 0-2 right
+FF25      : NR51, panning. Bits 4-7 channels 4-1 to the left, bits 0-3 channels 4-1 to the right
+FF26      : NR52, bit 7 power, bits 0-3 channel status (read only)
+FF30-FF3F : Wave RAM, 32 4 bit samples, high nibble first
+Control registers : bit 7 trigger, bit 6 length enable, bits 0-2 frequency high
+The frame sequencer runs at 512 Hz and clocks length (256 Hz), sweep (128 Hz) and envelope (64 Hz).
+*/
 
-trait Sound {
-    //TODO
+use std::collections::VecDeque;
+
+use super::bus::M_CYCLE;
+
+const NR10: u16 = 0xFF10;
+const NR11: u16 = 0xFF11;
+const NR12: u16 = 0xFF12;
+const NR13: u16 = 0xFF13;
+const NR14: u16 = 0xFF14;
+const NR21: u16 = 0xFF16;
+const NR22: u16 = 0xFF17;
+const NR23: u16 = 0xFF18;
+const NR24: u16 = 0xFF19;
+const NR30: u16 = 0xFF1A;
+const NR31: u16 = 0xFF1B;
+const NR32: u16 = 0xFF1C;
+const NR33: u16 = 0xFF1D;
+const NR34: u16 = 0xFF1E;
+const NR41: u16 = 0xFF20;
+const NR42: u16 = 0xFF21;
+const NR43: u16 = 0xFF22;
+const NR44: u16 = 0xFF23;
+const NR50: u16 = 0xFF24;
+const NR51: u16 = 0xFF25;
+const NR52: u16 = 0xFF26;
+pub const WAVE_RAM_START: u16 = 0xFF30;
+pub const WAVE_RAM_END: u16 = 0xFF3F;
+const SOUND_REGISTERS: usize = 0x17; // NR10 - NR52
+
+// Bits that read back as 1 whatever was written, NR10 - NR52
+const READ_MASKS: [u8; SOUND_REGISTERS] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10 - NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // unused, NR21 - NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30 - NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // unused, NR41 - NR44
+    0x00, 0x00, 0x70, // NR50 - NR52
+];
+
+const TRIGGER: u8 = 0b1000_0000;
+const LENGTH_ENABLE: u8 = 0b0100_0000;
+const POWER: u8 = 0b1000_0000;
+
+const CPU_CLOCK: usize = 4_194_304; // T-cycles per second
+const FRAME_SEQUENCER_PERIOD: usize = 8192; // 512 Hz
+pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+const VOLUME_SCALE: i32 = 64; // 4 channels * 15 * 8 master volume * 64 stays within an i16
+const CHANNEL_SCALE: i32 = VOLUME_SCALE * 8; // Single channels are recorded at full master volume
+const CAPACITOR_CHARGE: f32 = 0.999_958; // DMG high-pass filter, per T-cycle
+pub const RECORDED_CHANNELS: usize = 6; // Left, right, then channels 1-4 before panning
+
+// Waveforms for 12.5%, 25%, 50% and 75% duty, played from bit 0 up
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+const NOISE_DIVISORS: [usize; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+// SECTION Channel components
+
+// Silences the channel once it runs out, when enabled by the control register
+#[derive(PartialEq, Debug, Clone, Copy)]
+struct LengthCounter {
+    enabled: bool,
+    counter: u16,
+    max: u16, // 64, or 256 for the wave channel
+}
+
+impl LengthCounter {
+    fn new(max: u16) -> LengthCounter {
+        LengthCounter {
+            enabled: false,
+            counter: 0,
+            max,
+        }
+    }
+    fn load(&mut self, length: u8) {
+        self.counter = self.max - length as u16;
+    }
+    // Returns true when the counter just ran out
+    fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+        self.counter -= 1;
+        self.counter == 0
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    period: u8, // 0 stops the envelope
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    fn new() -> Envelope {
+        Envelope {
+            initial_volume: 0,
+            increase: false,
+            period: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+    fn write(&mut self, value: u8) {
+        self.initial_volume = value >> 4;
+        self.increase = value & 0x08 == 0x08;
+        self.period = value & 0x07;
+    }
+    // Writing 0 to the volume and direction bits turns the DAC off
+    fn is_dac_enabled(&self) -> bool {
+        self.initial_volume != 0 || self.increase
+    }
+    fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.period;
+    }
+    fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period;
+            match self.increase {
+                true if self.volume < 15 => self.volume += 1,
+                false if self.volume > 0 => self.volume -= 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+// Channel 1 frequency sweep, working on a shadow copy of the frequency
+#[derive(PartialEq, Debug, Clone, Copy)]
+struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+    timer: u8,
+    enabled: bool,
+    shadow: u16,
+    negate_used: bool, // Clearing negate after a subtraction disables the channel
+}
+
+impl Sweep {
+    fn new() -> Sweep {
+        Sweep {
+            period: 0,
+            negate: false,
+            shift: 0,
+            timer: 0,
+            enabled: false,
+            shadow: 0,
+            negate_used: false,
+        }
+    }
+    // Returns false when the write disables the channel
+    fn write(&mut self, value: u8) -> bool {
+        self.period = (value >> 4) & 0x07;
+        self.negate = value & 0x08 == 0x08;
+        self.shift = value & 0x07;
+        let disable = self.negate_used && !self.negate;
+        self.negate_used = false;
+        !disable
+    }
+    fn reload_timer(&mut self) {
+        self.timer = match self.period {
+            0 => 8,
+            period => period,
+        };
+    }
+    // Frequencies past 2047 overflow and disable the channel
+    fn calculate(&mut self) -> Option<u16> {
+        let delta = self.shadow >> self.shift;
+        let frequency = match self.negate {
+            true => {
+                self.negate_used = true;
+                self.shadow - delta
+            }
+            false => self.shadow + delta,
+        };
+        match frequency {
+            0x000..=0x7FF => Some(frequency),
+            _ => None,
+        }
+    }
+}
+
+// The parts of a channel the frame sequencer and the control register work on
+trait Channel {
+    fn get_length_mut(&mut self) -> &mut LengthCounter;
+    fn is_dac_enabled(&self) -> bool;
+    fn is_enabled(&self) -> bool;
+    fn set_enabled(&mut self, enabled: bool);
+    // Restarts the channel, returns false when it turns itself right back off
+    fn trigger(&mut self) -> bool;
+    // Advances the frequency timer by some T-cycles
+    fn step(&mut self, cycles: usize);
+    // Digital output, 0 - 15
+    fn get_output(&self) -> u8;
+}
+
+// !SECTION
+
+// SECTION Channels
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+struct Square {
+    enabled: bool,
+    length: LengthCounter,
+    envelope: Envelope,
+    sweep: Option<Sweep>, // Channel 1 only
+    duty: u8,
+    duty_position: u8,
+    frequency: u16,
+    timer: usize,
+}
+
+impl Square {
+    fn new(sweep: Option<Sweep>) -> Square {
+        Square {
+            enabled: false,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            sweep,
+            duty: 0,
+            duty_position: 0,
+            frequency: 0,
+            timer: 0,
+        }
+    }
+    fn get_period(&self) -> usize {
+        (2048 - self.frequency as usize) * 4
+    }
+    fn clock_sweep(&mut self) {
+        let sweep = match self.sweep.as_mut() {
+            Some(sweep) => sweep,
+            None => return,
+        };
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer != 0 {
+            return;
+        }
+        sweep.reload_timer();
+        if !sweep.enabled || sweep.period == 0 {
+            return;
+        }
+        match sweep.calculate() {
+            Some(frequency) if sweep.shift != 0 => {
+                sweep.shadow = frequency;
+                self.frequency = frequency;
+                // The new frequency is checked for overflow again straight away
+                if sweep.calculate().is_none() {
+                    self.enabled = false;
+                }
+            }
+            Some(_) => {}
+            None => self.enabled = false,
+        }
+    }
+}
+
+impl Channel for Square {
+    fn get_length_mut(&mut self) -> &mut LengthCounter {
+        &mut self.length
+    }
+    fn is_dac_enabled(&self) -> bool {
+        self.envelope.is_dac_enabled()
+    }
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+    fn trigger(&mut self) -> bool {
+        self.timer = self.get_period();
+        self.envelope.trigger();
+        let frequency = self.frequency;
+        match self.sweep.as_mut() {
+            Some(sweep) => {
+                sweep.shadow = frequency;
+                sweep.negate_used = false;
+                sweep.reload_timer();
+                sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+                sweep.shift == 0 || sweep.calculate().is_some()
+            }
+            None => true,
+        }
+    }
+    fn step(&mut self, cycles: usize) {
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.get_period();
+            self.duty_position = (self.duty_position + 1) & 0x07;
+        }
+        self.timer -= cycles;
+    }
+    fn get_output(&self) -> u8 {
+        match DUTY_PATTERNS[self.duty as usize] >> self.duty_position & 0x1 {
+            0x1 => self.envelope.volume,
+            _ => 0x0,
+        }
+    }
 }
 
-impl Sound for GumBoi {
-    //TODO
+#[derive(PartialEq, Debug, Clone, Copy)]
+struct Wave {
+    enabled: bool,
+    dac_enabled: bool,
+    length: LengthCounter,
+    volume_shift: u8, // NR32 bits 5-6 : mute, 100%, 50%, 25%
+    frequency: u16,
+    timer: usize,
+    position: usize, // Sample index into wave RAM, 0 - 31
+    ram: [u8; 16],
+}
+
+impl Wave {
+    fn new() -> Wave {
+        Wave {
+            enabled: false,
+            dac_enabled: false,
+            length: LengthCounter::new(256),
+            volume_shift: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            ram: [0u8; 16],
+        }
+    }
+    fn get_period(&self) -> usize {
+        (2048 - self.frequency as usize) * 2
+    }
+    // While the channel plays, the CPU only reaches the byte being played
+    fn get_ram_index(&self, index: u16) -> usize {
+        match self.enabled {
+            true => self.position / 2,
+            false => index as usize,
+        }
+    }
+}
+
+impl Channel for Wave {
+    fn get_length_mut(&mut self) -> &mut LengthCounter {
+        &mut self.length
+    }
+    fn is_dac_enabled(&self) -> bool {
+        self.dac_enabled
+    }
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+    fn trigger(&mut self) -> bool {
+        self.timer = self.get_period();
+        self.position = 0;
+        true
+    }
+    fn step(&mut self, cycles: usize) {
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.get_period();
+            self.position = (self.position + 1) & 0x1F;
+        }
+        self.timer -= cycles;
+    }
+    fn get_output(&self) -> u8 {
+        let sample = match self.position % 2 {
+            0 => self.ram[self.position / 2] >> 4,
+            _ => self.ram[self.position / 2] & 0x0F,
+        };
+        match self.volume_shift {
+            0 => 0x0,
+            shift => sample >> (shift - 1),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+struct Noise {
+    enabled: bool,
+    length: LengthCounter,
+    envelope: Envelope,
+    shift: u8,
+    short_mode: bool, // 7 bit LFSR
+    divisor: usize,
+    timer: usize,
+    lfsr: u16,
+}
+
+impl Noise {
+    fn new() -> Noise {
+        Noise {
+            enabled: false,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            shift: 0,
+            short_mode: false,
+            divisor: NOISE_DIVISORS[0],
+            timer: 0,
+            lfsr: 0x7FFF,
+        }
+    }
+    fn write_polynomial(&mut self, value: u8) {
+        self.shift = value >> 4;
+        self.short_mode = value & 0x08 == 0x08;
+        self.divisor = NOISE_DIVISORS[(value & 0x07) as usize];
+    }
+    fn get_period(&self) -> usize {
+        self.divisor << self.shift
+    }
+}
+
+impl Channel for Noise {
+    fn get_length_mut(&mut self) -> &mut LengthCounter {
+        &mut self.length
+    }
+    fn is_dac_enabled(&self) -> bool {
+        self.envelope.is_dac_enabled()
+    }
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+    fn trigger(&mut self) -> bool {
+        self.timer = self.get_period();
+        self.envelope.trigger();
+        self.lfsr = 0x7FFF;
+        true
+    }
+    // Bits 0 and 1 are XORed into bit 14, and into bit 6 as well in short mode
+    fn step(&mut self, cycles: usize) {
+        let mut cycles = cycles;
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.get_period();
+            let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 0x1;
+            self.lfsr = (self.lfsr >> 1) | feedback << 14;
+            if self.short_mode {
+                self.lfsr = (self.lfsr & !(0x1 << 6)) | feedback << 6;
+            }
+        }
+        self.timer -= cycles;
+    }
+    fn get_output(&self) -> u8 {
+        match self.lfsr & 0x1 {
+            0x0 => self.envelope.volume,
+            _ => 0x0,
+        }
+    }
+}
+
+// !SECTION
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct APU {
+    registers: [u8; SOUND_REGISTERS], // As last written, for read back
+    powered: bool,
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    frame_timer: usize,
+    frame_step: u8, // Next frame sequencer step, 0 - 7
+    sample_rate: u32,
+    sample_timer: usize, // Goes up by the sample rate every T-cycle, a sample is due at CPU_CLOCK
+    mix_sum: (i32, i32), // Output summed over the M-cycles of the pending sample
+    mix_count: i32,
+    samples: VecDeque<i16>, // Interleaved left and right samples, waiting for the host
+    recorded: Option<Vec<i16>>, // RECORDED_CHANNELS per sample, while a recording runs
+    channel_sums: [i32; 4],
+    capacitors: [f32; 2], // Left and right high-pass filter state
+    charge_factor: f32,   // CAPACITOR_CHARGE over the T-cycles of one sample
+}
+
+impl APU {
+    pub fn new() -> APU {
+        APU {
+            registers: [0u8; SOUND_REGISTERS],
+            powered: false,
+            square1: Square::new(Some(Sweep::new())),
+            square2: Square::new(None),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            frame_timer: 0,
+            frame_step: 0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_timer: 0,
+            mix_sum: (0, 0),
+            mix_count: 0,
+            samples: VecDeque::new(),
+            recorded: None,
+            channel_sums: [0; 4],
+            capacitors: [0.0; 2],
+            charge_factor: charge_factor(DEFAULT_SAMPLE_RATE),
+        }
+    }
+    // Drops any queued samples, they were made for the old rate
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.charge_factor = charge_factor(sample_rate);
+        self.capacitors = [0.0; 2];
+        self.sample_timer = 0;
+        self.mix_sum = (0, 0);
+        self.mix_count = 0;
+        self.samples.clear();
+    }
+    // Pops interleaved stereo samples into buffer, returning how many were written.
+    // Only whole left/right pairs are handed out
+    pub fn drain_samples(&mut self, buffer: &mut [i16]) -> usize {
+        let count = buffer.len().min(self.samples.len()) & !0x1;
+        for (slot, sample) in buffer.iter_mut().zip(self.samples.drain(..count)) {
+            *slot = sample;
+        }
+        count
+    }
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    // Recording keeps its own copy of every sample, so it does not compete with the host
+    pub fn start_recording(&mut self) {
+        self.recorded = Some(Vec::new());
+        self.channel_sums = [0; 4];
+    }
+    pub fn drain_recorded(&mut self) -> Vec<i16> {
+        self.recorded.as_mut().map_or_else(Vec::new, std::mem::take)
+    }
+    pub fn stop_recording(&mut self) -> Vec<i16> {
+        self.recorded.take().unwrap_or_default()
+    }
+    pub fn read_register(&self, addr: u16) -> u8 {
+        match addr {
+            WAVE_RAM_START..=WAVE_RAM_END => {
+                self.wave.ram[self.wave.get_ram_index(addr - WAVE_RAM_START)]
+            }
+            NR52 => {
+                let status = [
+                    self.square1.enabled,
+                    self.square2.enabled,
+                    self.wave.enabled,
+                    self.noise.enabled,
+                ]
+                .iter()
+                .enumerate()
+                .fold(0x0, |status, (bit, &enabled)| {
+                    status | (enabled as u8) << bit
+                });
+                READ_MASKS[(NR52 - NR10) as usize] | if self.powered { POWER } else { 0x0 } | status
+            }
+            NR10..=NR51 => {
+                let index = (addr - NR10) as usize;
+                self.registers[index] | READ_MASKS[index]
+            }
+            _ => 0xFF,
+        }
+    }
+    // Everything but NR52 and wave RAM ignores writes while the APU is off
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            WAVE_RAM_START..=WAVE_RAM_END => {
+                let index = self.wave.get_ram_index(addr - WAVE_RAM_START);
+                self.wave.ram[index] = value;
+            }
+            NR52 => self.write_power(value),
+            _ if !self.powered => {}
+            NR10..=NR51 => {
+                self.registers[(addr - NR10) as usize] = value;
+                self.write_channel_register(addr, value);
+            }
+            _ => {}
+        }
+    }
+    fn write_channel_register(&mut self, addr: u16, value: u8) {
+        let frame_step = self.frame_step;
+        match addr {
+            NR10 => {
+                if let Some(sweep) = self.square1.sweep.as_mut() {
+                    if !sweep.write(value) {
+                        self.square1.enabled = false;
+                    }
+                }
+            }
+            NR11 | NR21 => {
+                let square = self.get_square_mut(addr);
+                square.duty = value >> 6;
+                square.length.load(value & 0x3F);
+            }
+            NR12 | NR22 => {
+                let square = self.get_square_mut(addr);
+                square.envelope.write(value);
+                if !square.is_dac_enabled() {
+                    square.enabled = false;
+                }
+            }
+            NR13 | NR23 => {
+                let square = self.get_square_mut(addr);
+                square.frequency = (square.frequency & 0x700) | value as u16;
+            }
+            NR14 | NR24 => {
+                let square = self.get_square_mut(addr);
+                square.frequency = (square.frequency & 0xFF) | ((value & 0x07) as u16) << 8;
+                write_control(square, value, frame_step);
+            }
+            NR30 => {
+                self.wave.dac_enabled = value & 0x80 == 0x80;
+                if !self.wave.dac_enabled {
+                    self.wave.enabled = false;
+                }
+            }
+            NR31 => self.wave.length.load(value),
+            NR32 => self.wave.volume_shift = (value >> 5) & 0x03,
+            NR33 => self.wave.frequency = (self.wave.frequency & 0x700) | value as u16,
+            NR34 => {
+                self.wave.frequency = (self.wave.frequency & 0xFF) | ((value & 0x07) as u16) << 8;
+                write_control(&mut self.wave, value, frame_step);
+            }
+            NR41 => self.noise.length.load(value & 0x3F),
+            NR42 => {
+                self.noise.envelope.write(value);
+                if !self.noise.is_dac_enabled() {
+                    self.noise.enabled = false;
+                }
+            }
+            NR43 => self.noise.write_polynomial(value),
+            NR44 => write_control(&mut self.noise, value, frame_step),
+            _ => {}
+        }
+    }
+    fn get_square_mut(&mut self, addr: u16) -> &mut Square {
+        match addr {
+            NR10..=NR14 => &mut self.square1,
+            _ => &mut self.square2,
+        }
+    }
+    // Powering off clears every register, wave RAM survives
+    fn write_power(&mut self, value: u8) {
+        let powered = value & POWER == POWER;
+        if self.powered && !powered {
+            let ram = self.wave.ram;
+            self.registers = [0u8; SOUND_REGISTERS];
+            self.square1 = Square::new(Some(Sweep::new()));
+            self.square2 = Square::new(None);
+            self.wave = Wave::new();
+            self.wave.ram = ram;
+            self.noise = Noise::new();
+        }
+        if !self.powered && powered {
+            self.frame_timer = 0;
+            self.frame_step = 0;
+        }
+        self.powered = powered;
+    }
+    // Advances the APU by one M-cycle, queueing a sample whenever one is due
+    pub fn tick(&mut self) {
+        if self.powered {
+            self.frame_timer += M_CYCLE;
+            if self.frame_timer == FRAME_SEQUENCER_PERIOD {
+                self.frame_timer = 0;
+                self.step_frame_sequencer();
+            }
+            self.square1.step(M_CYCLE);
+            self.square2.step(M_CYCLE);
+            self.wave.step(M_CYCLE);
+            self.noise.step(M_CYCLE);
+        }
+        let outputs = self.get_dac_outputs();
+        let (left, right) = self.mix(&outputs);
+        self.mix_sum = (self.mix_sum.0 + left, self.mix_sum.1 + right);
+        self.mix_count += 1;
+        if self.recorded.is_some() {
+            for (sum, output) in self.channel_sums.iter_mut().zip(outputs.iter()) {
+                *sum += output;
+            }
+        }
+        self.sample_timer += self.sample_rate as usize * M_CYCLE;
+        if self.sample_timer >= CPU_CLOCK {
+            self.sample_timer -= CPU_CLOCK;
+            self.queue_sample();
+        }
+    }
+    // Steps 0, 2, 4 and 6 clock length, 2 and 6 sweep, 7 envelope
+    fn step_frame_sequencer(&mut self) {
+        if self.frame_step & 0x1 == 0x0 {
+            clock_length(&mut self.square1);
+            clock_length(&mut self.square2);
+            clock_length(&mut self.wave);
+            clock_length(&mut self.noise);
+        }
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.square1.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            self.square1.envelope.clock();
+            self.square2.envelope.clock();
+            self.noise.envelope.clock();
+        }
+        self.frame_step = (self.frame_step + 1) & 0x07;
+    }
+    // A DAC maps 0 - 15 onto -15 - 15, NR51 routes each channel and NR50 scales each side by 1 - 8
+    fn get_dac_outputs(&self) -> [i32; 4] {
+        let mut outputs = [0i32; 4];
+        if !self.powered {
+            return outputs;
+        }
+        let channels: [&dyn Channel; 4] = [&self.square1, &self.square2, &self.wave, &self.noise];
+        for (output, channel) in outputs.iter_mut().zip(channels.iter()) {
+            if channel.is_enabled() && channel.is_dac_enabled() {
+                *output = channel.get_output() as i32 * 2 - 15;
+            }
+        }
+        outputs
+    }
+    fn mix(&self, outputs: &[i32; 4]) -> (i32, i32) {
+        let panning = self.registers[(NR51 - NR10) as usize];
+        let (left, right) =
+            outputs
+                .iter()
+                .enumerate()
+                .fold((0, 0), |(left, right), (index, output)| {
+                    (
+                        left + output * (panning >> (index + 4) & 0x1) as i32,
+                        right + output * (panning >> index & 0x1) as i32,
+                    )
+                });
+        let volume = self.registers[(NR50 - NR10) as usize];
+        (
+            left * (((volume >> 4) & 0x07) as i32 + 1),
+            right * ((volume & 0x07) as i32 + 1),
+        )
+    }
+    // Averages the M-cycles since the last sample and runs each side through the high-pass
+    // filter. At most one second of audio is kept if the host stops pulling, the oldest samples
+    // go first
+    fn queue_sample(&mut self) {
+        let count = self.mix_count.max(1);
+        let (left, right) = (
+            self.high_pass(0, (self.mix_sum.0 / count * VOLUME_SCALE) as f32) as i16,
+            self.high_pass(1, (self.mix_sum.1 / count * VOLUME_SCALE) as f32) as i16,
+        );
+        self.samples.push_back(left);
+        self.samples.push_back(right);
+        if let Some(recorded) = self.recorded.as_mut() {
+            recorded.extend_from_slice(&[left, right]);
+            for sum in self.channel_sums.iter_mut() {
+                recorded.push((*sum / count * CHANNEL_SCALE) as i16);
+                *sum = 0;
+            }
+        }
+        self.mix_sum = (0, 0);
+        self.mix_count = 0;
+        while self.samples.len() > self.sample_rate as usize * 2 {
+            self.samples.drain(..2);
+        }
+    }
+    // The output capacitor blocks DC, a steady DAC level decays to silence
+    fn high_pass(&mut self, side: usize, input: f32) -> f32 {
+        let output = input - self.capacitors[side];
+        self.capacitors[side] = input - output * self.charge_factor;
+        output
+    }
+}
+
+fn charge_factor(sample_rate: u32) -> f32 {
+    CAPACITOR_CHARGE.powf(CPU_CLOCK as f32 / sample_rate as f32)
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn clock_length(channel: &mut impl Channel) {
+    if channel.get_length_mut().clock() {
+        channel.set_enabled(false);
+    }
+}
+
+// NRx4 : length enable and trigger. When the next frame sequencer step does not clock length,
+// enabling length clocks it once extra, and so does a trigger reloading an empty counter
+fn write_control(channel: &mut impl Channel, value: u8, frame_step: u8) {
+    let length_enable = value & LENGTH_ENABLE == LENGTH_ENABLE;
+    let triggered = value & TRIGGER == TRIGGER;
+    let extra_clock = frame_step % 2 == 1;
+    let length = channel.get_length_mut();
+    let was_enabled = length.enabled;
+    length.enabled = length_enable;
+    if extra_clock && !was_enabled && length.clock() && !triggered {
+        channel.set_enabled(false);
+    }
+    if triggered {
+        let length = channel.get_length_mut();
+        if length.counter == 0 {
+            length.counter = length.max;
+            if extra_clock {
+                length.clock();
+            }
+        }
+        let enabled = channel.trigger() && channel.is_dac_enabled();
+        channel.set_enabled(enabled);
+    }
+}
+
+#[cfg(test)]
+mod sound_tests {
+    use super::{Channel, APU, CPU_CLOCK, FRAME_SEQUENCER_PERIOD, M_CYCLE, RECORDED_CHANNELS};
+
+    fn apu() -> APU {
+        let mut apu = APU::new();
+        apu.write_register(0xFF26, 0x80);
+        apu.write_register(0xFF24, 0x77);
+        apu.write_register(0xFF25, 0xFF);
+        apu
+    }
+
+    fn run_frames(apu: &mut APU, frame_steps: usize) {
+        (0..frame_steps * FRAME_SEQUENCER_PERIOD / M_CYCLE).for_each(|_| apu.tick());
+    }
+
+    #[test]
+    fn registers_read_back_with_unused_bits_set() {
+        let mut apu = apu();
+        apu.write_register(0xFF11, 0x80);
+        apu.write_register(0xFF13, 0x12);
+        apu.write_register(0xFF1C, 0x20);
+        assert_eq!(
+            (
+                apu.read_register(0xFF11),
+                apu.read_register(0xFF13),
+                apu.read_register(0xFF1C),
+                apu.read_register(0xFF15),
+                apu.read_register(0xFF26)
+            ),
+            (0xBF, 0xFF, 0xBF, 0xFF, 0xF0)
+        );
+    }
+    #[test]
+    fn power_off_clears_registers_and_ignores_writes() {
+        let mut apu = apu();
+        apu.write_register(0xFF30, 0x12);
+        apu.write_register(0xFF26, 0x00);
+        apu.write_register(0xFF12, 0xF0);
+        assert_eq!(
+            (
+                apu.read_register(0xFF24),
+                apu.read_register(0xFF12),
+                apu.read_register(0xFF26),
+                apu.read_register(0xFF30)
+            ),
+            (0x00, 0x00, 0x70, 0x12)
+        );
+    }
+    #[test]
+    fn trigger_needs_the_dac() {
+        let mut apu = apu();
+        apu.write_register(0xFF19, 0x80);
+        assert_eq!(apu.read_register(0xFF26), 0xF0);
+        apu.write_register(0xFF17, 0xF0);
+        apu.write_register(0xFF19, 0x80);
+        assert_eq!(apu.read_register(0xFF26), 0xF2);
+        apu.write_register(0xFF17, 0x00);
+        assert_eq!(apu.read_register(0xFF26), 0xF0);
+    }
+    #[test]
+    fn length_counter_silences_channel() {
+        let mut apu = apu();
+        apu.write_register(0xFF21, 0xF0);
+        apu.write_register(0xFF20, 62);
+        apu.write_register(0xFF23, 0xC0);
+        run_frames(&mut apu, 2);
+        assert_eq!(apu.read_register(0xFF26), 0xF8);
+        run_frames(&mut apu, 1);
+        assert_eq!(apu.read_register(0xFF26), 0xF0);
+    }
+    #[test]
+    fn enabling_length_mid_frame_clocks_it() {
+        let mut apu = apu();
+        run_frames(&mut apu, 1); // The next step does not clock length
+        apu.write_register(0xFF21, 0xF0);
+        apu.write_register(0xFF20, 63);
+        apu.write_register(0xFF23, 0x80);
+        apu.write_register(0xFF23, 0x40);
+        assert_eq!(apu.read_register(0xFF26), 0xF0);
+    }
+    #[test]
+    fn envelope_steps_at_64_hz() {
+        let mut apu = apu();
+        apu.write_register(0xFF12, 0xF1);
+        apu.write_register(0xFF14, 0x80);
+        run_frames(&mut apu, 7);
+        assert_eq!(apu.square1.envelope.volume, 15);
+        run_frames(&mut apu, 1);
+        assert_eq!(apu.square1.envelope.volume, 14);
+    }
+    #[test]
+    fn sweep_overflow_disables_channel_1() {
+        let mut apu = apu();
+        apu.write_register(0xFF12, 0xF0);
+        apu.write_register(0xFF10, 0x12);
+        apu.write_register(0xFF13, 0x00);
+        apu.write_register(0xFF14, 0x86);
+        assert_eq!(apu.read_register(0xFF26), 0xF1);
+        // 0x600 + 0x180 is applied on the first sweep clock, the check after it overflows
+        run_frames(&mut apu, 3);
+        assert_eq!(apu.read_register(0xFF26), 0xF0);
+        apu.write_register(0xFF13, 0xFF);
+        apu.write_register(0xFF14, 0x87);
+        assert_eq!(apu.read_register(0xFF26), 0xF0);
+    }
+    #[test]
+    fn wave_channel_plays_wave_ram() {
+        let mut apu = apu();
+        apu.write_register(0xFF30, 0xF4);
+        apu.write_register(0xFF1A, 0x80);
+        apu.write_register(0xFF1C, 0x20);
+        apu.write_register(0xFF1D, 0xFF);
+        apu.write_register(0xFF1E, 0x87);
+        // Frequency 0x7FF advances one sample every 2 T-cycles
+        apu.tick();
+        assert_eq!((apu.wave.position, apu.wave.get_output()), (2, 0x0));
+        apu.wave.position = 1;
+        assert_eq!(apu.wave.get_output(), 0x4);
+        apu.write_register(0xFF1C, 0x60);
+        apu.wave.position = 0;
+        assert_eq!(apu.wave.get_output(), 0x3);
+    }
+    #[test]
+    fn noise_lfsr_short_mode() {
+        let mut apu = apu();
+        apu.write_register(0xFF21, 0xF0);
+        apu.write_register(0xFF22, 0x08);
+        apu.write_register(0xFF23, 0x80);
+        (0..2).for_each(|_| apu.tick());
+        assert_eq!(apu.noise.lfsr, 0x3FBF);
+    }
+    #[test]
+    fn produces_stereo_samples_at_the_host_rate() {
+        let mut apu = apu();
+        apu.set_sample_rate(44_100);
+        apu.write_register(0xFF25, 0x20);
+        apu.write_register(0xFF16, 0x80);
+        apu.write_register(0xFF17, 0xF0);
+        apu.write_register(0xFF19, 0x86);
+        (0..CPU_CLOCK / M_CYCLE).for_each(|_| apu.tick());
+        let mut buffer = vec![0i16; 100_000];
+        let count = apu.drain_samples(&mut buffer);
+        assert_eq!(count, 88_200);
+        let left = buffer[..count].iter().step_by(2);
+        let right = buffer[..count].iter().skip(1).step_by(2);
+        assert_eq!(
+            (
+                left.clone().any(|sample| *sample > 0),
+                left.clone().any(|sample| *sample < 0),
+                right.clone().all(|sample| *sample == 0)
+            ),
+            (true, true, true)
+        );
+        assert_eq!(apu.drain_samples(&mut buffer), 0);
+    }
+    #[test]
+    fn recording_taps_mix_and_channels() {
+        let mut apu = apu();
+        apu.write_register(0xFF25, 0x02);
+        apu.write_register(0xFF17, 0xF0);
+        apu.write_register(0xFF19, 0x86);
+        apu.start_recording();
+        (0..CPU_CLOCK / M_CYCLE / 100).for_each(|_| apu.tick());
+        let recorded = apu.stop_recording();
+        let mut buffer = vec![0i16; 1_000];
+        assert_eq!(
+            (
+                recorded.len() / RECORDED_CHANNELS,
+                apu.drain_samples(&mut buffer) / 2
+            ),
+            (479, 479)
+        );
+        // The mix matches what the host pulled, channel 2 is there whatever the panning
+        let frames = recorded.chunks_exact(RECORDED_CHANNELS);
+        assert!(frames
+            .clone()
+            .zip(buffer.chunks_exact(2))
+            .all(|(frame, pulled)| frame[..2] == *pulled && frame[2] == 0));
+        assert!(
+            frames.clone().any(|frame| frame[3] > 0) && frames.clone().any(|frame| frame[3] < 0)
+        );
+        assert_eq!(apu.drain_recorded(), vec![]);
+    }
+    #[test]
+    fn high_pass_filter_removes_dc() {
+        // An enabled DAC at volume 0 sits at -15, a constant offset
+        let mut apu = apu();
+        apu.write_register(0xFF25, 0x20);
+        apu.write_register(0xFF17, 0x08);
+        apu.write_register(0xFF19, 0x80);
+        (0..CPU_CLOCK / M_CYCLE / 10).for_each(|_| apu.tick());
+        let mut buffer = vec![0i16; 10_000];
+        let count = apu.drain_samples(&mut buffer);
+        let left: Vec<i16> = buffer[..count].iter().step_by(2).copied().collect();
+        assert_eq!(
+            (left[0] < -900, left[left.len() - 1]),
+            (true, 0),
+            "{:?}",
+            &left[..4]
+        );
+    }
 }
@@ -1,9 +1,228 @@
-use super::GumBoi;
+/* Timer : 0xFF04 - 0xFF07
+FF04 : DIV, upper 8 bits of the 16 bit system counter. Any write resets the whole counter
+FF05 : TIMA, increments on the falling edge of the counter bit selected by TAC
+FF06 : TMA, reloaded into TIMA one M-cycle after it overflows, along with the TIMER interrupt
+FF07 : TAC, bit 2 enable, bits 0-1 select : 00 bit 9 (4096 Hz), 01 bit 3 (262144 Hz),
+       10 bit 5 (65536 Hz), 11 bit 7 (16384 Hz)
+Enable and the selected bit are ANDed before the edge detector, so DIV and TAC writes that drop
+the signal from 1 to 0 increment TIMA too.
+*/
 
-trait Timer {
-    //TODO
+use super::bus::M_CYCLE;
+use super::interrupt::{InterruptController, InterruptType};
+
+pub const DIV_ADDR: u16 = 0xFF04;
+pub const TIMA_ADDR: u16 = 0xFF05;
+pub const TMA_ADDR: u16 = 0xFF06;
+pub const TAC_ADDR: u16 = 0xFF07;
+
+const TAC_ENABLE: u8 = 0b100;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Timer {
+    counter: u16, // Incremented every T-cycle
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    overflowed: bool, // TIMA reads 0x00 for the M-cycle before the reload
+    reloading: bool,  // M-cycle of the reload, TIMA writes are ignored and TMA writes go through
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        Timer {
+            counter: 0,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+            overflowed: false,
+            reloading: false,
+        }
+    }
+    fn get_signal(&self) -> bool {
+        let bit = match self.tac & 0b11 {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            _ => 7,
+        };
+        self.tac & TAC_ENABLE == TAC_ENABLE && (self.counter >> bit) & 0x1 == 0x1
+    }
+    fn increment_tima(&mut self) {
+        match self.tima.checked_add(1) {
+            Some(tima) => self.tima = tima,
+            None => {
+                self.tima = 0x00;
+                self.overflowed = true;
+            }
+        }
+    }
+    // Applies a change to the counter or TAC, incrementing TIMA on a falling edge
+    fn update_signal(&mut self, update: impl FnOnce(&mut Timer)) {
+        let signal = self.get_signal();
+        update(self);
+        if signal && !self.get_signal() {
+            self.increment_tima();
+        }
+    }
+    // DIV writes and STOP clear the whole counter, which can be a falling edge for TIMA
+    pub fn reset_divider(&mut self) {
+        self.update_signal(|timer| timer.counter = 0);
+    }
+    // Advances the timer by one M-cycle, ahead of the CPU access in that cycle
+    pub fn tick(&mut self, interrupts: &mut InterruptController) {
+        self.reloading = self.overflowed;
+        if self.overflowed {
+            self.overflowed = false;
+            self.tima = self.tma;
+            interrupts.request(InterruptType::TIMER);
+        }
+        self.update_signal(|timer| timer.counter = timer.counter.wrapping_add(M_CYCLE as u16));
+    }
+    pub fn read_register(&self, addr: u16) -> u8 {
+        match addr {
+            DIV_ADDR => (self.counter >> 8) as u8,
+            TIMA_ADDR => self.tima,
+            TMA_ADDR => self.tma,
+            _ => 0xF8 | self.tac,
+        }
+    }
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            DIV_ADDR => self.reset_divider(),
+            // Writing TIMA during the overflow cycle cancels the reload and the interrupt
+            TIMA_ADDR if self.reloading => {}
+            TIMA_ADDR => {
+                self.tima = value;
+                self.overflowed = false;
+            }
+            TMA_ADDR => {
+                self.tma = value;
+                if self.reloading {
+                    self.tima = value;
+                }
+            }
+            _ => self.update_signal(|timer| timer.tac = value & 0b111),
+        }
+    }
 }
 
-impl Timer for GumBoi {
-    //TODO
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Scenarios from mooneye's acceptance/timer suite
+#[cfg(test)]
+mod timer_tests {
+    use super::{Timer, DIV_ADDR, TAC_ADDR, TIMA_ADDR, TMA_ADDR};
+    use crate::interrupt::{InterruptController, InterruptType};
+
+    fn timer(tac: u8) -> (Timer, InterruptController) {
+        let mut timer = Timer::new();
+        let mut interrupts = InterruptController::new();
+        interrupts.write_register(0xFFFF, 0xFF);
+        timer.write_register(TAC_ADDR, tac);
+        (timer, interrupts)
+    }
+
+    fn run(timer: &mut Timer, interrupts: &mut InterruptController, m_cycles: usize) {
+        (0..m_cycles).for_each(|_| timer.tick(interrupts));
+    }
+
+    #[test]
+    fn div_counts_every_64_m_cycles_and_resets_on_write() {
+        let (mut timer, mut interrupts) = timer(0x00);
+        run(&mut timer, &mut interrupts, 64 * 3 + 10);
+        assert_eq!(timer.read_register(DIV_ADDR), 3);
+        timer.write_register(DIV_ADDR, 0x55);
+        assert_eq!(timer.read_register(DIV_ADDR), 0);
+    }
+    #[test]
+    fn tac_selects_the_rate() {
+        for (tac, m_cycles) in [(0x04, 256), (0x05, 4), (0x06, 16), (0x07, 64)].iter() {
+            let (mut timer, mut interrupts) = timer(*tac);
+            run(&mut timer, &mut interrupts, m_cycles - 1);
+            assert_eq!(timer.read_register(TIMA_ADDR), 0);
+            run(&mut timer, &mut interrupts, 1);
+            assert_eq!(timer.read_register(TIMA_ADDR), 1);
+        }
+    }
+    #[test]
+    fn disabled_timer_does_not_count() {
+        let (mut timer, mut interrupts) = timer(0x01);
+        run(&mut timer, &mut interrupts, 100);
+        assert_eq!(
+            (
+                timer.read_register(TIMA_ADDR),
+                timer.read_register(TAC_ADDR)
+            ),
+            (0, 0xF9)
+        );
+    }
+    #[test]
+    fn tima_reload_is_delayed_one_m_cycle() {
+        let (mut timer, mut interrupts) = timer(0x05);
+        timer.write_register(TMA_ADDR, 0xAB);
+        timer.write_register(TIMA_ADDR, 0xFF);
+        run(&mut timer, &mut interrupts, 4);
+        assert_eq!(
+            (timer.read_register(TIMA_ADDR), interrupts.pending()),
+            (0x00, None)
+        );
+        run(&mut timer, &mut interrupts, 1);
+        assert_eq!(
+            (timer.read_register(TIMA_ADDR), interrupts.pending()),
+            (0xAB, Some(InterruptType::TIMER))
+        );
+    }
+    #[test]
+    fn tima_write_during_overflow_cancels_reload() {
+        let (mut timer, mut interrupts) = timer(0x05);
+        timer.write_register(TMA_ADDR, 0xAB);
+        timer.write_register(TIMA_ADDR, 0xFF);
+        run(&mut timer, &mut interrupts, 4);
+        timer.write_register(TIMA_ADDR, 0x12);
+        run(&mut timer, &mut interrupts, 1);
+        assert_eq!(
+            (timer.read_register(TIMA_ADDR), interrupts.pending()),
+            (0x12, None)
+        );
+    }
+    #[test]
+    fn writes_during_reload_cycle() {
+        let (mut timer, mut interrupts) = timer(0x05);
+        timer.write_register(TIMA_ADDR, 0xFF);
+        run(&mut timer, &mut interrupts, 5);
+        // TIMA writes are dropped, TMA writes land in TIMA as well
+        timer.write_register(TIMA_ADDR, 0x12);
+        assert_eq!(timer.read_register(TIMA_ADDR), 0x00);
+        timer.write_register(TMA_ADDR, 0x34);
+        assert_eq!(timer.read_register(TIMA_ADDR), 0x34);
+        run(&mut timer, &mut interrupts, 1);
+        timer.write_register(TIMA_ADDR, 0x12);
+        assert_eq!(timer.read_register(TIMA_ADDR), 0x12);
+    }
+    #[test]
+    fn div_write_glitch_increments_tima() {
+        let (mut timer, mut interrupts) = timer(0x05);
+        run(&mut timer, &mut interrupts, 2); // Counter bit 3 set
+        timer.write_register(DIV_ADDR, 0x00);
+        assert_eq!(timer.read_register(TIMA_ADDR), 1);
+        run(&mut timer, &mut interrupts, 1); // Counter bit 3 clear
+        timer.write_register(DIV_ADDR, 0x00);
+        assert_eq!(timer.read_register(TIMA_ADDR), 1);
+    }
+    #[test]
+    fn tac_write_glitch_increments_tima() {
+        let (mut timer, mut interrupts) = timer(0x05);
+        run(&mut timer, &mut interrupts, 2);
+        timer.write_register(TAC_ADDR, 0x01);
+        assert_eq!(timer.read_register(TIMA_ADDR), 1);
+        // Switching from a set bit to a clear one is a falling edge as well
+        timer.write_register(TAC_ADDR, 0x05);
+        timer.write_register(TAC_ADDR, 0x04);
+        assert_eq!(timer.read_register(TIMA_ADDR), 2);
+    }
 }
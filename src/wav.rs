@@ -0,0 +1,244 @@
+/* RIFF WAV, 16 bit PCM
+00 : "RIFF", then the size of everything after this field
+08 : "WAVE"
+0C : "fmt " chunk : format 1 (PCM), channels, sample rate, byte rate, block align, 16 bits
+24 : "data" chunk : size, then interleaved little endian samples
+Both sizes are patched in on every flush, so a recording cut short still plays.
+The RIFF size is a u32 as well, which caps the data chunk just short of 4 GiB.
+*/
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::error::GumBoiError;
+use super::sound::RECORDED_CHANNELS;
+
+const HEADER_SIZE: u32 = 44;
+const RIFF_SIZE_OFFSET: u64 = 0x04;
+const DATA_SIZE_OFFSET: u64 = 0x28;
+const BYTES_PER_SAMPLE: u16 = 2;
+const MAX_DATA_SIZE: u32 = u32::MAX - (HEADER_SIZE - 8);
+
+pub struct WavWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    data_size: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, channels: u16, sample_rate: u32) -> Result<WavWriter, GumBoiError> {
+        let mut writer = WavWriter {
+            file: BufWriter::new(
+                File::create(path).map_err(|error| audio_file_error(path, error))?,
+            ),
+            path: path.to_path_buf(),
+            data_size: 0,
+        };
+        let block_align = channels * BYTES_PER_SAMPLE;
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(HEADER_SIZE - 8).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&(BYTES_PER_SAMPLE * 8).to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        writer.write_bytes(&header)?;
+        Ok(writer)
+    }
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), GumBoiError> {
+        let path = &self.path;
+        self.file
+            .write_all(bytes)
+            .map_err(|error| audio_file_error(path, error))
+    }
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<(), GumBoiError> {
+        let mut bytes = Vec::with_capacity(samples.len() * BYTES_PER_SAMPLE as usize);
+        samples
+            .iter()
+            .for_each(|sample| bytes.extend_from_slice(&sample.to_le_bytes()));
+        let data_size = u32::try_from(bytes.len())
+            .ok()
+            .and_then(|size| self.data_size.checked_add(size))
+            .filter(|size| *size <= MAX_DATA_SIZE)
+            .ok_or_else(|| GumBoiError::AudioFile {
+                path: self.path.display().to_string(),
+                message: "WAV files are limited to 4 GiB of samples".to_string(),
+            })?;
+        self.write_bytes(&bytes)?;
+        self.data_size = data_size;
+        Ok(())
+    }
+    fn patch_sizes(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(RIFF_SIZE_OFFSET))?;
+        self.file
+            .write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(DATA_SIZE_OFFSET))?;
+        self.file.write_all(&self.data_size.to_le_bytes())?;
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.flush()
+    }
+    pub fn flush(&mut self) -> Result<(), GumBoiError> {
+        self.patch_sizes()
+            .map_err(|error| audio_file_error(&self.path, error))
+    }
+}
+
+// The mixed stereo output, plus one mono file per channel when they are split out
+pub struct AudioRecorder {
+    mix: WavWriter,
+    channels: Vec<WavWriter>,
+}
+
+impl AudioRecorder {
+    // Channel files sit next to the mix, as song.wav -> song.ch1.wav to song.ch4.wav
+    pub fn create(
+        path: &Path,
+        split_channels: bool,
+        sample_rate: u32,
+    ) -> Result<AudioRecorder, GumBoiError> {
+        let channels = match split_channels {
+            true => (1..=4)
+                .map(|channel| {
+                    let path = path.with_extension(format!("ch{}.wav", channel));
+                    WavWriter::create(&path, 1, sample_rate)
+                })
+                .collect::<Result<Vec<WavWriter>, GumBoiError>>()?,
+            false => Vec::new(),
+        };
+        Ok(AudioRecorder {
+            mix: WavWriter::create(path, 2, sample_rate)?,
+            channels,
+        })
+    }
+    // Takes the APU's recorded frames : left, right, then channels 1-4
+    pub fn write_frames(&mut self, frames: &[i16]) -> Result<(), GumBoiError> {
+        let frames = frames.chunks_exact(RECORDED_CHANNELS);
+        let mut mix = Vec::with_capacity(frames.len() * 2);
+        frames
+            .clone()
+            .for_each(|frame| mix.extend_from_slice(&frame[..2]));
+        self.mix.write_samples(&mix)?;
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            let samples = frames
+                .clone()
+                .map(|frame| frame[2 + index])
+                .collect::<Vec<i16>>();
+            channel.write_samples(&samples)?;
+        }
+        Ok(())
+    }
+    pub fn flush(&mut self) -> Result<(), GumBoiError> {
+        self.mix.flush()?;
+        self.channels.iter_mut().try_for_each(WavWriter::flush)
+    }
+}
+
+fn audio_file_error(path: &Path, error: io::Error) -> GumBoiError {
+    GumBoiError::AudioFile {
+        path: path.display().to_string(),
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod wav_tests {
+    use super::{AudioRecorder, WavWriter, MAX_DATA_SIZE};
+    use crate::error::GumBoiError;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn wav_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("gumboi_{}_{}.wav", name, std::process::id()))
+    }
+
+    fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+        let mut field = [0u8; 4];
+        field.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(field)
+    }
+
+    #[test]
+    fn writes_a_pcm_header_and_patches_sizes() {
+        let path = wav_path("header");
+        let mut writer = WavWriter::create(&path, 2, 48_000).unwrap();
+        writer.write_samples(&[0x0102, -2, 3, 4]).unwrap();
+        writer.flush().unwrap();
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(
+            (
+                &bytes[0x00..0x04],
+                read_u32(&bytes, 0x04),
+                &bytes[0x08..0x10],
+                read_u32(&bytes, 0x18),
+                read_u32(&bytes, 0x1C),
+                read_u32(&bytes, 0x28),
+                &bytes[0x2C..0x30]
+            ),
+            (
+                &b"RIFF"[..],
+                44,
+                &b"WAVEfmt "[..],
+                48_000,
+                192_000,
+                8,
+                &[0x02, 0x01, 0xFE, 0xFF][..]
+            )
+        );
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn splits_channels_into_mono_files() {
+        let path = wav_path("split");
+        let mut recorder = AudioRecorder::create(&path, true, 1_000).unwrap();
+        recorder
+            .write_frames(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12])
+            .unwrap();
+        recorder.flush().unwrap();
+        let mix = fs::read(&path).unwrap();
+        let channel_4 = fs::read(path.with_extension("ch4.wav")).unwrap();
+        assert_eq!(
+            (mix.len(), &mix[0x2C..], &channel_4[0x2C..], channel_4[0x16]),
+            (52, &[1, 0, 2, 0, 7, 0, 8, 0][..], &[6, 0, 12, 0][..], 1)
+        );
+        fs::remove_file(&path).unwrap();
+        (1..=4).for_each(|channel| {
+            fs::remove_file(path.with_extension(format!("ch{}.wav", channel))).unwrap()
+        });
+    }
+    #[test]
+    fn refuses_to_grow_past_the_riff_limit() {
+        let path = wav_path("limit");
+        let mut writer = WavWriter::create(&path, 1, 48_000).unwrap();
+        writer.data_size = MAX_DATA_SIZE - 2;
+        writer.write_samples(&[1]).unwrap();
+        assert!(matches!(
+            writer.write_samples(&[2]),
+            Err(GumBoiError::AudioFile { .. })
+        ));
+        writer.flush().unwrap();
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(
+            (bytes.len(), read_u32(&bytes, 0x04), read_u32(&bytes, 0x28)),
+            (46, u32::MAX, MAX_DATA_SIZE)
+        );
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn reports_the_failing_path() {
+        let path = std::env::temp_dir()
+            .join("gumboi_missing_dir")
+            .join("out.wav");
+        assert!(matches!(
+            AudioRecorder::create(&path, false, 48_000),
+            Err(GumBoiError::AudioFile { .. })
+        ));
+    }
+}